@@ -0,0 +1,508 @@
+//! Persistent cache of per-segment analysis results.
+//!
+//! When `DbOptions::cache_dir` is set, the parse, scope and verify passes save
+//! their results for each segment to files in that directory, and a later
+//! process loading the same database reuses them instead of recomputing.  This
+//! complements the in-memory incremental machinery, which only helps while a
+//! process stays alive.
+//!
+//! Entries are keyed by SHA-256 hashes of their inputs rather than by file
+//! names or times.  A weaker hash would let a crafted database collide with a
+//! cached one and be reported as verified, so every key and checksum uses a
+//! cryptographic hash:
+//!
+//! * Parse results are keyed by the text of a source slice, like the second
+//!   cache of `segment_set`.
+//! * Scope and verify results depend on the declarations of every earlier
+//!   segment, so they are keyed by the text of the segment together with the
+//!   keys of all the segments before it (see `SegmentKeys`).  This is coarser
+//!   than the usage tracking of `NameUsage` and `ScopeUsage`, which cannot
+//!   outlive the atoms and generations of a single process, but it means that
+//!   an edit only invalidates the results for the segments after it.  Like
+//!   `NameUsage`, it does not track global `$d` statements finely, so changing
+//!   one invalidates every segment.
+//!
+//! Only results without diagnostics (other than a few simple warnings) are
+//! saved; segments with errors are expected to change soon, and are cheap to
+//! recompute relative to a whole database.  Each file starts with a header
+//! recording the format version, the crate version, the key and a checksum of
+//! the contents, and any mismatch or decoding failure is treated as a miss.
+//! Writing is best-effort, and I/O errors are ignored.
+//!
+//! Entries are never removed, since a directory may be shared by several
+//! databases and there is no way to tell which keys are still wanted.  Every
+//! edit adds entries for the segments it invalidates, so the directory grows
+//! over time; it can be deleted at any point to reclaim the space.
+
+use database::DbOptions;
+use parser::SegmentId;
+use parser::SegmentRef;
+use parser::Span;
+use parser::StatementAddress;
+use parser::Token;
+use segment_set::SegmentSet;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use util::new_map;
+use util::HashMap;
+
+/// Version of the encoding of cache entries; change this whenever the layout of
+/// any cached structure changes.
+const FORMAT_VERSION: u32 = 2;
+
+/// Identifies cache files.
+const MAGIC: &[u8] = b"smmcache";
+
+/// A SHA-256 hash, used for keys and checksums.
+pub type Digest = [u8; 32];
+
+/// Round constants of SHA-256.
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Incremental SHA-256 hasher.
+///
+/// No hashing crate is a dependency, and the cache hashes little enough text
+/// that a straightforward implementation is fast enough.
+struct Sha256 {
+    state: [u32; 8],
+    pending: Vec<u8>,
+    length: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Sha256 {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            pending: Vec::with_capacity(64),
+            length: 0,
+        }
+    }
+}
+
+impl Sha256 {
+    /// Adds some bytes to the message.
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.length = self.length.wrapping_add(bytes.len() as u64);
+        if !self.pending.is_empty() {
+            let take = (64 - self.pending.len()).min(bytes.len());
+            self.pending.extend_from_slice(&bytes[..take]);
+            bytes = &bytes[take..];
+            if self.pending.len() < 64 {
+                return;
+            }
+            let block = std::mem::take(&mut self.pending);
+            self.compress(&block);
+            self.pending = block;
+            self.pending.clear();
+        }
+        let mut blocks = bytes.chunks_exact(64);
+        for block in &mut blocks {
+            self.compress(block);
+        }
+        self.pending.extend_from_slice(blocks.remainder());
+    }
+
+    /// Adds a byte string preceded by its length, so that consecutive strings
+    /// cannot be confused with a different split of the same bytes.
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+
+    /// Pads the message and returns its hash.
+    fn finish(mut self) -> Digest {
+        let bits = self.length.wrapping_mul(8);
+        self.write(&[0x80]);
+        while self.pending.len() != 56 {
+            self.write(&[0]);
+        }
+        self.write(&bits.to_be_bytes());
+        let mut out = [0; 32];
+        for (chunk, word) in out.chunks_exact_mut(4).zip(&self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0u32; 64];
+        for (word, chunk) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, value) in self.state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(*value);
+        }
+    }
+}
+
+/// Calculates the SHA-256 hash of some bytes.
+pub fn content_hash(bytes: &[u8]) -> Digest {
+    let mut hasher = Sha256::default();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// Formats a hash for use in a file name.
+fn hex(digest: &Digest) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Returns the source text of a segment, which is the concatenation of the
+/// full spans of its statements.
+fn segment_text<'a>(sref: SegmentRef<'a>) -> &'a [u8] {
+    let mut stmts = sref.into_iter();
+    match stmts.next() {
+        Some(first) => {
+            let start = first.span_full().start;
+            let end = stmts.last().unwrap_or(first).span_full().end;
+            &sref.segment.buffer[start as usize..end as usize]
+        }
+        None => &[],
+    }
+}
+
+/// Serializes values for a cache entry.
+///
+/// Integers are stored in little-endian order, and sequences are preceded by
+/// their length.
+#[derive(Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    /// Writes a 32-bit integer.
+    pub fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a 64-bit integer.
+    pub fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a size or index.
+    pub fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    /// Writes a signed 32-bit integer, such as a `StatementIndex`.
+    pub fn i32(&mut self, value: i32) {
+        self.u32(value as u32);
+    }
+
+    /// Writes a flag.
+    pub fn bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    /// Writes a byte string.
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.usize(value.len());
+        self.buf.extend_from_slice(value);
+    }
+
+    /// Writes a hash.
+    pub fn digest(&mut self, value: &Digest) {
+        self.buf.extend_from_slice(value);
+    }
+
+    /// Writes a span.
+    pub fn span(&mut self, value: Span) {
+        self.u32(value.start);
+        self.u32(value.end);
+    }
+
+    /// Writes a sequence of items with a function for each item.
+    pub fn seq<T, F: FnMut(&mut Encoder, &T)>(&mut self, items: &[T], mut fun: F) {
+        self.usize(items.len());
+        for item in items {
+            fun(self, item);
+        }
+    }
+}
+
+/// Reads values written by an `Encoder`, returning `None` if the data runs out.
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    /// Starts reading the contents of a cache entry.
+    pub fn new(buf: &'a [u8]) -> Decoder<'a> {
+        Decoder { buf }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.buf.len() {
+            return None;
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Some(head)
+    }
+
+    /// Reads a 32-bit integer.
+    pub fn u32(&mut self) -> Option<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Some(u32::from_le_bytes(bytes))
+    }
+
+    /// Reads a 64-bit integer.
+    pub fn u64(&mut self) -> Option<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Some(u64::from_le_bytes(bytes))
+    }
+
+    /// Reads a size or index.
+    pub fn usize(&mut self) -> Option<usize> {
+        let value = self.u64()?;
+        if value > usize::MAX as u64 {
+            return None;
+        }
+        Some(value as usize)
+    }
+
+    /// Reads a signed 32-bit integer.
+    pub fn i32(&mut self) -> Option<i32> {
+        self.u32().map(|value| value as i32)
+    }
+
+    /// Reads a flag.
+    pub fn bool(&mut self) -> Option<bool> {
+        match self.take(1)?[0] {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Reads a byte string.
+    pub fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.usize()?;
+        self.take(len)
+    }
+
+    /// Reads a byte string as a token.
+    pub fn token(&mut self) -> Option<Token> {
+        self.bytes().map(|bytes| bytes.into())
+    }
+
+    /// Reads a hash.
+    pub fn digest(&mut self) -> Option<Digest> {
+        let mut digest = [0; 32];
+        digest.copy_from_slice(self.take(32)?);
+        Some(digest)
+    }
+
+    /// Reads a span.
+    pub fn span(&mut self) -> Option<Span> {
+        let start = self.u32()?;
+        let end = self.u32()?;
+        Some(Span { start, end })
+    }
+
+    /// Reads a sequence of items with a function for each item.
+    pub fn seq<T, F: FnMut(&mut Decoder<'a>) -> Option<T>>(
+        &mut self,
+        mut fun: F,
+    ) -> Option<Vec<T>> {
+        let len = self.usize()?;
+        // every item takes at least a byte, so this bounds the allocation
+        let mut out = Vec::with_capacity(len.min(self.buf.len()));
+        for _ in 0..len {
+            out.push(fun(self)?);
+        }
+        Some(out)
+    }
+
+    /// Checks that all of the data has been read.
+    pub fn finish(self) -> Option<()> {
+        if self.buf.is_empty() {
+            Some(())
+        } else {
+            None
+        }
+    }
+}
+
+/// Keys for the results of later passes for each segment of a database.
+///
+/// The key of a segment covers its own text, the keys of all earlier segments,
+/// and every global `$d` statement of the database.  Since the earlier segments
+/// of a hit are then known to be the same as when the entry was written,
+/// statement addresses in them can be saved using the position of their
+/// segment in the database.  Only the text of the segment itself is hashed, not
+/// the whole slice it was parsed from, so an edit leaves the keys of the
+/// segments before it unchanged.
+pub struct SegmentKeys {
+    order: Vec<SegmentId>,
+    keys: HashMap<SegmentId, (usize, Digest)>,
+}
+
+impl SegmentKeys {
+    /// Calculates the keys for the segments of a database.
+    pub fn new(sset: &SegmentSet) -> SegmentKeys {
+        let mut order = Vec::new();
+        let mut keys = new_map();
+        let mut hasher = Sha256::default();
+        for sref in sset.segments() {
+            for dv in &sref.global_dvs {
+                hasher.write(&(dv.vars.len() as u64).to_le_bytes());
+                for var in &dv.vars {
+                    hasher.write_bytes(var);
+                }
+            }
+        }
+        let mut chain = hasher.finish();
+        for sref in sset.segments() {
+            let mut hasher = Sha256::default();
+            hasher.write(&chain);
+            hasher.write_bytes(segment_text(sref));
+            chain = hasher.finish();
+            keys.insert(sref.id, (order.len(), chain));
+            order.push(sref.id);
+        }
+        SegmentKeys { order, keys }
+    }
+
+    /// Returns the key of a segment.
+    pub fn key(&self, id: SegmentId) -> Digest {
+        self.keys[&id].1
+    }
+
+    /// Writes the address of a statement in the segment or an earlier one.
+    pub fn encode_address(&self, enc: &mut Encoder, addr: StatementAddress) {
+        enc.usize(self.keys[&addr.segment_id].0);
+        enc.i32(addr.index);
+    }
+
+    /// Reads an address written by `encode_address`.
+    pub fn decode_address(&self, dec: &mut Decoder) -> Option<StatementAddress> {
+        let segment_id = *self.order.get(dec.usize()?)?;
+        Some(StatementAddress::new(segment_id, dec.i32()?))
+    }
+}
+
+/// A directory of cache entries.
+#[derive(Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    /// Opens the cache directory of a database, if it has one.
+    pub fn new(options: &DbOptions) -> Option<DiskCache> {
+        options
+            .cache_dir
+            .as_ref()
+            .map(|dir| DiskCache { dir: dir.into() })
+    }
+
+    fn path(&self, kind: &str, key: &Digest) -> PathBuf {
+        self.dir.join(format!("{}-{}.bin", kind, hex(key)))
+    }
+
+    fn header(kind: &str, key: &Digest, payload: &[u8]) -> Encoder {
+        let mut enc = Encoder::default();
+        enc.bytes(MAGIC);
+        enc.u32(FORMAT_VERSION);
+        enc.bytes(env!("CARGO_PKG_VERSION").as_bytes());
+        enc.bytes(kind.as_bytes());
+        enc.digest(key);
+        enc.digest(&content_hash(payload));
+        enc
+    }
+
+    /// Reads the contents of an entry, if there is a valid one.
+    fn load(&self, kind: &str, key: &Digest) -> Option<Vec<u8>> {
+        let mut data = fs::read(self.path(kind, key)).ok()?;
+        let mut dec = Decoder::new(&data);
+        // compare the header field by field, since the checksum is unknown
+        let header_ok = dec.bytes()? == MAGIC
+            && dec.u32()? == FORMAT_VERSION
+            && dec.bytes()? == env!("CARGO_PKG_VERSION").as_bytes()
+            && dec.bytes()? == kind.as_bytes()
+            && dec.digest()? == *key;
+        let checksum = dec.digest()?;
+        let payload = data.len() - dec.buf.len();
+        if !header_ok || content_hash(&data[payload..]) != checksum {
+            return None;
+        }
+        Some(data.split_off(payload))
+    }
+
+    /// Decodes an entry with a function, which must read all of its contents.
+    pub fn load_with<T, F>(&self, kind: &str, key: Digest, fun: F) -> Option<T>
+    where
+        F: FnOnce(&mut Decoder) -> Option<T>,
+    {
+        let data = self.load(kind, &key)?;
+        let mut dec = Decoder::new(&data);
+        let value = fun(&mut dec)?;
+        dec.finish()?;
+        Some(value)
+    }
+
+    /// Saves an entry, replacing any existing one.
+    pub fn store(&self, kind: &str, key: Digest, payload: Encoder) {
+        let mut data = DiskCache::header(kind, &key, &payload.buf).buf;
+        data.extend_from_slice(&payload.buf);
+        // write under a temporary name, so that readers never see a partial
+        // entry
+        let path = self.path(kind, &key);
+        let temp = self
+            .dir
+            .join(format!("{}-{}.{}.tmp", kind, hex(&key), process::id()));
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&temp, &data))
+            .and_then(|_| fs::rename(&temp, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+    }
+}
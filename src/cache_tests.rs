@@ -0,0 +1,173 @@
+use cache::content_hash;
+use cache::DiskCache;
+use cache::Encoder;
+use cache::SegmentKeys;
+use database::Database;
+use database::DbOptions;
+use parser;
+use parser::Span;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use test_util::mkdb;
+
+const DB: &str = "$c wff |- ( -> ) $.
+    $v ph ps $.
+    wph $f wff ph $.
+    wps $f wff ps $.
+    wi $a wff ( ph -> ps ) $.
+    ${
+      $d ph ps $.
+      ax-1 $a |- ( ph -> ( ps -> ph ) ) $.
+      th1 $p |- ( ph -> ( ph -> ph ) ) $= wph wph ax-1 $.
+    $}
+    th2 $p |- ( ph -> ph ) $= wph wph wi $.";
+
+fn options(dir: &Path) -> DbOptions {
+    DbOptions {
+        cache_dir: Some(dir.to_str().unwrap().to_owned()),
+        ..DbOptions::default()
+    }
+}
+
+fn open(dir: &Path) -> Database {
+    mkdb(DB, options(dir))
+}
+
+fn results(db: &mut Database) -> (String, Vec<String>) {
+    let frame = format!("{:?}", db.scope_result().get(b"th1"));
+    let mut diags = db.verify_result().diagnostics();
+    diags.sort_by_key(|&(addr, _)| addr.index);
+    let diags = diags
+        .into_iter()
+        .map(|(_, diag)| format!("{:?}", diag))
+        .collect();
+    (frame, diags)
+}
+
+fn kinds(dir: &Path) -> Vec<String> {
+    let mut kinds = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let name = entry.unwrap().file_name().into_string().unwrap();
+            name.split('-').next().unwrap().to_owned()
+        })
+        .collect::<Vec<_>>();
+    kinds.sort();
+    kinds
+}
+
+#[test]
+fn test_disk_cache() {
+    let dir = env::temp_dir().join(format!("smetamath-cache-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    // th1 has an unneeded $d and th2 an invalid proof, so only the parse and
+    // scope results are saved
+    let mut db = open(&dir);
+    let (frame, diags) = results(&mut db);
+    assert_eq!(diags.len(), 2);
+    assert_eq!(kinds(&dir), vec!["parse", "scope"]);
+
+    // a valid entry is used in place of the verifier
+    let sset = db.parse_result().clone();
+    let addr = db.statement("th2").unwrap().address();
+    let key = SegmentKeys::new(&sset).key(addr.segment_id);
+    let mut empty = Encoder::default();
+    empty.usize(0);
+    DiskCache::new(&options(&dir))
+        .unwrap()
+        .store("verify", key, empty);
+    assert_eq!(results(&mut open(&dir)), (frame.clone(), vec![]));
+
+    // damaged entries are ignored
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let mut data = fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        fs::write(&path, data).unwrap();
+    }
+    assert_eq!(results(&mut open(&dir)), (frame, diags));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[test]
+fn test_content_hash() {
+    assert_eq!(
+        hex(&content_hash(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hex(&content_hash(b"abc")),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        hex(&content_hash(
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+        )),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+    assert_eq!(
+        hex(&content_hash(&[b'a'; 1000])),
+        "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+    );
+}
+
+fn segment_keys(tail: &str) -> Vec<[u8; 32]> {
+    let mut db = Database::new(DbOptions::default());
+    db.parse(
+        "test.mm".to_owned(),
+        vec![
+            (
+                "test.mm".to_owned(),
+                format!("$c wff $.\n$[ inc.mm $]\n{}", tail).into_bytes(),
+            ),
+            ("inc.mm".to_owned(), b"$v ph $.\n".to_vec()),
+        ],
+    );
+    let sset = db.parse_result().clone();
+    let keys = SegmentKeys::new(&sset);
+    sset.segments()
+        .into_iter()
+        .map(|sref| keys.key(sref.id))
+        .collect()
+}
+
+#[test]
+fn test_segment_keys() {
+    // an edit after the include keeps the keys of the segments before it, even
+    // the one parsed from the same file
+    let old = segment_keys("wph $f wff ph $.\n");
+    let new = segment_keys("wph $f wff ph $.\n$c |- $.\n");
+    assert_eq!(old.len(), 3);
+    assert_eq!(old[..2], new[..2]);
+    assert_ne!(old[2], new[2]);
+}
+
+#[test]
+fn test_damaged_spans() {
+    let dir = env::temp_dir().join(format!("smetamath-cache-spans-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let text = "$( $j syntax 'wff'; $)\n$c wff $.";
+
+    // an entry with a command argument outside the text is ignored
+    let buffer = Arc::new(text.as_bytes().to_owned());
+    let mut segments = parser::parse_segments(&buffer);
+    Arc::get_mut(&mut segments[0]).unwrap().commands[0].1.args[0].span = Span::new(0, 1000);
+    DiskCache::new(&options(&dir)).unwrap().store(
+        "parse",
+        content_hash(&buffer),
+        parser::encode_segments(&segments).unwrap(),
+    );
+    let mut db = mkdb(text, options(&dir));
+    let commands = db.commands_result().clone();
+    let sset = db.parse_result().clone();
+    assert_eq!(commands.get(b"syntax")[0].args(&sset), vec![b"wff"]);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,83 @@
+//! Collection of the commands in `$j` comments.
+//!
+//! A `$j` comment holds commands for proof tools, such as
+//! `syntax 'wff';` or `definition 'dfbi1' for 'wb';`.  The parser splits each
+//! comment into commands as it reads a segment and reports malformed ones as
+//! parse diagnostics; this pass gathers the commands of all segments, in
+//! database order, and indexes them by keyword.
+
+use parser::Command;
+use parser::StatementAddress;
+use parser::Token;
+use parser::TokenPtr;
+use segment_set::SegmentSet;
+use util::new_map;
+use util::HashMap;
+
+/// A command found in a `$j` comment.
+#[derive(Clone, Debug)]
+pub struct CommandEntry {
+    /// Address of the comment statement containing the command.
+    pub address: StatementAddress,
+    /// The command, with spans into the buffer of the statement's segment.
+    pub command: Command,
+}
+
+impl CommandEntry {
+    /// Returns the text of the keyword which starts the command.
+    pub fn keyword<'a>(&self, sset: &'a SegmentSet) -> TokenPtr<'a> {
+        let seg = sset.segment(self.address.segment_id).segment;
+        self.command.keyword.as_ref(&seg.buffer)
+    }
+
+    /// Returns the text of each argument of the command; quoted strings are
+    /// given without their quotes.
+    pub fn args<'a>(&self, sset: &'a SegmentSet) -> Vec<TokenPtr<'a>> {
+        let seg = sset.segment(self.address.segment_id).segment;
+        self.command
+            .args
+            .iter()
+            .map(|arg| arg.span.as_ref(&seg.buffer))
+            .collect()
+    }
+}
+
+/// Analysis pass result for `$j` commands.
+#[derive(Default, Clone)]
+pub struct CommandResult {
+    commands: HashMap<Token, Vec<CommandEntry>>,
+}
+
+impl CommandResult {
+    /// Returns the commands with a given keyword, in database order.
+    pub fn get(&self, keyword: &[u8]) -> &[CommandEntry] {
+        self.commands
+            .get(keyword)
+            .map_or(&[], |entries| &entries[..])
+    }
+
+    /// Returns all keywords which were used, in sorted order.
+    pub fn keywords(&self) -> Vec<&[u8]> {
+        let mut out = self.commands.keys().map(|k| &k[..]).collect::<Vec<_>>();
+        out.sort();
+        out
+    }
+}
+
+/// Gathers the `$j` commands of every segment.
+pub fn collect_commands(sset: &SegmentSet) -> CommandResult {
+    let mut commands: HashMap<Token, Vec<CommandEntry>> = new_map();
+    for sref in sset.segments() {
+        for &(index, ref command) in &sref.commands {
+            let keyword = command.keyword.as_ref(&sref.buffer);
+            commands
+                .entry(keyword.into())
+                .or_default()
+                .push(CommandEntry {
+                    address: StatementAddress::new(sref.id, index),
+                    command: command.clone(),
+                });
+        }
+    }
+    CommandResult { commands }
+}
@@ -69,14 +69,15 @@
 //!
 //! A pass will be calculated when its result is needed.  Operation is currently
 //! lazy at a pass level, so it is not possible to verify only one segment,
-//! although that _might_ change.  The results of a pass are stored in a data
-//! structure indexed by some means, each element of which has an associated
-//! version number.  When another pass needs to use the result of the first
-//! pass, it tracks which elements of the first pass's result are used for each
-//! segment, and their associated version numbers; this means that if a small
-//! database change is made and the second pass is rerun, it can quickly abort
-//! on most segments by checking if the dependencies _of that segment_ have
-//! changed, using only the version numbers.
+//! although that _might_ change; individual statements can be checked outside
+//! of the pass with `Database::verify_statements`.  The results of a pass are
+//! stored in a data structure indexed by some means, each element of which has
+//! an associated version number.  When another pass needs to use the result of
+//! the first pass, it tracks which elements of the first pass's result are used
+//! for each segment, and their associated version numbers; this means that if a
+//! small database change is made and the second pass is rerun, it can quickly
+//! abort on most segments by checking if the dependencies _of that segment_
+//! have changed, using only the version numbers.
 //!
 //! This is not yet a rigidly systematized thing; for an example, nameck
 //! generates its result as a `nameck::Nameset`, and implements
@@ -85,6 +86,9 @@
 //! which can be used to access the nameset while simultaneously building a
 //! usage object that can be used for future checking.
 //!
+//! Usages only make sense within a single process.  Results can also be saved
+//! across processes with the `cache` module, which uses content hashes instead.
+//!
 //! ## Parallelism and promises
 //!
 //! The current parallel processing implementation is fairly simplistic.  If you
@@ -96,28 +100,71 @@
 //!
 //! To improve packing efficiency, jobs are dispatched in descending order of
 //! estimated runtime.  This requires an additional argument when queueing.
+//!
+//! Waiting for a promise blocks the thread without running other jobs, so a job
+//! must not wait for jobs which it queues itself.  A pass which divides a
+//! segment's work between several jobs, as the verifier does for large
+//! segments, queues the pieces from the thread which started the pass.
 
+use commands;
+use commands::CommandResult;
+use defck;
+use defck::DefinitionResult;
+use deps::Dependencies;
+use deps::DependencyCache;
 use diag;
+use diag::Diagnostic;
 use diag::DiagnosticClass;
 use diag::Notation;
+use discouraged;
+use discouraged::DiscouragedResult;
+use dupck;
+use dupck::DuplicateResult;
 use export;
+use forbidck;
+use forbidck::ForbiddenResult;
+use grammar;
+use grammar::AmbiguityResult;
+use grammar::GrammarResult;
+use html;
+use html::HtmlState;
+use import_mmp;
+use latex;
+use lint;
+use lint::LintResult;
+use markupck;
+use markupck::MarkupResult;
 use nameck::Nameset;
+use parser::Span;
 use parser::StatementRef;
+use parser::StatementType;
+use proof::ProofStyle;
+use proof::ProofTreeArray;
+use rewrite;
 use scopeck;
 use scopeck::ScopeResult;
 use segment_set::SegmentSet;
+use segment_set::SourceInfo;
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
 use std::fmt;
 use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
 use std::panic;
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::Condvar;
 use std::sync::Mutex;
 use std::thread;
 use std::time::Instant;
+use typesetting;
+use typesetting::TypesettingResult;
 use verify;
 use verify::VerifyResult;
+use xref;
+use xref::XrefResult;
 
 /// Structure for options that affect database processing, and must be constant
 /// for the lifetime of the database container.
@@ -145,6 +192,17 @@ pub struct DbOptions {
     pub incremental: bool,
     /// Number of jobs to run in parallel at any given time.
     pub jobs: usize,
+    /// Label prefix which identifies the definitions checked by the
+    /// definition pass; `df-` if empty.
+    pub definition_prefix: String,
+    /// Labels of axioms which proofs must not depend on, in addition to those
+    /// forbidden by the database itself.
+    pub forbidden_axioms: Vec<String>,
+    /// Names of the lints run by the lint pass; every lint if empty.
+    pub lints: Vec<String>,
+    /// Directory in which to save parse, scope and verify results for later
+    /// runs, as described in the `cache` module; no caching if `None`.
+    pub cache_dir: Option<String>,
 }
 
 /// Wraps a heap-allocated closure with a difficulty score which can be used for
@@ -359,6 +417,28 @@ pub struct Database {
     scopes: Option<Arc<ScopeResult>>,
     prev_verify: Option<Arc<VerifyResult>>,
     verify: Option<Arc<VerifyResult>>,
+    prev_grammar: Option<Arc<GrammarResult>>,
+    grammar: Option<Arc<GrammarResult>>,
+    prev_ambiguity: Option<Arc<AmbiguityResult>>,
+    ambiguity: Option<Arc<AmbiguityResult>>,
+    commands: Option<Arc<CommandResult>>,
+    prev_typesetting: Option<Arc<TypesettingResult>>,
+    typesetting: Option<Arc<TypesettingResult>>,
+    prev_definitions: Option<Arc<DefinitionResult>>,
+    definitions: Option<Arc<DefinitionResult>>,
+    prev_xref: Option<Arc<XrefResult>>,
+    xref: Option<Arc<XrefResult>>,
+    /// Memoised results of `dependencies`; unlike the passes, this is filled
+    /// in on demand.
+    dependencies: Option<DependencyCache>,
+    forbidden: Option<Arc<ForbiddenResult>>,
+    discouraged: Option<Arc<DiscouragedResult>>,
+    markup: Option<Arc<MarkupResult>>,
+    lint: Option<Arc<LintResult>>,
+    prev_duplicates: Option<Arc<DuplicateResult>>,
+    duplicates: Option<Arc<DuplicateResult>>,
+    /// Pages written by `html`, so that unchanged pages need not be rewritten.
+    html: HtmlState,
 }
 
 fn time<R, F: FnOnce() -> R>(opts: &DbOptions, name: &str, f: F) -> R {
@@ -374,6 +454,24 @@ fn time<R, F: FnOnce() -> R>(opts: &DbOptions, name: &str, f: F) -> R {
 impl Drop for Database {
     fn drop(&mut self) {
         time(&self.options.clone(), "free", move || {
+            self.prev_duplicates = None;
+            self.duplicates = None;
+            self.lint = None;
+            self.markup = None;
+            self.discouraged = None;
+            self.forbidden = None;
+            self.dependencies = None;
+            self.prev_xref = None;
+            self.xref = None;
+            self.prev_definitions = None;
+            self.definitions = None;
+            self.commands = None;
+            self.prev_typesetting = None;
+            self.typesetting = None;
+            self.prev_ambiguity = None;
+            self.ambiguity = None;
+            self.prev_grammar = None;
+            self.grammar = None;
             self.prev_verify = None;
             self.verify = None;
             self.prev_scopes = None;
@@ -402,6 +500,25 @@ impl Database {
             prev_nameset: None,
             prev_scopes: None,
             prev_verify: None,
+            grammar: None,
+            prev_grammar: None,
+            ambiguity: None,
+            prev_ambiguity: None,
+            definitions: None,
+            prev_definitions: None,
+            commands: None,
+            typesetting: None,
+            prev_typesetting: None,
+            xref: None,
+            prev_xref: None,
+            dependencies: None,
+            forbidden: None,
+            discouraged: None,
+            markup: None,
+            lint: None,
+            duplicates: None,
+            prev_duplicates: None,
+            html: HtmlState::default(),
         }
     }
 
@@ -440,6 +557,18 @@ impl Database {
             self.nameset = None;
             self.scopes = None;
             self.verify = None;
+            self.grammar = None;
+            self.ambiguity = None;
+            self.definitions = None;
+            self.commands = None;
+            self.typesetting = None;
+            self.xref = None;
+            self.dependencies = None;
+            self.forbidden = None;
+            self.discouraged = None;
+            self.markup = None;
+            self.lint = None;
+            self.duplicates = None;
         });
     }
 
@@ -522,6 +651,318 @@ impl Database {
         self.verify.as_ref().unwrap()
     }
 
+    /// Verifies the proofs of some `$p` statements, given by label, and
+    /// returns their diagnostics.
+    ///
+    /// Unlike `verify_result`, this does not check the rest of the database,
+    /// so it is suitable for rechecking a single theorem after an edit.
+    /// Labels which do not name a `$p` statement are ignored.
+    pub fn verify_statements(&mut self, labels: &[&str]) -> Vec<Notation> {
+        time(&self.options.clone(), "verify_statements", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let mut diags = Vec::new();
+            for label in labels {
+                let stmt = match name.lookup_label(label.as_bytes()) {
+                    Some(lookup) => parse.statement(lookup.address),
+                    None => continue,
+                };
+                if stmt.statement_type() != StatementType::Provable {
+                    continue;
+                }
+                if let Some(diag) = verify::verify_statement(&parse, &name, &scope, stmt) {
+                    diags.push((stmt.address(), diag));
+                }
+            }
+            diag::to_annotations(&parse, diags)
+        })
+    }
+
+    /// Returns the commands found in `$j` comments, indexed by keyword.
+    ///
+    /// Malformed commands are reported as parse diagnostics.
+    pub fn commands_result(&mut self) -> &Arc<CommandResult> {
+        if self.commands.is_none() {
+            time(&self.options.clone(), "commands", || {
+                let parse = self.parse_result().clone();
+                self.commands = Some(Arc::new(commands::collect_commands(&parse)));
+            });
+        }
+        self.commands.as_ref().unwrap()
+    }
+
+    /// Calculates and returns the typesetting table built from the `$t`
+    /// comments of the database.
+    pub fn typesetting_result(&mut self) -> &Arc<TypesettingResult> {
+        if self.typesetting.is_none() {
+            self.name_result();
+            time(&self.options.clone(), "typesetting", || {
+                if self.prev_typesetting.is_none() {
+                    self.prev_typesetting = Some(Arc::new(TypesettingResult::default()));
+                }
+
+                let parse = self.parse_result().clone();
+                let name = self.name_result().clone();
+                {
+                    let ts = Arc::make_mut(self.prev_typesetting.as_mut().unwrap());
+                    typesetting::typesetting(ts, &parse, &name);
+                }
+                self.typesetting = self.prev_typesetting.clone();
+            });
+        }
+        self.typesetting.as_ref().unwrap()
+    }
+
+    /// Calculates and returns the grammar of the database, with the results of
+    /// parsing its logical statements.
+    ///
+    /// The grammar is built from the syntax axioms, and every `$e`, `$p`, and
+    /// logical `$a` statement is parsed into a syntax tree; statements which
+    /// cannot be parsed unambiguously are reported as diagnostics.
+    pub fn grammar_result(&mut self) -> &Arc<GrammarResult> {
+        if self.grammar.is_none() {
+            self.name_result();
+            self.scope_result();
+            self.commands_result();
+            time(&self.options.clone(), "grammar", || {
+                if self.prev_grammar.is_none() {
+                    self.prev_grammar = Some(Arc::new(GrammarResult::default()));
+                }
+
+                let parse = self.parse_result().clone();
+                let scope = self.scope_result().clone();
+                let name = self.name_result().clone();
+                let commands = self.commands_result().clone();
+                {
+                    let gram = Arc::make_mut(self.prev_grammar.as_mut().unwrap());
+                    grammar::grammar_check(gram, &parse, &name, &scope, &commands);
+                }
+                self.grammar = self.prev_grammar.clone();
+            });
+        }
+        self.grammar.as_ref().unwrap()
+    }
+
+    /// Checks the syntax axioms of the database for ambiguous overlaps, and
+    /// returns the result.
+    ///
+    /// Each offending pair of axioms is reported as a diagnostic on the later
+    /// axiom.
+    pub fn ambiguity_result(&mut self) -> &Arc<AmbiguityResult> {
+        if self.ambiguity.is_none() {
+            self.grammar_result();
+            time(&self.options.clone(), "ambiguity", || {
+                if self.prev_ambiguity.is_none() {
+                    self.prev_ambiguity = Some(Arc::new(AmbiguityResult::default()));
+                }
+
+                let parse = self.parse_result().clone();
+                let grammar = self.grammar_result().grammar().clone();
+                {
+                    let amb = Arc::make_mut(self.prev_ambiguity.as_mut().unwrap());
+                    grammar::ambiguity_check(amb, &parse, &grammar);
+                }
+                self.ambiguity = self.prev_ambiguity.clone();
+            });
+        }
+        self.ambiguity.as_ref().unwrap()
+    }
+
+    /// Checks the definitions of the database for soundness, and returns the
+    /// result.
+    ///
+    /// Definitions are identified by the `definition_prefix` option.
+    pub fn definition_result(&mut self) -> &Arc<DefinitionResult> {
+        if self.definitions.is_none() {
+            self.grammar_result();
+            time(&self.options.clone(), "definitions", || {
+                if self.prev_definitions.is_none() {
+                    self.prev_definitions = Some(Arc::new(DefinitionResult::default()));
+                }
+
+                let parse = self.parse_result().clone();
+                let scope = self.scope_result().clone();
+                let name = self.name_result().clone();
+                let grammar = self.grammar_result().grammar().clone();
+                let commands = self.commands_result().clone();
+                let prefix = self.options.definition_prefix.clone();
+                {
+                    let defs = Arc::make_mut(self.prev_definitions.as_mut().unwrap());
+                    defck::definition_check(
+                        defs, &parse, &name, &scope, &grammar, &commands, &prefix,
+                    );
+                }
+                self.definitions = self.prev_definitions.clone();
+            });
+        }
+        self.definitions.as_ref().unwrap()
+    }
+
+    /// Calculates and returns the index from labels to the theorems whose
+    /// proofs cite them.
+    pub fn xref_result(&mut self) -> &Arc<XrefResult> {
+        if self.xref.is_none() {
+            time(&self.options.clone(), "xref", || {
+                if self.prev_xref.is_none() {
+                    self.prev_xref = Some(Arc::new(XrefResult::default()));
+                }
+
+                let parse = self.parse_result().clone();
+                let name = self.name_result().clone();
+                let scope = self.scope_result().clone();
+                {
+                    let xr = Arc::make_mut(self.prev_xref.as_mut().unwrap());
+                    xref::cross_reference(xr, &parse, &name, &scope);
+                }
+                self.xref = self.prev_xref.clone();
+            });
+        }
+        self.xref.as_ref().unwrap()
+    }
+
+    /// Returns the `$p` statements whose proofs cite a label, in database
+    /// order.
+    pub fn usages(&mut self, label: &str) -> Vec<StatementRef<'_>> {
+        let addrs = self.xref_result().usages(label.as_bytes());
+        let sset = self.parse_result();
+        addrs.into_iter().map(|addr| sset.statement(addr)).collect()
+    }
+
+    /// Returns the axioms and definitions which a statement ultimately depends
+    /// on, or `None` if there is no statement with the label.
+    ///
+    /// Definitions are identified by the `definition_prefix` option.  Results
+    /// are memoised until the database is next parsed, so repeated queries
+    /// share the work of tracing common lemmas.
+    pub fn dependencies(&mut self, label: &str) -> Option<Dependencies> {
+        let parse = self.parse_result().clone();
+        let name = self.name_result().clone();
+        let addr = name.lookup_label(label.as_bytes())?.address;
+        let prefix = defck::effective_prefix(&self.options.definition_prefix).to_owned();
+        let cache = self
+            .dependencies
+            .get_or_insert_with(DependencyCache::default);
+        Some(time(&self.options.clone(), "dependencies", || {
+            cache.dependencies(&parse, &name, addr, &prefix)
+        }))
+    }
+
+    /// Checks that no theorem depends on a forbidden axiom, and returns the
+    /// result.
+    ///
+    /// Axioms are forbidden by the `forbidden_axioms` option and by markers in
+    /// the database; the dependencies traced are memoised as for
+    /// `dependencies`.
+    pub fn forbidden_result(&mut self) -> &Arc<ForbiddenResult> {
+        if self.forbidden.is_none() {
+            self.commands_result();
+            time(&self.options.clone(), "forbidden", || {
+                let parse = self.parse_result().clone();
+                let name = self.name_result().clone();
+                let scope = self.scope_result().clone();
+                let commands = self.commands_result().clone();
+                let extra = self.options.forbidden_axioms.clone();
+                let cache = self
+                    .dependencies
+                    .get_or_insert_with(DependencyCache::default);
+                let mut result = ForbiddenResult::default();
+                forbidck::forbidden_check(
+                    &mut result,
+                    &parse,
+                    &name,
+                    &scope,
+                    &commands,
+                    cache,
+                    &extra,
+                );
+                self.forbidden = Some(Arc::new(result));
+            });
+        }
+        self.forbidden.as_ref().unwrap()
+    }
+
+    /// Checks the discouraged tags of the database, and returns the result.
+    pub fn discouraged_result(&mut self) -> &Arc<DiscouragedResult> {
+        if self.discouraged.is_none() {
+            self.name_result();
+            time(&self.options.clone(), "discouraged", || {
+                let parse = self.parse_result().clone();
+                let name = self.name_result().clone();
+                let mut result = DiscouragedResult::default();
+                discouraged::discouraged_check(&mut result, &parse, &name);
+                self.discouraged = Some(Arc::new(result));
+            });
+        }
+        self.discouraged.as_ref().unwrap()
+    }
+
+    /// Checks the markup of comments, and returns the result.
+    pub fn markup_result(&mut self) -> &Arc<MarkupResult> {
+        if self.markup.is_none() {
+            self.name_result();
+            time(&self.options.clone(), "markup", || {
+                let parse = self.parse_result().clone();
+                let name = self.name_result().clone();
+                let mut result = MarkupResult::default();
+                markupck::markup_check(&mut result, &parse, &name);
+                self.markup = Some(Arc::new(result));
+            });
+        }
+        self.markup.as_ref().unwrap()
+    }
+
+    /// Runs the lints named by the `lints` option, and returns the result.
+    pub fn lint_result(&mut self) -> &Arc<LintResult> {
+        if self.lint.is_none() {
+            self.scope_result();
+            time(&self.options.clone(), "lint", || {
+                let parse = self.parse_result().clone();
+                let name = self.name_result().clone();
+                let scope = self.scope_result().clone();
+                let commands = self.commands_result().clone();
+                let lints = lint::lints_named(&self.options.lints);
+                let prefix = defck::effective_prefix(&self.options.definition_prefix).to_owned();
+                let typecode = grammar::provable_typecode(&parse, &commands);
+                let mut result = LintResult::default();
+                lint::lint(
+                    &mut result,
+                    &parse,
+                    &name,
+                    &scope,
+                    &lints,
+                    &prefix,
+                    typecode,
+                );
+                self.lint = Some(Arc::new(result));
+            });
+        }
+        self.lint.as_ref().unwrap()
+    }
+
+    /// Finds the assertions which are equivalent to one another, and returns
+    /// the result.
+    pub fn duplicate_result(&mut self) -> &Arc<DuplicateResult> {
+        if self.duplicates.is_none() {
+            self.scope_result();
+            time(&self.options.clone(), "duplicates", || {
+                if self.prev_duplicates.is_none() {
+                    self.prev_duplicates = Some(Arc::new(DuplicateResult::default()));
+                }
+
+                let parse = self.parse_result().clone();
+                let name = self.name_result().clone();
+                let scope = self.scope_result().clone();
+                {
+                    let dr = Arc::make_mut(self.prev_duplicates.as_mut().unwrap());
+                    dupck::find_duplicates(dr, &parse, &name, &scope);
+                }
+                self.duplicates = self.prev_duplicates.clone();
+            });
+        }
+        self.duplicates.as_ref().unwrap()
+    }
+
     /// Get a statement by label.
     pub fn statement(&mut self, name: &str) -> Option<StatementRef> {
         match self.name_result().lookup_label(name.as_bytes()) {
@@ -551,6 +992,137 @@ impl Database {
         })
     }
 
+    /// Export a LaTeX file for a given statement, typeset with the `latexdef`
+    /// definitions of the database.
+    pub fn latex(&mut self, stmt: String) {
+        time(&self.options.clone(), "latex", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let table = self.typesetting_result().clone();
+            let sref = self.statement(&stmt).unwrap_or_else(|| {
+                panic!(
+                    "Label {} did not correspond to an existing statement",
+                    &stmt
+                )
+            });
+
+            File::create(format!("{}.tex", stmt.clone()))
+                .map_err(export::ExportError::Io)
+                .and_then(|mut file| {
+                    latex::export_latex(&parse, &name, &scope, &table, sref, &mut file)
+                })
+                .unwrap()
+        })
+    }
+
+    /// Writes an HTML page for every `$p` statement to a directory.
+    ///
+    /// Pages are typeset with the definitions from the `$t` comments.  Pages
+    /// written by a previous call to the same directory are only regenerated
+    /// if their segment or the typesetting table has changed.  Returns the
+    /// number of pages written.
+    pub fn html(&mut self, dir: String) -> io::Result<usize> {
+        time(&self.options.clone(), "html", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let table = self.typesetting_result().clone();
+            html::html_pages(
+                &mut self.html,
+                &parse,
+                &name,
+                &scope,
+                &table,
+                Path::new(&dir),
+            )
+        })
+    }
+
+    /// Replaces the proofs of one or more `$p` statements in the source text.
+    ///
+    /// Each label is paired with its new proof, which will be written in the
+    /// given style.  Returns the name and new contents of every affected
+    /// source file; the database itself is not modified, so the files must be
+    /// parsed again to observe the change.
+    pub fn rewrite_proofs(
+        &mut self,
+        proofs: &[(String, ProofTreeArray)],
+        style: ProofStyle,
+    ) -> Result<Vec<(String, Vec<u8>)>, export::ExportError> {
+        time(&self.options.clone(), "rewrite", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let stmts = proofs
+                .iter()
+                .map(|(label, arr)| match name.lookup_label(label.as_bytes()) {
+                    Some(lookup) => Ok((parse.statement(lookup.address), arr)),
+                    None => Err(export::ExportError::UnknownLabel(label.clone())),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rewrite::rewrite_proofs(
+                &parse, &name, &scope, &stmts, style,
+            )?)
+        })
+    }
+
+    /// Replaces the proofs of `$p` statements as for `rewrite_proofs`, and
+    /// writes the modified source files back to disk.
+    pub fn write_proofs(
+        &mut self,
+        proofs: &[(String, ProofTreeArray)],
+        style: ProofStyle,
+    ) -> Result<(), export::ExportError> {
+        for (path, text) in self.rewrite_proofs(proofs, style)? {
+            File::create(path)?.write_all(&text)?;
+        }
+        Ok(())
+    }
+
+    /// Import an mmp file, replacing the proof of its theorem in the source
+    /// files with the one described by the worksheet.
+    ///
+    /// The new proof is written in compressed style.  Returns the problems
+    /// found in the worksheet, with locations in the worksheet file; if there
+    /// are any, the source files are left untouched.
+    pub fn import(&mut self, file: String) -> Vec<Notation> {
+        time(&self.options.clone(), "import", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+
+            let mut text = Vec::new();
+            let diags = match File::open(&file).and_then(|mut f| f.read_to_end(&mut text)) {
+                Err(err) => vec![Diagnostic::from(err)],
+                Ok(_) => match import_mmp::import_mmp(&parse, &name, &scope, &text) {
+                    Err(diags) => diags,
+                    Ok((addr, arr)) => {
+                        let label = String::from_utf8_lossy(parse.statement(addr).label());
+                        match self
+                            .write_proofs(&[(label.into_owned(), arr)], ProofStyle::Compressed)
+                        {
+                            Ok(()) => vec![],
+                            Err(export::ExportError::Io(err)) => vec![Diagnostic::from(err)],
+                            Err(export::ExportError::Verify(diag)) => vec![diag],
+                            // only if the label is not valid UTF-8
+                            Err(export::ExportError::UnknownLabel(_)) => {
+                                vec![Diagnostic::MmpUnknownTheorem(Span::null())]
+                            }
+                        }
+                    }
+                },
+            };
+
+            let source = Arc::new(SourceInfo {
+                span: Span::new(0, text.len()),
+                name: file,
+                text: Arc::new(text),
+            });
+            diag::to_worksheet_annotations(&source, diags)
+        })
+    }
+
     /// Runs one or more passes and collects all errors they generate.
     ///
     /// Passes are identified by the `types` argument and are not inclusive; if
@@ -570,6 +1142,31 @@ impl Database {
         if types.contains(&DiagnosticClass::Verify) {
             diags.extend(self.verify_result().diagnostics());
         }
+        if types.contains(&DiagnosticClass::Grammar) {
+            diags.extend(self.grammar_result().diagnostics());
+            diags.extend(self.ambiguity_result().diagnostics());
+        }
+        if types.contains(&DiagnosticClass::Definition) {
+            diags.extend(self.definition_result().diagnostics());
+        }
+        if types.contains(&DiagnosticClass::Typesetting) {
+            diags.extend(self.typesetting_result().diagnostics());
+        }
+        if types.contains(&DiagnosticClass::Forbidden) {
+            diags.extend(self.forbidden_result().diagnostics());
+        }
+        if types.contains(&DiagnosticClass::Discouraged) {
+            diags.extend(self.discouraged_result().diagnostics());
+        }
+        if types.contains(&DiagnosticClass::Markup) {
+            diags.extend(self.markup_result().diagnostics());
+        }
+        if types.contains(&DiagnosticClass::Lint) {
+            diags.extend(self.lint_result().diagnostics());
+        }
+        if types.contains(&DiagnosticClass::Duplicate) {
+            diags.extend(self.duplicate_result().diagnostics());
+        }
         time(&self.options.clone(), "diag", || {
             diag::to_annotations(self.parse_result(), diags)
         })
@@ -0,0 +1,446 @@
+//! Soundness checks for definitional axioms.
+//!
+//! In set.mm a definition is an `$a |-` statement, found by its label prefix
+//! (`df-` by default), of the form `A = B` or `( ph <-> ps )` whose left side
+//! applies a new syntax axiom, the _defined constructor_, to distinct
+//! variables.  Such an axiom is conservative, and therefore harmless, provided
+//! that:
+//!
+//! 1. The defined constructor is not used by any logical statement before the
+//!    definition, and is defined only once.
+//! 2. The right side does not use the defined constructor, nor any constructor
+//!    whose definition comes later, so that definitions cannot be circular.
+//! 3. Every _dummy variable_, which appears on the right side but not the left,
+//!    is in a `$d` constraint with every other variable of the definition.
+//!
+//! The left and right sides are found by parsing the definition with the
+//! grammar of the database.  Uses of defined constructors are found by parsing
+//! every logical statement, one segment at a time in parallel.
+//!
+//! The equality relations are the syntax axioms named by `$j` commands such as
+//! `equality 'wceq' from 'eqid' 'eqcomi' 'eqtri';`, or failing those, any
+//! syntax axiom whose only constant other than parentheses is `=` or `<->`.
+//! Definitions which do not fit the usual form, such as `df-bi` and `df-cleq`
+//! in set.mm, can be declared with a command like `definition 'df-bi' for
+//! 'wb';`; only the first of the checks above is applied to them.
+
+use commands::CommandResult;
+use diag::Diagnostic;
+use grammar::Grammar;
+use nameck::Atom;
+use nameck::Nameset;
+use parser::copy_token;
+use parser::Comparer;
+use parser::SegmentId;
+use parser::StatementAddress;
+use parser::StatementRef;
+use parser::StatementType;
+use proof::ProofTreeArray;
+use scopeck::Frame;
+use scopeck::Hyp;
+use scopeck::ScopeResult;
+use segment_set::SegmentSet;
+use std::cmp::Ordering;
+use std::sync::Arc;
+use util::new_map;
+use util::HashMap;
+
+/// Label prefix of definitions when none is configured.
+const DEFAULT_PREFIX: &str = "df-";
+
+/// `$j` keyword of commands naming the syntax axiom of an equality relation.
+const EQUALITY_KEYWORD: &[u8] = b"equality";
+
+/// `$j` keyword of commands naming the syntax axiom defined by a definition.
+const DEFINITION_KEYWORD: &[u8] = b"definition";
+
+/// Constants which may join the two sides of a definition, once parentheses
+/// have been discounted, in a database without `equality` commands.
+const DEFAULT_EQUALITY_SYMBOLS: &[&[u8]] = &[b"=", b"<->"];
+
+/// A definition which has been parsed into its two sides, or which was
+/// declared by a `definition` command.
+struct Definition {
+    /// The definitional axiom.
+    address: StatementAddress,
+    /// The syntax axiom applied on the left side.
+    constructor: StatementAddress,
+    /// Syntax tree of the definition.
+    arr: ProofTreeArray,
+    /// Index of the right side in `arr`, unless the definition was declared
+    /// rather than split.
+    rhs: Option<usize>,
+}
+
+/// Conventions of a database which are declared by its `$j` commands.
+#[derive(Default)]
+struct Declarations {
+    /// Syntax axioms of the equality relations, if any were declared.
+    equalities: Vec<StatementAddress>,
+    /// The syntax axiom defined by each declared definition.
+    definitions: HashMap<StatementAddress, StatementAddress>,
+}
+
+impl Declarations {
+    /// Reads the `equality` and `definition` commands of a database.  Commands
+    /// naming unknown labels are ignored.
+    fn new(sset: &SegmentSet, nset: &Nameset, commands: &CommandResult) -> Declarations {
+        let address = |label: &[u8]| nset.lookup_label(label).map(|lookup| lookup.address);
+        let mut decls = Declarations::default();
+        for entry in commands.get(EQUALITY_KEYWORD) {
+            if let Some(addr) = entry.args(sset).first().and_then(|&label| address(label)) {
+                decls.equalities.push(addr);
+            }
+        }
+        for entry in commands.get(DEFINITION_KEYWORD) {
+            let args = entry.args(sset);
+            if args.len() == 3 && args[1] == b"for" {
+                if let (Some(def), Some(constructor)) = (address(args[0]), address(args[2])) {
+                    decls.definitions.insert(def, constructor);
+                }
+            }
+        }
+        decls
+    }
+}
+
+/// Analysis pass result for the definition checker.
+#[derive(Default, Clone)]
+pub struct DefinitionResult {
+    diagnostics: Vec<(StatementAddress, Diagnostic)>,
+}
+
+impl DefinitionResult {
+    /// Report definitions which may not be conservative.
+    pub fn diagnostics(&self) -> Vec<(StatementAddress, Diagnostic)> {
+        self.diagnostics.clone()
+    }
+}
+
+/// Returns the label prefix of definitions: `prefix`, or `df-` if it is empty.
+pub fn effective_prefix(prefix: &str) -> &str {
+    if prefix.is_empty() {
+        DEFAULT_PREFIX
+    } else {
+        prefix
+    }
+}
+
+/// Returns true if the statement is a `$a` whose label has the prefix and whose
+/// typecode is the provable typecode.
+fn is_definition(grammar: &Grammar, nset: &Nameset, prefix: &str, stmt: StatementRef) -> bool {
+    stmt.statement_type() == StatementType::Axiom
+        && stmt.label().starts_with(prefix.as_bytes())
+        && grammar.is_logical(nset, stmt)
+}
+
+/// Splits a definition into its two sides, checking the form of the left side.
+fn split_definition(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scope: &ScopeResult,
+    grammar: &Grammar,
+    decls: &Declarations,
+    stmt: StatementRef,
+) -> Result<Definition, Diagnostic> {
+    let arr = grammar
+        .parse_statement(nset, scope, stmt)
+        .map_err(|_| Diagnostic::DefinitionBadForm)?;
+    let root = &arr.trees[arr.qed];
+    let frame = scope
+        .get(sset.statement(root.address).label())
+        .ok_or(Diagnostic::DefinitionBadForm)?;
+
+    // the root must be an equality of the form `A = B` or `( ph <-> ps )`
+    let tail = &frame.target.tail;
+    if tail.len() != 2 || frame.hypotheses.len() != 2 {
+        return Err(Diagnostic::DefinitionBadForm);
+    }
+    let is_equality = if decls.equalities.is_empty() {
+        joined_by_equality(frame)
+    } else {
+        decls.equalities.contains(&root.address)
+    };
+    if !is_equality {
+        return Err(Diagnostic::DefinitionBadForm);
+    }
+    let child = |var| {
+        frame
+            .hypotheses
+            .iter()
+            .position(|hyp| match *hyp {
+                Hyp::Floating(_, fvar, _) => fvar == var,
+                Hyp::Essential(..) => false,
+            })
+            .map(|ix| root.children[ix])
+    };
+    let lhs = child(tail[0].var).ok_or(Diagnostic::DefinitionBadForm)?;
+    let rhs = child(tail[1].var).ok_or(Diagnostic::DefinitionBadForm)?;
+
+    // the left side must apply a syntax axiom to distinct variables
+    let lhs_tree = &arr.trees[lhs];
+    if sset.statement(lhs_tree.address).statement_type() != StatementType::Axiom {
+        return Err(Diagnostic::DefinitionBadForm);
+    }
+    for (ix, &child) in lhs_tree.children.iter().enumerate() {
+        if !arr.trees[child].children.is_empty() || lhs_tree.children[..ix].contains(&child) {
+            return Err(Diagnostic::DefinitionBadForm);
+        }
+    }
+
+    Ok(Definition {
+        address: stmt.address(),
+        constructor: lhs_tree.address,
+        rhs: Some(rhs),
+        arr,
+    })
+}
+
+/// Returns true if the only constant of a binary syntax axiom, other than
+/// parentheses, is one of the default equality symbols.
+fn joined_by_equality(frame: &Frame) -> bool {
+    let tail = &frame.target.tail;
+    let mut consts = Vec::new();
+    let ranges = [
+        tail[0].prefix.clone(),
+        tail[1].prefix.clone(),
+        frame.target.rump.clone(),
+    ];
+    for range in ranges.iter() {
+        let mut token = Vec::new();
+        for &chr in &frame.const_pool[range.clone()] {
+            token.push(chr & 0x7F);
+            if chr & 0x80 != 0 {
+                if token != b"(" && token != b")" {
+                    consts.push(token.clone());
+                }
+                token.clear();
+            }
+        }
+    }
+    consts.len() == 1 && DEFAULT_EQUALITY_SYMBOLS.contains(&&consts[0][..])
+}
+
+/// Collects the syntax axioms and `$f` statements used in a subtree.
+fn collect_nodes(arr: &ProofTreeArray, ix: usize, out: &mut Vec<StatementAddress>) {
+    let tree = &arr.trees[ix];
+    if !out.contains(&tree.address) {
+        out.push(tree.address);
+    }
+    for &child in &tree.children {
+        collect_nodes(arr, child, out);
+    }
+}
+
+/// Finds the first logical statement in a segment using each defined
+/// constructor.
+fn first_uses(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scope: &ScopeResult,
+    grammar: &Grammar,
+    constructors: &HashMap<StatementAddress, StatementAddress>,
+    sid: SegmentId,
+) -> HashMap<StatementAddress, StatementAddress> {
+    let mut uses = new_map();
+    for stmt in sset.segment(sid) {
+        if !grammar.is_logical(nset, stmt) {
+            continue;
+        }
+        if let Ok(arr) = grammar.parse_statement(nset, scope, stmt) {
+            for tree in &arr.trees {
+                if constructors.contains_key(&tree.address) {
+                    uses.entry(tree.address).or_insert_with(|| stmt.address());
+                }
+            }
+        }
+    }
+    uses
+}
+
+/// Checks the `$d` constraints on the dummy variables of a definition.
+fn check_dummies(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scope: &ScopeResult,
+    def: &Definition,
+    out: &mut Vec<(StatementAddress, Diagnostic)>,
+) {
+    let rhs_ix = match def.rhs {
+        Some(rhs_ix) => rhs_ix,
+        None => return,
+    };
+    let var_of = |addr: StatementAddress| {
+        nset.lookup_symbol(&sset.statement(addr).math_at(1))
+            .map(|lookup| lookup.atom)
+    };
+    let mut all = Vec::new();
+    collect_nodes(&def.arr, def.arr.qed, &mut all);
+    let mut rhs = Vec::new();
+    collect_nodes(&def.arr, rhs_ix, &mut rhs);
+    let lhs_vars = def.arr.trees[def.arr.qed]
+        .children
+        .iter()
+        .filter(|&&ix| ix != rhs_ix)
+        .flat_map(|&ix| def.arr.trees[ix].children.iter())
+        .filter_map(|&ix| var_of(def.arr.trees[ix].address))
+        .collect::<Vec<Atom>>();
+    let is_float =
+        |addr: &StatementAddress| sset.statement(*addr).statement_type() == StatementType::Floating;
+    let distinct_vars = |addrs: &[StatementAddress]| {
+        let mut vars = Vec::new();
+        for var in addrs
+            .iter()
+            .filter(|addr| is_float(addr))
+            .filter_map(|&addr| var_of(addr))
+        {
+            if !vars.contains(&var) {
+                vars.push(var);
+            }
+        }
+        vars
+    };
+    let all_vars = distinct_vars(&all);
+    let dummies = distinct_vars(&rhs)
+        .into_iter()
+        .filter(|var| !lhs_vars.contains(var))
+        .collect::<Vec<Atom>>();
+    if dummies.is_empty() {
+        return;
+    }
+
+    let frame = match scope.get(sset.statement(def.address).label()) {
+        Some(frame) => frame,
+        None => return,
+    };
+    let index = |var: Atom| {
+        frame.var_list[..frame.mandatory_count]
+            .iter()
+            .position(|&v| v == var)
+    };
+    for (ix, &dummy) in dummies.iter().enumerate() {
+        for &other in &all_vars {
+            // a pair of dummies is checked once, from the earlier one
+            if other == dummy || dummies[..ix].contains(&other) {
+                continue;
+            }
+            let disjoint = match (index(dummy), index(other)) {
+                (Some(d), Some(o)) => frame
+                    .mandatory_dv
+                    .iter()
+                    .any(|&pair| pair == (d, o) || pair == (o, d)),
+                _ => false,
+            };
+            if !disjoint {
+                out.push((
+                    def.address,
+                    Diagnostic::DefinitionMissingDv(
+                        copy_token(nset.atom_name(dummy)),
+                        copy_token(nset.atom_name(other)),
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// Finds and checks the definitions of a database.
+///
+/// Definitions are the provable `$a` statements whose labels start with
+/// `prefix`, or with `df-` if `prefix` is empty.
+pub fn definition_check(
+    result: &mut DefinitionResult,
+    segments: &Arc<SegmentSet>,
+    nset: &Arc<Nameset>,
+    scope: &Arc<ScopeResult>,
+    grammar: &Arc<Grammar>,
+    commands: &CommandResult,
+    prefix: &str,
+) {
+    let prefix = effective_prefix(prefix);
+    let order = &segments.order;
+    let decls = Declarations::new(segments, nset, commands);
+    let mut diagnostics = Vec::new();
+
+    // find the definitions, and the first definition of each constructor
+    let mut defs = Vec::new();
+    let mut constructors = new_map();
+    for sref in segments.segments() {
+        for stmt in sref {
+            if !is_definition(grammar, nset, prefix, stmt) {
+                continue;
+            }
+            let split = split_definition(segments, nset, scope, grammar, &decls, stmt);
+            let declared = decls
+                .definitions
+                .get(&stmt.address())
+                .map(|&constructor| Definition {
+                    address: stmt.address(),
+                    constructor,
+                    arr: ProofTreeArray::default(),
+                    rhs: None,
+                });
+            match split.or_else(|diag| declared.ok_or(diag)) {
+                Err(diag) => diagnostics.push((stmt.address(), diag)),
+                Ok(def) => {
+                    if let Some(&prev) = constructors.get(&def.constructor) {
+                        diagnostics.push((def.address, Diagnostic::DefinitionDuplicate(prev)));
+                    } else {
+                        constructors.insert(def.constructor, def.address);
+                        defs.push(def);
+                    }
+                }
+            }
+        }
+    }
+    let constructors = Arc::new(constructors);
+
+    // find the first use of each defined constructor
+    let mut usesq = Vec::new();
+    for sref in segments.segments() {
+        let segments2 = segments.clone();
+        let nset = nset.clone();
+        let scope = scope.clone();
+        let grammar = grammar.clone();
+        let constructors = constructors.clone();
+        let id = sref.id;
+        usesq.push(segments.exec.exec(sref.bytes(), move || {
+            first_uses(&segments2, &nset, &scope, &grammar, &constructors, id)
+        }));
+    }
+    let mut uses: HashMap<StatementAddress, StatementAddress> = new_map();
+    for promise in usesq {
+        for (constructor, stmt) in promise.wait() {
+            let first = uses.entry(constructor).or_insert(stmt);
+            if order.cmp(&stmt, first) == Ordering::Less {
+                *first = stmt;
+            }
+        }
+    }
+
+    for def in &defs {
+        if let Some(&first) = uses.get(&def.constructor) {
+            if order.cmp(&first, &def.address) == Ordering::Less {
+                diagnostics.push((def.address, Diagnostic::DefinitionUsedBefore(first)));
+            }
+        }
+
+        let mut rhs = Vec::new();
+        if let Some(rhs_ix) = def.rhs {
+            collect_nodes(&def.arr, rhs_ix, &mut rhs);
+        }
+        for addr in rhs {
+            let circular = addr == def.constructor
+                || constructors
+                    .get(&addr)
+                    .is_some_and(|&other| order.cmp(&other, &def.address) == Ordering::Greater);
+            if circular {
+                diagnostics.push((def.address, Diagnostic::DefinitionCircular(addr)));
+            }
+        }
+
+        check_dummies(segments, nset, scope, def, &mut diagnostics);
+    }
+
+    result.diagnostics = diagnostics;
+}
@@ -0,0 +1,161 @@
+use database::DbOptions;
+use diag::Diagnostic;
+use parser::StatementAddress;
+use test_util::mkdb;
+
+const DEFS: &str = "
+  $c 0 1 2 + = -> ( ) f term wff |- $.
+  $v t r s P Q $.
+  tt $f term t $.
+  tr $f term r $.
+  ts $f term s $.
+  wp $f wff P $.
+  wq $f wff Q $.
+  tze $a term 0 $.
+  tpl $a term ( t + r ) $.
+  weq $a wff t = r $.
+  wim $a wff ( P -> Q ) $.
+  tone $a term 1 $.
+  ttwo $a term 2 $.
+  tf $a term f ( r ) $.
+  EXTRA
+";
+
+fn definition_diags(extra: &str, prefix: &str) -> Vec<(String, Diagnostic)> {
+    let mut db = mkdb(
+        &DEFS.replace("EXTRA", extra),
+        DbOptions {
+            definition_prefix: prefix.to_owned(),
+            ..DbOptions::default()
+        },
+    );
+    let sset = db.parse_result().clone();
+    db.definition_result()
+        .diagnostics()
+        .into_iter()
+        .map(|(addr, diag)| {
+            let label = String::from_utf8(sset.statement(addr).label().to_owned()).unwrap();
+            (label, diag)
+        })
+        .collect()
+}
+
+fn address(extra: &str, label: &str) -> StatementAddress {
+    let mut db = mkdb(&DEFS.replace("EXTRA", extra), DbOptions::default());
+    db.name_result()
+        .lookup_label(label.as_bytes())
+        .unwrap()
+        .address
+}
+
+#[test]
+fn test_sound_definitions() {
+    let extra = "df-one $a |- 1 = ( 0 + 0 ) $.
+                 df-two $a |- 2 = ( 1 + 1 ) $.
+                 ${ $d r t $. df-f $a |- f ( r ) = ( r + t ) $. $}";
+    assert_eq!(definition_diags(extra, ""), vec![]);
+}
+
+#[test]
+fn test_bad_form() {
+    let extra = "df-one $a |- ( 1 = 0 -> 0 = 1 ) $.
+                 df-f $a |- f ( ( r + r ) ) = r $.";
+    assert_eq!(
+        definition_diags(extra, ""),
+        vec![
+            ("df-one".to_owned(), Diagnostic::DefinitionBadForm),
+            ("df-f".to_owned(), Diagnostic::DefinitionBadForm),
+        ]
+    );
+}
+
+#[test]
+fn test_used_before() {
+    let extra = "ax-one $a |- 1 = 0 $.
+                 df-one $a |- 1 = ( 0 + 0 ) $.";
+    let ax = address(extra, "ax-one");
+    assert_eq!(
+        definition_diags(extra, ""),
+        vec![("df-one".to_owned(), Diagnostic::DefinitionUsedBefore(ax))]
+    );
+}
+
+#[test]
+fn test_duplicate_and_circular() {
+    let extra = "df-one $a |- 1 = ( 2 + 0 ) $.
+                 df-two $a |- 2 = ( 2 + 1 ) $.
+                 df-one2 $a |- 1 = 0 $.";
+    let ttwo = address(extra, "ttwo");
+    let df_one = address(extra, "df-one");
+    assert_eq!(
+        definition_diags(extra, ""),
+        vec![
+            (
+                "df-one2".to_owned(),
+                Diagnostic::DefinitionDuplicate(df_one)
+            ),
+            ("df-one".to_owned(), Diagnostic::DefinitionCircular(ttwo)),
+            (
+                "df-two".to_owned(),
+                Diagnostic::DefinitionUsedBefore(df_one)
+            ),
+            ("df-two".to_owned(), Diagnostic::DefinitionCircular(ttwo)),
+        ]
+    );
+}
+
+#[test]
+fn test_missing_dv_and_prefix() {
+    let extra = "def-f $a |- f ( r ) = ( r + t ) $.";
+    assert_eq!(definition_diags(extra, ""), vec![]);
+    assert_eq!(
+        definition_diags(extra, "def-"),
+        vec![(
+            "def-f".to_owned(),
+            Diagnostic::DefinitionMissingDv(
+                b"t".to_vec().into_boxed_slice(),
+                b"r".to_vec().into_boxed_slice()
+            )
+        )]
+    );
+}
+
+#[test]
+fn test_dummy_pairs_once() {
+    let extra = "def-f $a |- f ( r ) = ( ( t + s ) + t ) $.";
+    let pair = |dummy: &[u8], other: &[u8]| {
+        (
+            "def-f".to_owned(),
+            Diagnostic::DefinitionMissingDv(
+                dummy.to_vec().into_boxed_slice(),
+                other.to_vec().into_boxed_slice(),
+            ),
+        )
+    };
+    assert_eq!(
+        definition_diags(extra, "def-"),
+        vec![pair(b"t", b"r"), pair(b"t", b"s"), pair(b"s", b"r")]
+    );
+}
+
+#[test]
+fn test_declarations() {
+    // a declared definition need not have the usual form, but must still come
+    // before the uses of its syntax
+    let extra = "$( $j definition 'df-one' for 'tone'; $)
+                 ax-one $a |- 1 = 0 $.
+                 df-one $a |- ( 1 = 0 -> 0 = 1 ) $.";
+    let ax = address(extra, "ax-one");
+    assert_eq!(
+        definition_diags(extra, ""),
+        vec![("df-one".to_owned(), Diagnostic::DefinitionUsedBefore(ax))]
+    );
+
+    // once equalities are declared, `=` is no longer recognised by itself
+    let extra = "$( $j equality 'wim' from 'x'; $)
+                 df-one $a |- 1 = ( 0 + 0 ) $.";
+    assert_eq!(
+        definition_diags(extra, ""),
+        vec![("df-one".to_owned(), Diagnostic::DefinitionBadForm)]
+    );
+}
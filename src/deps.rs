@@ -0,0 +1,176 @@
+//! The axioms and definitions which theorems ultimately depend on.
+//!
+//! This is the equivalent of metamath.exe's `show trace_back /essential
+//! /axioms`: the proof of a theorem is followed through every cited `$p`
+//! statement, transitively, collecting the `$a` statements reached.  Only
+//! essential steps are followed, which are the steps whose typecode is that of
+//! the theorem, so syntax axioms such as `wi` are not reported for a `|-`
+//! theorem.
+//!
+//! The dependencies of every theorem visited are memoised as a bitset over the
+//! axioms of the database, so that a query for many theorems, or for all of
+//! them, visits each proof only once.
+
+use bit_set::Bitset;
+use nameck::Nameset;
+use parser::Comparer;
+use parser::StatementAddress;
+use parser::StatementType;
+use segment_set::SegmentSet;
+use util::new_set;
+use util::HashMap;
+use xref;
+
+/// The `$a` statements a theorem depends on, in database order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dependencies {
+    /// Axioms whose labels do not have the definition prefix.
+    pub axioms: Vec<StatementAddress>,
+    /// Axioms whose labels have the definition prefix.
+    pub definitions: Vec<StatementAddress>,
+}
+
+/// Memoised dependencies for one version of a database.
+#[derive(Default)]
+pub struct DependencyCache {
+    /// Each axiom reached so far, by its bit in the sets.
+    axioms: Vec<StatementAddress>,
+    axiom_bits: HashMap<StatementAddress, usize>,
+    /// Dependencies of each `$p` statement visited so far.
+    theorems: HashMap<StatementAddress, Bitset>,
+}
+
+/// Returns the `$a` and `$p` statements cited by the essential steps of a
+/// proof.
+fn essential_citations(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    addr: StatementAddress,
+) -> Vec<StatementAddress> {
+    let stmt = sset.statement(addr);
+    if stmt.math_len() == 0 {
+        return Vec::new();
+    }
+    let typecode = stmt.math_at(0).slice;
+    xref::cited_labels(stmt)
+        .into_iter()
+        .filter_map(|label| nset.lookup_label(label))
+        .map(|lookup| sset.statement(lookup.address))
+        .filter(|cited| {
+            let stype = cited.statement_type();
+            (stype == StatementType::Axiom || stype == StatementType::Provable)
+                && cited.math_len() > 0
+                && cited.math_at(0).slice == typecode
+        })
+        .map(|cited| cited.address())
+        .collect()
+}
+
+impl DependencyCache {
+    fn axiom_bit(&mut self, addr: StatementAddress) -> usize {
+        let axioms = &mut self.axioms;
+        *self.axiom_bits.entry(addr).or_insert_with(|| {
+            axioms.push(addr);
+            axioms.len() - 1
+        })
+    }
+
+    /// Calculates the dependencies of a `$p` statement and everything it
+    /// cites, without recursion so that long chains of lemmas are harmless.
+    fn visit(&mut self, sset: &SegmentSet, nset: &Nameset, root: StatementAddress) {
+        let mut active = new_set();
+        let mut stack = vec![(root, false)];
+        while let Some((addr, expanded)) = stack.pop() {
+            if self.theorems.contains_key(&addr) {
+                continue;
+            }
+            let cited = essential_citations(sset, nset, addr);
+            if !expanded {
+                // a theorem already in progress is cited circularly; the
+                // database is invalid, so just avoid looping
+                if !active.insert(addr) {
+                    continue;
+                }
+                stack.push((addr, true));
+                for &dep in &cited {
+                    if sset.statement(dep).statement_type() == StatementType::Provable {
+                        stack.push((dep, false));
+                    }
+                }
+            } else {
+                let mut set = Bitset::new();
+                for dep in cited {
+                    if sset.statement(dep).statement_type() == StatementType::Axiom {
+                        set.set_bit(self.axiom_bit(dep));
+                    } else if let Some(deps) = self.theorems.get(&dep) {
+                        set |= deps;
+                    }
+                }
+                self.theorems.insert(addr, set);
+            }
+        }
+    }
+
+    /// Returns true if a `$a` or `$p` statement depends on an axiom.
+    pub fn depends_on(
+        &mut self,
+        sset: &SegmentSet,
+        nset: &Nameset,
+        addr: StatementAddress,
+        axiom: StatementAddress,
+    ) -> bool {
+        match sset.statement(addr).statement_type() {
+            StatementType::Axiom => addr == axiom,
+            StatementType::Provable => {
+                self.visit(sset, nset, addr);
+                // an axiom without a bit has not been reached by any proof
+                self.axiom_bits
+                    .get(&axiom)
+                    .is_some_and(|&bit| self.theorems[&addr].has_bit(bit))
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the axioms and definitions a statement depends on, classifying
+    /// them by the label prefix of definitions.
+    ///
+    /// A `$p` statement depends on the axioms its proof reaches, and a `$a`
+    /// statement only on itself; other statements have no dependencies.
+    pub fn dependencies(
+        &mut self,
+        sset: &SegmentSet,
+        nset: &Nameset,
+        addr: StatementAddress,
+        definition_prefix: &str,
+    ) -> Dependencies {
+        let mut set = Bitset::new();
+        match sset.statement(addr).statement_type() {
+            StatementType::Axiom => set.set_bit(self.axiom_bit(addr)),
+            StatementType::Provable => {
+                self.visit(sset, nset, addr);
+                set = self.theorems[&addr].clone();
+            }
+            _ => {}
+        }
+
+        let mut addrs = (&set)
+            .into_iter()
+            .map(|bit| self.axioms[bit])
+            .collect::<Vec<_>>();
+        addrs.sort_by(|a, b| sset.order.cmp(a, b));
+        let mut deps = Dependencies::default();
+        for addr in addrs {
+            if sset
+                .statement(addr)
+                .label()
+                .starts_with(definition_prefix.as_bytes())
+            {
+                deps.definitions.push(addr);
+            } else {
+                deps.axioms.push(addr);
+            }
+        }
+        deps
+    }
+}
@@ -0,0 +1,61 @@
+use database::Database;
+use database::DbOptions;
+use test_util::mkdb;
+
+const DB: &str = "$c wff |- ( -> ) $.
+    $v ph ps $.
+    wph $f wff ph $.
+    wps $f wff ps $.
+    wi $a wff ( ph -> ps ) $.
+    ax-1 $a |- ( ph -> ( ps -> ph ) ) $.
+    df-id $a |- ( ph -> ph ) $.
+    ${
+      mp.1 $e |- ph $.
+      mp.2 $e |- ( ph -> ps ) $.
+      ax-mp $a |- ps $.
+    $}
+    ${
+      a1i.1 $e |- ph $.
+      a1i $p |- ( ps -> ph ) $= wph wps wph wi a1i.1 wph wps ax-1 ax-mp $.
+    $}
+    ${
+      mpd.1 $e |- ph $.
+      mpd $p |- ph $= wph wph mpd.1 wph df-id ax-mp $.
+    $}
+    ${
+      both.1 $e |- ph $.
+      both $p |- ( ps -> ph ) $= wph wps wph both.1 mpd a1i $.
+    $}
+    th $p wff ( ph -> ph ) $= wph wph wi $.";
+
+fn labels(db: &mut Database, label: &str) -> (Vec<String>, Vec<String>) {
+    let deps = db.dependencies(label).unwrap();
+    let sset = db.parse_result();
+    let names = |addrs: Vec<_>| {
+        addrs
+            .into_iter()
+            .map(|addr| String::from_utf8_lossy(sset.statement(addr).label()).into_owned())
+            .collect::<Vec<_>>()
+    };
+    (names(deps.axioms), names(deps.definitions))
+}
+
+#[test]
+fn test_dependencies() {
+    let mut db = mkdb(DB, DbOptions::default());
+    assert_eq!(
+        labels(&mut db, "a1i"),
+        (vec!["ax-1".to_owned(), "ax-mp".to_owned()], vec![])
+    );
+    assert_eq!(
+        labels(&mut db, "both"),
+        (
+            vec!["ax-1".to_owned(), "ax-mp".to_owned()],
+            vec!["df-id".to_owned()]
+        )
+    );
+    // syntax axioms are only reached by syntax theorems
+    assert_eq!(labels(&mut db, "th"), (vec!["wi".to_owned()], vec![]));
+    assert_eq!(labels(&mut db, "ax-1"), (vec!["ax-1".to_owned()], vec![]));
+    assert!(db.dependencies("nonexistent").is_none());
+}
@@ -33,6 +33,30 @@ pub enum DiagnosticClass {
     /// Verify errors do not invalidate the interpretation of statements, but
     /// affect only proofs.
     Verify,
+    /// Grammar errors are statements which cannot be parsed into a unique
+    /// syntax tree using the syntax axioms of the database, and syntax axioms
+    /// which make the grammar ambiguous.
+    Grammar,
+    /// Definition errors are definitional axioms which may not be
+    /// conservative extensions of the database.
+    Definition,
+    /// Typesetting errors are `$t` commands which cannot be used to build the
+    /// typesetting table.
+    Typesetting,
+    /// Forbidden axiom errors are proofs which depend on axioms that the
+    /// database or the user has forbidden.
+    Forbidden,
+    /// Discouraged warnings are uses of statements tagged as discouraged.
+    Discouraged,
+    /// Markup warnings are comments with references to labels or math
+    /// symbols which cannot be resolved.
+    Markup,
+    /// Lint warnings are departures from the style conventions of set.mm,
+    /// found by the lints which were asked for.
+    Lint,
+    /// Duplicate warnings are assertions which are equivalent to an earlier
+    /// assertion up to the names of variables.
+    Duplicate,
 }
 
 /// List of all diagnostic codes.  For a description of each, see the source of
@@ -48,12 +72,23 @@ pub enum Diagnostic {
     BadCommentEnd(Span, Span),
     BadFloating,
     BadLabel(Span),
+    CommandExpectedKeyword(Span),
+    CommandMissingSemicolon(Span),
+    CommandUnclosedComment(Span),
+    CommandUnterminatedString(Span),
     CommentMarkerNotStart(Span),
     ConstantNotTopLevel,
+    DefinitionBadForm,
+    DefinitionCircular(StatementAddress),
+    DefinitionDuplicate(StatementAddress),
+    DefinitionMissingDv(Token, Token),
+    DefinitionUsedBefore(StatementAddress),
+    DiscouragedUsage(TokenIndex, StatementAddress),
     DisjointSingle,
     DjNotVariable(TokenIndex),
     DjRepeatedVariable(TokenIndex, TokenIndex),
     DuplicateLabel(StatementAddress),
+    DuplicateStatement(StatementAddress),
     EmptyFilename,
     EmptyMathString,
     EssentialAtTopLevel,
@@ -63,34 +98,65 @@ pub enum Diagnostic {
     FloatNotConstant(TokenIndex),
     FloatNotVariable(TokenIndex),
     FloatRedeclared(StatementAddress),
+    ForbiddenAxiom(Span, StatementAddress),
+    GrammarAmbiguous,
+    GrammarAmbiguousAxioms(StatementAddress),
+    GrammarUnparseable(TokenIndex),
     IoError(String),
+    LintAxiomLabel,
+    LintMissingModificationTag,
+    LintMissingUsageTag,
+    LintReservedLabel,
+    LintUnusedDvVariable(TokenIndex),
+    LintUnusedFloat,
+    MarkupLaterLabel(Span, StatementAddress),
+    MarkupUnclosedMath(Span),
+    MarkupUndefinedLabel(Span),
+    MarkupUndefinedSymbol(Span),
     MidStatementCommentMarker(Span),
     MissingLabel,
     MissingProof(Span),
+    MmpBadHeader(Span),
+    MmpBadStep(Span),
+    MmpDuplicateStep(Span),
+    MmpHypCount(Span, usize),
+    MmpMissingQed(Span),
+    MmpNoSyntax(Span),
+    MmpProofInvalid(Span, Box<Diagnostic>),
+    MmpStepMismatch(Span),
+    MmpUnknownLabel(Span),
+    MmpUnknownStep(Span),
+    MmpUnknownTheorem(Span),
     NestedComment(Span, Span),
     NotActiveSymbol(TokenIndex),
-    ProofDvViolation,
+    NotProvable,
+    ProofDvViolation(Vec<(Token, Token)>),
     ProofExcessEnd,
     ProofIncomplete,
     ProofInvalidSave,
     ProofMalformedVarint,
     ProofNoSteps,
-    ProofUnderflow,
+    ProofUnderflow(Box<StepInfo>),
     ProofUnterminatedRoster,
+    ProofUnusedDv(Vec<(Token, Token)>),
     ProofWrongExprEnd,
     ProofWrongTypeEnd,
     RepeatedLabel(Span, Span),
     SpuriousLabel(Span),
     SpuriousProof(Span),
-    StepEssenWrong,
-    StepEssenWrongType,
-    StepFloatWrongType,
+    StepEssenWrong(Box<StepInfo>),
+    StepEssenWrongType(Box<StepInfo>),
+    StepFloatWrongType(Box<StepInfo>),
     StepMissing(Token),
     StepOutOfRange,
     StepUsedAfterScope(Token),
     StepUsedBeforeDefinition(Token),
     SymbolDuplicatesLabel(TokenIndex, StatementAddress),
     SymbolRedeclared(TokenIndex, TokenAddress),
+    TypesettingBadCommand(Span),
+    TypesettingDuplicate(Span, StatementAddress, Span),
+    TypesettingUndefinedSymbol(Span),
+    TypesettingUnknownKeyword(Span),
     UnclosedBeforeEof,
     UnclosedBeforeInclude(StatementIndex),
     UnclosedComment(Span),
@@ -104,6 +170,23 @@ pub enum Diagnostic {
 }
 use self::Diagnostic::*;
 
+/// The proof step at which verification failed, and what went wrong there.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct StepInfo {
+    /// Number of the step, counting from 1 in the order in which steps are
+    /// applied.
+    pub step: usize,
+    /// Span of the step in the proof; null if the proof did not come from the
+    /// source.
+    pub span: Span,
+    /// Label of the assertion applied by the step.
+    pub label: Token,
+    /// What the assertion required, as a math string or a typecode.
+    pub expected: Token,
+    /// The math string which the step found on the stack.
+    pub actual: Token,
+}
+
 impl From<io::Error> for Diagnostic {
     fn from(err: io::Error) -> Diagnostic {
         IoError(format!("{}", err))
@@ -111,7 +194,7 @@ impl From<io::Error> for Diagnostic {
 }
 
 /// An indication of the severity of a notation.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Level {
     /// Notes indicate other statements relevant to an error which is primarily
     /// elsewhere.
@@ -135,6 +218,12 @@ pub struct Notation {
     /// message will be in English but, being not dynamically generated, it is
     /// suitable for remapping with a resource file.
     pub message: &'static str,
+    /// Name of the diagnostic which produced the notation, such as
+    /// `StepEssenWrong`; unlike the message, it is stable across releases.
+    pub code: String,
+    /// Label of the statement which the notation is attached to; empty if the
+    /// statement has no label, or for notations about a proof worksheet.
+    pub label: String,
     /// The location of the error (byte offset within the SourceInfo; _this is
     /// not the same as the byte offset in the file_).
     pub span: Span,
@@ -145,6 +234,204 @@ pub struct Notation {
     pub args: Vec<(&'static str, String)>,
 }
 
+impl Notation {
+    /// Formats the message with its arguments substituted.
+    pub fn formatted_message(&self) -> String {
+        let mut message = self.message.to_owned();
+        for &(id, ref value) in &self.args {
+            message = message.replace(&format!("{{{}}}", id), value);
+        }
+        message
+    }
+}
+
+/// Returns the name of a diagnostic's variant, for `Notation::code`.
+fn diagnostic_code(diag: &Diagnostic) -> &'static str {
+    match *diag {
+        BadCharacter(..) => "BadCharacter",
+        BadCommentEnd(..) => "BadCommentEnd",
+        BadFloating => "BadFloating",
+        BadLabel(..) => "BadLabel",
+        CommandExpectedKeyword(..) => "CommandExpectedKeyword",
+        CommandMissingSemicolon(..) => "CommandMissingSemicolon",
+        CommandUnclosedComment(..) => "CommandUnclosedComment",
+        CommandUnterminatedString(..) => "CommandUnterminatedString",
+        CommentMarkerNotStart(..) => "CommentMarkerNotStart",
+        ConstantNotTopLevel => "ConstantNotTopLevel",
+        DefinitionBadForm => "DefinitionBadForm",
+        DefinitionCircular(..) => "DefinitionCircular",
+        DefinitionDuplicate(..) => "DefinitionDuplicate",
+        DefinitionMissingDv(..) => "DefinitionMissingDv",
+        DefinitionUsedBefore(..) => "DefinitionUsedBefore",
+        DiscouragedUsage(..) => "DiscouragedUsage",
+        DisjointSingle => "DisjointSingle",
+        DjNotVariable(..) => "DjNotVariable",
+        DjRepeatedVariable(..) => "DjRepeatedVariable",
+        DuplicateLabel(..) => "DuplicateLabel",
+        DuplicateStatement(..) => "DuplicateStatement",
+        EmptyFilename => "EmptyFilename",
+        EmptyMathString => "EmptyMathString",
+        EssentialAtTopLevel => "EssentialAtTopLevel",
+        ExprNotConstantPrefix(..) => "ExprNotConstantPrefix",
+        FilenameDollar => "FilenameDollar",
+        FilenameSpaces => "FilenameSpaces",
+        FloatNotConstant(..) => "FloatNotConstant",
+        FloatNotVariable(..) => "FloatNotVariable",
+        FloatRedeclared(..) => "FloatRedeclared",
+        ForbiddenAxiom(..) => "ForbiddenAxiom",
+        GrammarAmbiguous => "GrammarAmbiguous",
+        GrammarAmbiguousAxioms(..) => "GrammarAmbiguousAxioms",
+        GrammarUnparseable(..) => "GrammarUnparseable",
+        IoError(..) => "IoError",
+        LintAxiomLabel => "LintAxiomLabel",
+        LintMissingModificationTag => "LintMissingModificationTag",
+        LintMissingUsageTag => "LintMissingUsageTag",
+        LintReservedLabel => "LintReservedLabel",
+        LintUnusedDvVariable(..) => "LintUnusedDvVariable",
+        LintUnusedFloat => "LintUnusedFloat",
+        MarkupLaterLabel(..) => "MarkupLaterLabel",
+        MarkupUnclosedMath(..) => "MarkupUnclosedMath",
+        MarkupUndefinedLabel(..) => "MarkupUndefinedLabel",
+        MarkupUndefinedSymbol(..) => "MarkupUndefinedSymbol",
+        MidStatementCommentMarker(..) => "MidStatementCommentMarker",
+        MissingLabel => "MissingLabel",
+        MissingProof(..) => "MissingProof",
+        MmpBadHeader(..) => "MmpBadHeader",
+        MmpBadStep(..) => "MmpBadStep",
+        MmpDuplicateStep(..) => "MmpDuplicateStep",
+        MmpHypCount(..) => "MmpHypCount",
+        MmpMissingQed(..) => "MmpMissingQed",
+        MmpNoSyntax(..) => "MmpNoSyntax",
+        MmpProofInvalid(..) => "MmpProofInvalid",
+        MmpStepMismatch(..) => "MmpStepMismatch",
+        MmpUnknownLabel(..) => "MmpUnknownLabel",
+        MmpUnknownStep(..) => "MmpUnknownStep",
+        MmpUnknownTheorem(..) => "MmpUnknownTheorem",
+        NestedComment(..) => "NestedComment",
+        NotActiveSymbol(..) => "NotActiveSymbol",
+        NotProvable => "NotProvable",
+        ProofDvViolation(..) => "ProofDvViolation",
+        ProofExcessEnd => "ProofExcessEnd",
+        ProofIncomplete => "ProofIncomplete",
+        ProofInvalidSave => "ProofInvalidSave",
+        ProofMalformedVarint => "ProofMalformedVarint",
+        ProofNoSteps => "ProofNoSteps",
+        ProofUnderflow(..) => "ProofUnderflow",
+        ProofUnterminatedRoster => "ProofUnterminatedRoster",
+        ProofUnusedDv(..) => "ProofUnusedDv",
+        ProofWrongExprEnd => "ProofWrongExprEnd",
+        ProofWrongTypeEnd => "ProofWrongTypeEnd",
+        RepeatedLabel(..) => "RepeatedLabel",
+        SpuriousLabel(..) => "SpuriousLabel",
+        SpuriousProof(..) => "SpuriousProof",
+        StepEssenWrong(..) => "StepEssenWrong",
+        StepEssenWrongType(..) => "StepEssenWrongType",
+        StepFloatWrongType(..) => "StepFloatWrongType",
+        StepMissing(..) => "StepMissing",
+        StepOutOfRange => "StepOutOfRange",
+        StepUsedAfterScope(..) => "StepUsedAfterScope",
+        StepUsedBeforeDefinition(..) => "StepUsedBeforeDefinition",
+        SymbolDuplicatesLabel(..) => "SymbolDuplicatesLabel",
+        SymbolRedeclared(..) => "SymbolRedeclared",
+        TypesettingBadCommand(..) => "TypesettingBadCommand",
+        TypesettingDuplicate(..) => "TypesettingDuplicate",
+        TypesettingUndefinedSymbol(..) => "TypesettingUndefinedSymbol",
+        TypesettingUnknownKeyword(..) => "TypesettingUnknownKeyword",
+        UnclosedBeforeEof => "UnclosedBeforeEof",
+        UnclosedBeforeInclude(..) => "UnclosedBeforeInclude",
+        UnclosedComment(..) => "UnclosedComment",
+        UnclosedInclude => "UnclosedInclude",
+        UnclosedMath => "UnclosedMath",
+        UnclosedProof => "UnclosedProof",
+        UnknownKeyword(..) => "UnknownKeyword",
+        UnmatchedCloseGroup => "UnmatchedCloseGroup",
+        VariableMissingFloat(..) => "VariableMissingFloat",
+        VariableRedeclaredAsConstant(..) => "VariableRedeclaredAsConstant",
+    }
+}
+
+/// Converts diagnostics from importing a proof worksheet, whose spans point
+/// into the worksheet text rather than into a database statement, to a
+/// notation list before output.
+pub fn to_worksheet_annotations(source: &Arc<SourceInfo>, diags: Vec<Diagnostic>) -> Vec<Notation> {
+    diags
+        .into_iter()
+        .map(|diag| {
+            let (message, span, args) = worksheet_message(&diag);
+            Notation {
+                source: source.clone(),
+                message,
+                code: diagnostic_code(&diag).to_owned(),
+                label: String::new(),
+                span,
+                level: Error,
+                args,
+            }
+        })
+        .collect()
+}
+
+/// Message, worksheet span and arguments for diagnostics generated by the
+/// worksheet importer.  Anything else is only expected to come from reading the
+/// worksheet file.
+fn worksheet_message(diag: &Diagnostic) -> (&'static str, Span, Vec<(&'static str, String)>) {
+    match *diag {
+        MmpBadHeader(span) => (
+            "A proof worksheet must start with $( <MM> <PROOF_ASST> THEOREM=label",
+            span,
+            vec![],
+        ),
+        MmpBadStep(span) => (
+            "A proof step must have the form step:hyps:ref followed by the formula",
+            span,
+            vec![],
+        ),
+        MmpDuplicateStep(span) => ("This step name was already used", span, vec![]),
+        MmpHypCount(span, count) => (
+            "The referenced assertion requires {count} logical hypotheses",
+            span,
+            vec![("count", format!("{}", count))],
+        ),
+        MmpMissingQed(span) => ("The proof worksheet has no qed step", span, vec![]),
+        MmpNoSyntax(span) => (
+            "No syntax proof could be found for the substitutions in this step",
+            span,
+            vec![],
+        ),
+        MmpProofInvalid(span, ref err) => (
+            "The proof built from this worksheet is invalid ({error})",
+            span,
+            vec![("error", format!("{:?}", err))],
+        ),
+        MmpStepMismatch(span) => (
+            "Step formula does not match the referenced assertion or hypothesis",
+            span,
+            vec![],
+        ),
+        MmpUnknownLabel(span) => (
+            "Step reference is not an assertion or a hypothesis of the theorem",
+            span,
+            vec![],
+        ),
+        MmpUnknownStep(span) => ("Hypothesis does not refer to a previous step", span, vec![]),
+        MmpUnknownTheorem(span) => (
+            "Worksheet theorem is not a $p statement of the database",
+            span,
+            vec![],
+        ),
+        IoError(ref err) => (
+            "Source file could not be read (error: {error})",
+            Span::null(),
+            vec![("error", err.clone())],
+        ),
+        ref other => (
+            "Worksheet could not be imported ({error})",
+            Span::null(),
+            vec![("error", format!("{:?}", other))],
+        ),
+    }
+}
+
 /// Converts a collection of raw diagnostics to a notation list before output.
 pub fn to_annotations(
     sset: &SegmentSet,
@@ -170,6 +457,7 @@ fn annotate_diagnostic(
         stmt: StatementRef<'a>,
         level: Level,
         s: &'static str,
+        code: String,
         args: Vec<(&'static str, String)>,
     }
 
@@ -180,6 +468,8 @@ fn annotate_diagnostic(
         info.notes.push(Notation {
             source: info.sset.source_info(info.stmt.segment().id).clone(),
             message: info.s,
+            code: info.code.clone(),
+            label: as_str(info.stmt.label()).to_owned(),
             span: span,
             level: info.level,
             args: mem::replace(&mut info.args, Vec::new()),
@@ -194,12 +484,28 @@ fn annotate_diagnostic(
         as_str(v).to_owned()
     }
 
+    fn step_args(info: &mut AnnInfo, step: &StepInfo) {
+        info.args.push(("step", d(step.step)));
+        info.args.push(("label", t(&step.label)));
+        info.args.push(("expected", t(&step.expected)));
+        info.args.push(("actual", t(&step.actual)));
+    }
+
+    fn var_pairs(pairs: &[(Token, Token)]) -> String {
+        pairs
+            .iter()
+            .map(|(var1, var2)| format!("{} {}", t(var1), t(var2)))
+            .collect::<Vec<_>>()
+            .join(" $. $d ")
+    }
+
     let mut info = AnnInfo {
         notes: notes,
         sset: sset,
         stmt: stmt,
         level: Error,
         s: "",
+        code: diagnostic_code(diag).to_owned(),
         args: Vec::new(),
     };
 
@@ -226,6 +532,22 @@ fn annotate_diagnostic(
             info.s = "Statement labels may contain only alphanumeric characters and - _ .";
             ann(&mut info, lbl);
         }
+        CommandExpectedKeyword(span) => {
+            info.s = "A $j or $t command must start with a keyword, not a quoted string or ;";
+            ann(&mut info, span);
+        }
+        CommandMissingSemicolon(span) => {
+            info.s = "A $j or $t command must be ended with ;";
+            ann(&mut info, span);
+        }
+        CommandUnclosedComment(span) => {
+            info.s = "Comment inside a $j or $t comment is not closed with */";
+            ann(&mut info, span);
+        }
+        CommandUnterminatedString(span) => {
+            info.s = "Quoted string in a $j or $t command is not closed";
+            ann(&mut info, span);
+        }
         CommentMarkerNotStart(marker) => {
             info.s = "This comment marker must be the first token in the comment to be effective";
             info.level = Warning;
@@ -235,6 +557,55 @@ fn annotate_diagnostic(
             info.s = "$c statements are not allowed in nested groups";
             ann(&mut info, stmt.span());
         }
+        DefinitionBadForm => {
+            info.s = "A definition should have the form A = B or ( ph <-> ps ), where the left \
+                     side applies a syntax axiom to distinct variables; declare other forms with \
+                     a $j definition command";
+            info.level = Warning;
+            ann(&mut info, stmt.span());
+        }
+        DefinitionCircular(saddr) => {
+            info.s = "The right side of a definition must not use the defined syntax, or syntax \
+                     which is defined later";
+            ann(&mut info, stmt.span());
+            info.stmt = sset.statement(saddr);
+            info.s = "Syntax axiom used here";
+            info.level = Note;
+            ann(&mut info, Span::null());
+        }
+        DefinitionDuplicate(saddr) => {
+            info.s = "This syntax already has a definition";
+            ann(&mut info, stmt.span());
+            info.stmt = sset.statement(saddr);
+            info.s = "Previous definition was here";
+            info.level = Note;
+            ann(&mut info, Span::null());
+        }
+        DefinitionMissingDv(ref dummy, ref other) => {
+            info.s = "Dummy variable {dummy} of a definition must be disjoint from {other}";
+            info.args.push(("dummy", t(dummy)));
+            info.args.push(("other", t(other)));
+            ann(&mut info, stmt.span());
+        }
+        DefinitionUsedBefore(saddr) => {
+            info.s = "Syntax defined here was already used by an earlier statement";
+            ann(&mut info, stmt.span());
+            info.stmt = sset.statement(saddr);
+            info.s = "Earlier use was here";
+            info.level = Note;
+            ann(&mut info, Span::null());
+        }
+        DiscouragedUsage(index, saddr) => {
+            info.s = "Proof uses {label}, which is tagged (New usage is discouraged.)";
+            info.args
+                .push(("label", as_str(sset.statement(saddr).label()).to_owned()));
+            info.level = Warning;
+            ann(&mut info, stmt.proof_span(index));
+            info.stmt = sset.statement(saddr);
+            info.s = "Discouraged statement is here";
+            info.level = Note;
+            ann(&mut info, Span::null());
+        }
         DisjointSingle => {
             info.s = "A $d statement which lists only one variable is meaningless";
             info.level = Warning;
@@ -259,6 +630,17 @@ fn annotate_diagnostic(
             info.level = Note;
             ann(&mut info, Span::null());
         }
+        DuplicateStatement(prevstmt) => {
+            info.s = "This statement is equivalent to {label}, apart from the names of variables";
+            info.args
+                .push(("label", as_str(sset.statement(prevstmt).label()).to_owned()));
+            info.level = Warning;
+            ann(&mut info, stmt.span());
+            info.stmt = sset.statement(prevstmt);
+            info.s = "Equivalent statement is here";
+            info.level = Note;
+            ann(&mut info, Span::null());
+        }
         EmptyFilename => {
             info.s = "Filename included by a $[ directive must not be empty";
             ann(&mut info, stmt.span());
@@ -301,11 +683,98 @@ fn annotate_diagnostic(
             info.level = Note;
             ann(&mut info, Span::null());
         }
+        ForbiddenAxiom(span, saddr) => {
+            info.s = "Proof depends on the forbidden axiom {axiom} through this step";
+            info.args
+                .push(("axiom", as_str(sset.statement(saddr).label()).to_owned()));
+            ann(&mut info, span);
+            info.stmt = sset.statement(saddr);
+            info.s = "Forbidden axiom is here";
+            info.level = Note;
+            ann(&mut info, Span::null());
+        }
+        GrammarAmbiguous => {
+            info.s = "Math string has more than one parse using the syntax axioms of the database";
+            ann(&mut info, stmt.span());
+        }
+        GrammarAmbiguousAxioms(prevstmt) => {
+            info.s = "Syntax axiom overlaps ambiguously with another syntax axiom";
+            ann(&mut info, stmt.span());
+            info.stmt = sset.statement(prevstmt);
+            info.s = "Overlapping syntax axiom is here";
+            info.level = Note;
+            ann(&mut info, Span::null());
+        }
+        GrammarUnparseable(index) => {
+            info.s = "Math string cannot be parsed using the syntax axioms of the database; \
+                     parsing failed at this token";
+            ann(&mut info, stmt.math_span(index));
+        }
         IoError(ref err) => {
             info.s = "Source file could not be read (error: {error})";
             info.args.push(("error", err.clone()));
             ann(&mut info, Span::null());
         }
+        LintAxiomLabel => {
+            info.s =
+                "The label of a $a statement with the provable typecode should start with ax- \
+                      or the definition prefix";
+            info.level = Warning;
+            ann(&mut info, stmt.span());
+        }
+        LintMissingModificationTag => {
+            info.s = "An OLD or ALT theorem should be tagged (Proof modification is discouraged.)";
+            info.level = Warning;
+            ann(&mut info, stmt.span());
+        }
+        LintMissingUsageTag => {
+            info.s = "An OLD or ALT theorem should be tagged (New usage is discouraged.)";
+            info.level = Warning;
+            ann(&mut info, stmt.span());
+        }
+        LintReservedLabel => {
+            info.s =
+                "Labels starting with ax- or the definition prefix should be used only for $a \
+                      statements with the provable typecode";
+            info.level = Warning;
+            ann(&mut info, stmt.span());
+        }
+        LintUnusedDvVariable(index) => {
+            info.s = "Variable {var} of this $d is not used by any assertion in its scope";
+            info.args
+                .push(("var", as_str(stmt.math_at(index).slice).to_owned()));
+            info.level = Warning;
+            ann(&mut info, stmt.math_span(index));
+        }
+        LintUnusedFloat => {
+            info.s = "The variable of this $f is not used by any statement in its scope";
+            info.level = Warning;
+            ann(&mut info, stmt.span());
+        }
+        MarkupLaterLabel(span, saddr) => {
+            info.s = "Comment refers to a statement which comes after it";
+            info.level = Warning;
+            ann(&mut info, span);
+            info.stmt = sset.statement(saddr);
+            info.s = "Statement is here";
+            info.level = Note;
+            ann(&mut info, Span::null());
+        }
+        MarkupUnclosedMath(span) => {
+            info.s = "Math in a comment must be closed with a backtick";
+            info.level = Warning;
+            ann(&mut info, span);
+        }
+        MarkupUndefinedLabel(span) => {
+            info.s = "Comment refers to a label which is not defined";
+            info.level = Warning;
+            ann(&mut info, span);
+        }
+        MarkupUndefinedSymbol(span) => {
+            info.s = "Math in a comment uses a symbol which is not declared";
+            info.level = Warning;
+            ann(&mut info, span);
+        }
         MidStatementCommentMarker(marker) => {
             info.s = "Marked comments are only effective between statements, not inside them";
             info.level = Warning;
@@ -320,6 +789,16 @@ fn annotate_diagnostic(
                      if you do not have a proof yet";
             ann(&mut info, math_end);
         }
+        MmpBadHeader(_) | MmpBadStep(_) | MmpDuplicateStep(_) | MmpHypCount(..)
+        | MmpMissingQed(_) | MmpNoSyntax(_) | MmpProofInvalid(..) | MmpStepMismatch(_)
+        | MmpUnknownLabel(_) | MmpUnknownStep(_) | MmpUnknownTheorem(_) => {
+            // worksheet spans do not point into the database, so when attached
+            // to a statement these cover the whole statement
+            let (message, _, args) = worksheet_message(diag);
+            info.s = message;
+            info.args = args;
+            ann(&mut info, Span::null());
+        }
         NestedComment(tok, opener) => {
             info.s = "Nested comments are not supported - comment will end at the first $)";
             info.level = Warning;
@@ -332,8 +811,13 @@ fn annotate_diagnostic(
             info.s = "Token used here must be active in the current scope";
             ann(&mut info, stmt.math_span(index));
         }
-        ProofDvViolation => {
-            info.s = "Disjoint variable constraint violated";
+        NotProvable => {
+            info.s = "Only $p statements have proofs which can be rewritten";
+            ann(&mut info, Span::null());
+        }
+        ProofDvViolation(ref pairs) => {
+            info.s = "Disjoint variable constraint violated; the proof needs $d {pairs}";
+            info.args.push(("pairs", var_pairs(pairs)));
             ann(&mut info, stmt.span());
         }
         ProofExcessEnd => {
@@ -357,14 +841,23 @@ fn annotate_diagnostic(
             info.s = "Proof must have at least one step (use ? if deliberately incomplete)";
             ann(&mut info, stmt.span());
         }
-        ProofUnderflow => {
-            info.s = "Too few statements on stack to satisfy step's mandatory hypotheses";
-            ann(&mut info, stmt.span());
+        ProofUnderflow(ref step) => {
+            info.s = "Too few statements on stack to satisfy the mandatory hypotheses of {label} \
+                      at step {step}";
+            info.args.push(("step", d(step.step)));
+            info.args.push(("label", t(&step.label)));
+            ann(&mut info, step.span);
         }
         ProofUnterminatedRoster => {
             info.s = "List of referenced assertions in a compressed proof must be terminated by )";
             ann(&mut info, stmt.span());
         }
+        ProofUnusedDv(ref pairs) => {
+            info.s = "Disjoint variable constraints are not needed by the proof: $d {pairs}";
+            info.args.push(("pairs", var_pairs(pairs)));
+            info.level = Warning;
+            ann(&mut info, stmt.span());
+        }
         ProofWrongExprEnd => {
             info.s = "Final step statement does not match assertion";
             ann(&mut info, stmt.span());
@@ -388,17 +881,23 @@ fn annotate_diagnostic(
             info.s = "Proofs are only allowed on $p assertions";
             ann(&mut info, math_end);
         }
-        StepEssenWrong => {
-            info.s = "Step used for $e hypothesis does not match statement";
-            ann(&mut info, stmt.span());
+        StepEssenWrong(ref step) => {
+            info.s = "Step used for $e hypothesis of {label} at step {step} does not match \
+                      statement (expected {expected}, found {actual})";
+            step_args(&mut info, step);
+            ann(&mut info, step.span);
         }
-        StepEssenWrongType => {
-            info.s = "Step used for $e hypothesis does not match typecode";
-            ann(&mut info, stmt.span());
+        StepEssenWrongType(ref step) => {
+            info.s = "Step used for $e hypothesis of {label} at step {step} does not match \
+                      typecode (expected {expected}, found {actual})";
+            step_args(&mut info, step);
+            ann(&mut info, step.span);
         }
-        StepFloatWrongType => {
-            info.s = "Step used for $f hypothesis does not match typecode";
-            ann(&mut info, stmt.span());
+        StepFloatWrongType(ref step) => {
+            info.s = "Step used for $f hypothesis of {label} at step {step} does not match \
+                      typecode (expected {expected}, found {actual})";
+            step_args(&mut info, step);
+            ann(&mut info, step.span);
         }
         StepMissing(ref tok) => {
             info.s = "Step {step} referenced by proof does not correspond to a $p statement (or \
@@ -439,6 +938,29 @@ fn annotate_diagnostic(
             let sp = info.stmt.math_span(taddr.token_index);
             ann(&mut info, sp);
         }
+        TypesettingBadCommand(span) => {
+            info.s = "A typesetting command must have the form htmldef \"symbol\" as \"value\"; \
+                     or htmltitle \"value\";, where values may be joined with +";
+            ann(&mut info, span);
+        }
+        TypesettingDuplicate(span, prevstmt, prevspan) => {
+            info.s = "This typesetting definition was already given";
+            ann(&mut info, span);
+            info.stmt = sset.statement(prevstmt);
+            info.s = "Previous definition was here";
+            info.level = Note;
+            ann(&mut info, prevspan);
+        }
+        TypesettingUndefinedSymbol(span) => {
+            info.s = "Typesetting definition is for a symbol which is not declared";
+            info.level = Warning;
+            ann(&mut info, span);
+        }
+        TypesettingUnknownKeyword(span) => {
+            info.s = "Unknown typesetting command";
+            info.level = Warning;
+            ann(&mut info, span);
+        }
         UnclosedBeforeEof => {
             info.s = "${ group must be closed with a $} before end of file";
             ann(&mut info, stmt.span());
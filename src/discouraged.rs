@@ -0,0 +1,110 @@
+//! Lint for the discouraged tags of set.mm, as checked by metamath.exe's
+//! `verify markup`.
+//!
+//! A statement whose comment contains `(New usage is discouraged.)` should
+//! only be cited by proofs of theorems which carry the same tag, so a warning
+//! is given at the first citation from any other theorem.  The
+//! `obsolete-tags` lint checks that theorems whose labels end in `OLD` or
+//! `ALT` carry the tag.
+//!
+//! Tags are matched with any run of white space treated as a single space, so
+//! that a tag may be split across lines.  Segments are checked in parallel,
+//! and the tags of the statements cited by a segment are read once each.
+
+use diag::Diagnostic;
+use nameck::Nameset;
+use parser::SegmentId;
+use parser::StatementAddress;
+use parser::StatementRef;
+use parser::StatementType;
+use segment_set::SegmentSet;
+use std::sync::Arc;
+use util::new_map;
+use util::HashMap;
+use xref;
+
+/// Tag for statements which should not be used by new proofs.
+pub const NEW_USAGE_TAG: &[u8] = b"(New usage is discouraged.)";
+
+/// Analysis pass result for the discouraged tag lint.
+#[derive(Default, Clone)]
+pub struct DiscouragedResult {
+    diagnostics: Vec<(StatementAddress, Diagnostic)>,
+}
+
+impl DiscouragedResult {
+    /// Report uses of discouraged statements.
+    pub fn diagnostics(&self) -> Vec<(StatementAddress, Diagnostic)> {
+        self.diagnostics.clone()
+    }
+}
+
+/// Returns true if the comment before a statement contains a tag.
+pub fn has_tag(stmt: StatementRef, tag: &[u8]) -> bool {
+    let comment = match stmt.associated_comment() {
+        Some(comment) => comment,
+        None => return false,
+    };
+    let mut text = Vec::new();
+    for &chr in comment.span().as_ref(&comment.segment().segment.buffer) {
+        let chr = if chr.is_ascii_whitespace() { b' ' } else { chr };
+        if chr != b' ' || text.last() != Some(&b' ') {
+            text.push(chr);
+        }
+    }
+    text.windows(tag.len()).any(|window| window == tag)
+}
+
+/// Checks the theorems of a segment.
+fn discouraged_segment(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    sid: SegmentId,
+) -> Vec<(StatementAddress, Diagnostic)> {
+    let mut out = Vec::new();
+    // whether each cited statement is tagged
+    let mut tagged: HashMap<StatementAddress, bool> = new_map();
+    for stmt in sset.segment(sid) {
+        if stmt.statement_type() != StatementType::Provable || has_tag(stmt, NEW_USAGE_TAG) {
+            continue;
+        }
+        for (ix, label) in xref::cited_steps(stmt) {
+            if let Some(lookup) = nset.lookup_label(label) {
+                let discouraged = *tagged.entry(lookup.address).or_insert_with(|| {
+                    let cited = sset.statement(lookup.address);
+                    let stype = cited.statement_type();
+                    (stype == StatementType::Axiom || stype == StatementType::Provable)
+                        && has_tag(cited, NEW_USAGE_TAG)
+                });
+                if discouraged {
+                    out.push((
+                        stmt.address(),
+                        Diagnostic::DiscouragedUsage(ix, lookup.address),
+                    ));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Checks the discouraged tags of a database.
+pub fn discouraged_check(
+    result: &mut DiscouragedResult,
+    segments: &Arc<SegmentSet>,
+    nset: &Arc<Nameset>,
+) {
+    let mut dq = Vec::new();
+    for sref in segments.segments() {
+        let segments2 = segments.clone();
+        let nset = nset.clone();
+        let id = sref.id;
+        dq.push(segments.exec.exec(sref.bytes(), move || {
+            discouraged_segment(&segments2, &nset, id)
+        }));
+    }
+    result.diagnostics.clear();
+    for promise in dq {
+        result.diagnostics.extend(promise.wait());
+    }
+}
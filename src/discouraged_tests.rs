@@ -0,0 +1,41 @@
+use database::DbOptions;
+use diag::Diagnostic;
+use test_util::mkdb;
+
+const DB: &str = "$c wff |- ( -> ) $.
+    $v ph ps $.
+    wph $f wff ph $.
+    wps $f wff ps $.
+    wi $a wff ( ph -> ps ) $.
+    ax-1 $a |- ( ph -> ( ps -> ph ) ) $.
+    $( An old theorem.  (New usage is
+       discouraged.) $)
+    old1 $p |- ( ph -> ( ps -> ph ) ) $= wph wps ax-1 $.
+    use1 $p |- ( ph -> ( ps -> ph ) ) $= ( old1 ) ABC $.
+    $( Also discouraged.  (New usage is discouraged.) $)
+    use2 $p |- ( ph -> ( ps -> ph ) ) $= wph wps old1 $.
+    use3 $p |- ( ph -> ( ps -> ph ) ) $= wph wps old1 $.";
+
+#[test]
+fn test_discouraged() {
+    let mut db = mkdb(DB, DbOptions::default());
+    let old1 = db.statement("old1").unwrap().address();
+    let diags = db.discouraged_result().diagnostics();
+    let sset = db.parse_result();
+    let diags = diags
+        .into_iter()
+        .map(|(addr, diag)| {
+            let label = String::from_utf8_lossy(sset.statement(addr).label()).into_owned();
+            (label, diag)
+        })
+        .collect::<Vec<_>>();
+
+    // compressed proofs are reported at the roster label
+    assert_eq!(
+        diags,
+        vec![
+            ("use1".to_owned(), Diagnostic::DiscouragedUsage(1, old1)),
+            ("use3".to_owned(), Diagnostic::DiscouragedUsage(2, old1)),
+        ]
+    );
+}
@@ -0,0 +1,240 @@
+//! Detection of assertions which are stated more than once.
+//!
+//! Two `$a` or `$p` statements are equivalent if their frames are the same up
+//! to a renaming of variables: the same essential hypotheses in the same order,
+//! the same conclusion, variables of the same typecodes, and the same mandatory
+//! `$d` constraints.  Each frame is normalised by numbering its variables in
+//! order of first occurrence in the hypotheses and conclusion, using the
+//! compiled expressions of `scopeck`, and the normal forms are hashed to find
+//! the groups of equivalent statements.  Segments are normalised in parallel,
+//! and the normal forms of a segment are reused while the segment and the
+//! frames it used are unchanged; only the grouping is done again for the whole
+//! database.
+
+use diag::Diagnostic;
+use nameck::Atom;
+use nameck::Nameset;
+use parser;
+use parser::Segment;
+use parser::SegmentId;
+use parser::StatementAddress;
+use parser::StatementType;
+use scopeck::Frame;
+use scopeck::Hyp;
+use scopeck::ScopeReader;
+use scopeck::ScopeResult;
+use scopeck::ScopeUsage;
+use scopeck::VarIndex;
+use scopeck::VerifyExpr;
+use segment_set::SegmentSet;
+use std::mem;
+use std::sync::Arc;
+use util::new_map;
+use util::ptr_eq;
+use util::HashMap;
+
+/// An expression with renamed variables.
+#[derive(PartialEq, Eq, Hash)]
+struct NormalExpr {
+    typecode: Atom,
+    /// Constant symbols, in the delimited form of the constant pool, each
+    /// followed by a variable.
+    fragments: Vec<(Vec<u8>, usize)>,
+    /// Constant symbols after the last variable.
+    rump: Vec<u8>,
+}
+
+/// A frame with renamed variables.
+#[derive(PartialEq, Eq, Hash)]
+struct NormalForm {
+    /// Typecode of each variable, in order of first occurrence.
+    var_types: Vec<Atom>,
+    /// The essential hypotheses followed by the conclusion.
+    exprs: Vec<NormalExpr>,
+    /// Pairs of variables in mandatory `$d` constraints, in order.
+    dv: Vec<(usize, usize)>,
+}
+
+/// Assigns new numbers to the variables of a frame as they are encountered.
+struct Renamer<'a> {
+    frame: &'a Frame,
+    /// The new number of each variable of the frame.
+    names: Vec<Option<usize>>,
+    var_types: Vec<Atom>,
+}
+
+impl<'a> Renamer<'a> {
+    fn rename(&mut self, var: VarIndex) -> usize {
+        if let Some(name) = self.names[var] {
+            return name;
+        }
+        let typecode = self
+            .frame
+            .hypotheses
+            .iter()
+            .filter_map(|hyp| match *hyp {
+                Hyp::Floating(_, index, typecode) if index == var => Some(typecode),
+                _ => None,
+            })
+            .next()
+            .unwrap_or_default();
+        self.var_types.push(typecode);
+        self.names[var] = Some(self.var_types.len() - 1);
+        self.var_types.len() - 1
+    }
+
+    fn expr(&mut self, expr: &VerifyExpr) -> NormalExpr {
+        let pool = &self.frame.const_pool;
+        NormalExpr {
+            typecode: expr.typecode,
+            fragments: expr
+                .tail
+                .iter()
+                .map(|frag| (pool[frag.prefix.clone()].to_vec(), self.rename(frag.var)))
+                .collect(),
+            rump: pool[expr.rump.clone()].to_vec(),
+        }
+    }
+}
+
+/// Calculates the normal form of a frame.
+fn normalize(frame: &Frame) -> NormalForm {
+    let mut renamer = Renamer {
+        frame,
+        names: vec![None; frame.var_list.len()],
+        var_types: Vec::new(),
+    };
+    let mut exprs = Vec::new();
+    for hyp in frame.hypotheses.iter() {
+        if let Hyp::Essential(_, ref expr) = *hyp {
+            exprs.push(renamer.expr(expr));
+        }
+    }
+    exprs.push(renamer.expr(&frame.target));
+
+    let mut dv = frame
+        .mandatory_dv
+        .iter()
+        .map(|&(left, right)| {
+            let (left, right) = (renamer.rename(left), renamer.rename(right));
+            (left.min(right), left.max(right))
+        })
+        .collect::<Vec<_>>();
+    dv.sort_unstable();
+    dv.dedup();
+
+    NormalForm {
+        var_types: renamer.var_types,
+        exprs,
+        dv,
+    }
+}
+
+/// Stored normal forms of the assertions of a segment.
+struct DuplicateSegment {
+    source: Arc<Segment>,
+    scope_usage: ScopeUsage,
+    forms: Vec<(StatementAddress, NormalForm)>,
+}
+
+/// Calculates the normal forms of the assertions of a segment.
+fn normalize_segment(sset: &SegmentSet, scopes: &ScopeResult, sid: SegmentId) -> DuplicateSegment {
+    let sref = sset.segment(sid);
+    let mut scoper = ScopeReader::new(scopes);
+    let mut forms = Vec::new();
+    for stmt in sref {
+        let stype = stmt.statement_type();
+        if stype != StatementType::Axiom && stype != StatementType::Provable {
+            continue;
+        }
+        if let Some(frame) = scoper.get(stmt.label()) {
+            // a duplicated label finds the frame of the other statement
+            if frame.valid.start == stmt.address() {
+                forms.push((stmt.address(), normalize(frame)));
+            }
+        }
+    }
+    DuplicateSegment {
+        source: sref.segment.clone(),
+        scope_usage: scoper.into_usage(),
+        forms,
+    }
+}
+
+/// Analysis pass result for the duplicate checker.
+#[derive(Default, Clone)]
+pub struct DuplicateResult {
+    segments: HashMap<SegmentId, Arc<DuplicateSegment>>,
+    groups: Vec<Vec<StatementAddress>>,
+}
+
+impl DuplicateResult {
+    /// Returns each group of two or more equivalent statements, in database
+    /// order of the first statement of each group.
+    pub fn groups(&self) -> &[Vec<StatementAddress>] {
+        &self.groups
+    }
+
+    /// Report every statement which is equivalent to an earlier one.
+    pub fn diagnostics(&self) -> Vec<(StatementAddress, Diagnostic)> {
+        let mut out = Vec::new();
+        for group in &self.groups {
+            for &addr in &group[1..] {
+                out.push((addr, Diagnostic::DuplicateStatement(group[0])));
+            }
+        }
+        out
+    }
+}
+
+/// Finds the groups of equivalent assertions in a database.
+pub fn find_duplicates(
+    result: &mut DuplicateResult,
+    segments: &Arc<SegmentSet>,
+    nset: &Arc<Nameset>,
+    scopes: &Arc<ScopeResult>,
+) {
+    let old = mem::replace(&mut result.segments, new_map());
+    let mut dq = Vec::new();
+    for sref in segments.segments() {
+        let segments2 = segments.clone();
+        let nset = nset.clone();
+        let scopes = scopes.clone();
+        let id = sref.id;
+        let old_res_o = old.get(&id).cloned();
+        dq.push(segments.exec.exec(sref.bytes(), move || {
+            let sref = segments2.segment(id);
+            if let Some(old_res) = old_res_o {
+                if old_res.scope_usage.valid(&nset, &scopes)
+                    && ptr_eq::<Segment>(&old_res.source, &sref)
+                {
+                    return (id, old_res);
+                }
+            }
+            if segments2.options.trace_recalc {
+                println!("dupck({:?})", parser::guess_buffer_name(&sref.buffer));
+            }
+            (id, Arc::new(normalize_segment(&segments2, &scopes, id)))
+        }));
+    }
+
+    let order = dq
+        .into_iter()
+        .map(|promise| promise.wait())
+        .collect::<Vec<_>>();
+    let mut index: HashMap<&NormalForm, usize> = new_map();
+    let mut groups: Vec<Vec<StatementAddress>> = Vec::new();
+    for (_, dsr) in &order {
+        for (addr, form) in &dsr.forms {
+            let next = groups.len();
+            let group = *index.entry(form).or_insert(next);
+            if group == next {
+                groups.push(Vec::new());
+            }
+            groups[group].push(*addr);
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    result.groups = groups;
+    result.segments = order.into_iter().collect();
+}
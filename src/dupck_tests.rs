@@ -0,0 +1,91 @@
+use database::Database;
+use database::DbOptions;
+use test_util::mkdb;
+use test_util::reparse;
+
+const DB: &str = "$c wff |- ( -> ) $.
+    $v ph ps ch $.
+    wph $f wff ph $.
+    wps $f wff ps $.
+    wch $f wff ch $.
+    wi $a wff ( ph -> ps ) $.
+    ax-1 $a |- ( ph -> ( ps -> ph ) ) $.
+    th1 $p |- ( ch -> ( ph -> ch ) ) $= wch wph ax-1 $.
+    th2 $p |- ( ps -> ( ph -> ps ) ) $= wps wph ax-1 $.
+    th3 $p |- ( ph -> ( ph -> ph ) ) $= wph wph ax-1 $.
+    ${
+      $d ph ps $.
+      th4 $p |- ( ph -> ( ps -> ph ) ) $= wph wps ax-1 $.
+    $}
+    ${
+      mp.1 $e |- ph $.
+      mp.2 $e |- ( ph -> ps ) $.
+      ax-mp $a |- ps $.
+    $}
+    ${
+      mp2.1 $e |- ps $.
+      mp2.2 $e |- ( ps -> ch ) $.
+      mp2 $p |- ch $= wps wch mp2.1 mp2.2 ax-mp $.
+    $}
+    ${
+      mp3.1 $e |- ( ph -> ps ) $.
+      mp3.2 $e |- ph $.
+      mp3 $p |- ps $= wph wps mp3.2 mp3.1 ax-mp $.
+    $}";
+
+fn groups(db: &mut Database) -> Vec<Vec<String>> {
+    let groups = db.duplicate_result().clone();
+    let sset = db.parse_result();
+    groups
+        .groups()
+        .iter()
+        .map(|group| {
+            group
+                .iter()
+                .map(|&addr| String::from_utf8_lossy(sset.statement(addr).label()).into_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>()
+}
+
+#[test]
+fn test_duplicates() {
+    let mut db = mkdb(DB, DbOptions::default());
+
+    // th3 identifies variables, th4 has a $d and mp3 has its hypotheses in a
+    // different order
+    assert_eq!(
+        groups(&mut db),
+        vec![vec!["ax-1", "th1", "th2"], vec!["ax-mp", "mp2"]]
+    );
+}
+
+#[test]
+fn test_duplicates_incremental() {
+    let mut db = mkdb(
+        DB,
+        DbOptions {
+            incremental: true,
+            ..DbOptions::default()
+        },
+    );
+    assert_eq!(
+        groups(&mut db),
+        vec![vec!["ax-1", "th1", "th2"], vec!["ax-mp", "mp2"]]
+    );
+    reparse(
+        &mut db,
+        &DB.replace(
+            "th2 $p |- ( ps -> ( ph -> ps ) ) $= wps wph ax-1 $.",
+            "th2 $p |- ( ps -> ( ps -> ps ) ) $= wps wps ax-1 $.",
+        ),
+    );
+    assert_eq!(
+        groups(&mut db),
+        vec![
+            vec!["ax-1", "th1"],
+            vec!["th2", "th3"],
+            vec!["ax-mp", "mp2"]
+        ]
+    );
+}
@@ -25,6 +25,8 @@ pub enum ExportError {
     Io(io::Error),
     /// Proof verification error
     Verify(Diagnostic),
+    /// A label which does not name a statement of the database
+    UnknownLabel(String),
 }
 
 impl From<io::Error> for ExportError {
@@ -43,6 +45,11 @@ impl fmt::Display for ExportError {
         match *self {
             ExportError::Io(ref err) => write!(f, "IO error: {}", err),
             ExportError::Verify(ref err) => write!(f, "{:?}", err),
+            ExportError::UnknownLabel(ref label) => write!(
+                f,
+                "Label {} did not correspond to an existing statement",
+                label
+            ),
         }
     }
 }
@@ -51,7 +58,7 @@ impl error::Error for ExportError {
     fn cause(&self) -> Option<&dyn error::Error> {
         match *self {
             ExportError::Io(ref err) => Some(err),
-            ExportError::Verify(_) => None,
+            ExportError::Verify(_) | ExportError::UnknownLabel(_) => None,
         }
     }
 }
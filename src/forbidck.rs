@@ -0,0 +1,165 @@
+//! Checks that proofs do not depend on forbidden axioms.
+//!
+//! An axiom is forbidden if it is named by the user, named by a `$j` command
+//! such as `forbid_axiom 'ax-ac' 'ax-reg';`, or if its description comment
+//! contains the marker `(New usage is discouraged.)`.  A theorem may be
+//! exempted from some forbidden axioms with a command such as `allow_axiom
+//! 'ac2' 'ax-ac';`; theorems using it are not exempted, and must be allowed
+//! the axiom in turn.  Theorems which carry the marker themselves may use the
+//! axioms which are forbidden only by the marker.
+//!
+//! Dependencies are found transitively, following essential steps only, with
+//! the memoised computation of the `deps` module.  A theorem depending on a
+//! forbidden axiom is reported once per axiom, at the first step of its proof
+//! which brings in the axiom.
+
+use commands::CommandResult;
+use deps::DependencyCache;
+use diag::Diagnostic;
+use discouraged;
+use nameck::Nameset;
+use parser::Comparer;
+use parser::Span;
+use parser::StatementAddress;
+use parser::StatementRef;
+use parser::StatementType;
+use scopeck::ScopeResult;
+use segment_set::SegmentSet;
+use util::new_map;
+use util::HashMap;
+use xref;
+
+/// `$j` keyword of commands listing forbidden axioms.
+const FORBID_KEYWORD: &[u8] = b"forbid_axiom";
+
+/// `$j` keyword of commands exempting a theorem from forbidden axioms.
+const ALLOW_KEYWORD: &[u8] = b"allow_axiom";
+
+/// Analysis pass result for the forbidden axiom checker.
+#[derive(Default, Clone)]
+pub struct ForbiddenResult {
+    diagnostics: Vec<(StatementAddress, Diagnostic)>,
+}
+
+impl ForbiddenResult {
+    /// Report theorems which depend on forbidden axioms.
+    pub fn diagnostics(&self) -> Vec<(StatementAddress, Diagnostic)> {
+        self.diagnostics.clone()
+    }
+}
+
+/// Finds the first step of a proof which depends on an axiom, or a null span
+/// if the proof is too malformed to tell.
+fn first_offending_step(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scope: &ScopeResult,
+    deps: &mut DependencyCache,
+    stmt: StatementRef,
+    axiom: StatementAddress,
+) -> Span {
+    let typecode = stmt.math_at(0).slice;
+    let hyps = scope
+        .get(stmt.label())
+        .map_or(0, |frame| frame.hypotheses.len());
+    for (span, label) in xref::cited_spans(stmt, hyps) {
+        let step = match nset.lookup_label(label) {
+            Some(lookup) => sset.statement(lookup.address),
+            None => continue,
+        };
+        if step.math_len() > 0
+            && step.math_at(0).slice == typecode
+            && deps.depends_on(sset, nset, step.address(), axiom)
+        {
+            return span;
+        }
+    }
+    Span::null()
+}
+
+/// Checks every theorem of a database against the forbidden axioms.
+///
+/// `extra` lists forbidden axioms in addition to those marked in the
+/// database; labels which are not axioms are ignored.
+pub fn forbidden_check(
+    result: &mut ForbiddenResult,
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scope: &ScopeResult,
+    commands: &CommandResult,
+    deps: &mut DependencyCache,
+    extra: &[String],
+) {
+    let is_axiom =
+        |addr: &StatementAddress| sset.statement(*addr).statement_type() == StatementType::Axiom;
+    let lookup = |label: &[u8]| nset.lookup_label(label).map(|lookup| lookup.address);
+
+    let mut forbidden = extra
+        .iter()
+        .filter_map(|label| lookup(label.as_bytes()))
+        .collect::<Vec<_>>();
+    for entry in commands.get(FORBID_KEYWORD) {
+        forbidden.extend(entry.args(sset).into_iter().filter_map(lookup));
+    }
+    forbidden.retain(is_axiom);
+    // axioms which are forbidden only by their tag
+    let mut by_tag = Vec::new();
+    for sref in sset.segments() {
+        for stmt in sref {
+            if stmt.statement_type() == StatementType::Axiom
+                && !forbidden.contains(&stmt.address())
+                && discouraged::has_tag(stmt, discouraged::NEW_USAGE_TAG)
+            {
+                by_tag.push(stmt.address());
+            }
+        }
+    }
+    forbidden.extend(&by_tag);
+    forbidden.sort_by(|a, b| sset.order.cmp(a, b));
+    forbidden.dedup();
+
+    let mut allowed: HashMap<StatementAddress, Vec<StatementAddress>> = new_map();
+    for entry in commands.get(ALLOW_KEYWORD) {
+        let args = entry.args(sset);
+        if let Some(thm) = args.first().and_then(|&label| lookup(label)) {
+            allowed
+                .entry(thm)
+                .or_default()
+                .extend(args[1..].iter().filter_map(|&label| lookup(label)));
+        }
+    }
+
+    result.diagnostics.clear();
+    if forbidden.is_empty() {
+        return;
+    }
+    for sref in sset.segments() {
+        for stmt in sref {
+            if stmt.statement_type() != StatementType::Provable || stmt.math_len() == 0 {
+                continue;
+            }
+            let address = stmt.address();
+            let mut tagged = None;
+            for &axiom in &forbidden {
+                if allowed
+                    .get(&address)
+                    .is_some_and(|axioms| axioms.contains(&axiom))
+                    || !deps.depends_on(sset, nset, address, axiom)
+                {
+                    continue;
+                }
+                if by_tag.contains(&axiom)
+                    && *tagged.get_or_insert_with(|| {
+                        discouraged::has_tag(stmt, discouraged::NEW_USAGE_TAG)
+                    })
+                {
+                    continue;
+                }
+                let step = first_offending_step(sset, nset, scope, deps, stmt, axiom);
+                result
+                    .diagnostics
+                    .push((address, Diagnostic::ForbiddenAxiom(step, axiom)));
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use database::DbOptions;
+use diag::Diagnostic;
+use test_util::mkdb;
+
+const DB: &str = "$c wff |- ( -> ) $.
+    $v ph ps $.
+    wph $f wff ph $.
+    wps $f wff ps $.
+    wi $a wff ( ph -> ps ) $.
+    $( $j forbid_axiom 'ax-ac'; allow_axiom 'ac1' 'ax-ac'; $)
+    ax-ac $a |- ph $.
+    $( An old axiom.  (New usage is discouraged.) $)
+    ax-reg $a |- ( ph -> ph ) $.
+    ax-ok $a |- ( ph -> ( ps -> ph ) ) $.
+    ${
+      mp.1 $e |- ph $.
+      mp.2 $e |- ( ph -> ps ) $.
+      ax-mp $a |- ps $.
+    $}
+    ac1 $p |- ph $= wph ax-ac $.
+    ac2 $p |- ps $= wph wps wph ac1 wph wps wph wi wi ax-ok wph wps ax-ok ax-mp ax-mp $.
+    reg1 $p |- ( ph -> ph ) $= ( ax-reg ) AB $.
+    $( Uses an old axiom.  (New usage is discouraged.) $)
+    reg2 $p |- ( ph -> ph ) $= wph ax-reg $.
+    reg3 $p |- ( ph -> ph ) $=
+      ( wi wi wi wi wi wi wi wi wi wi wi wi wi wi wi wi wi wi wi ax-reg ) AU
+      A $.
+    ok1 $p |- ( ph -> ( ps -> ph ) ) $= wph wps ax-ok $.";
+
+/// Returns the theorem, the text of the offending step and the axiom of each
+/// diagnostic.
+fn check(options: DbOptions) -> Vec<(String, String, String)> {
+    let mut db = mkdb(DB, options);
+    let diags = db.forbidden_result().diagnostics();
+    let sset = db.parse_result();
+    let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+    diags
+        .into_iter()
+        .map(|(addr, diag)| match diag {
+            Diagnostic::ForbiddenAxiom(span, axiom) => {
+                let stmt = sset.statement(addr);
+                (
+                    text(stmt.label()),
+                    text(span.as_ref(&stmt.segment().buffer)),
+                    text(sset.statement(axiom).label()),
+                )
+            }
+            diag => panic!("unexpected diagnostic {:?}", diag),
+        })
+        .collect()
+}
+
+fn diag(thm: &str, step: &str, axiom: &str) -> (String, String, String) {
+    (thm.to_owned(), step.to_owned(), axiom.to_owned())
+}
+
+#[test]
+fn test_forbidden_axioms() {
+    // ac1 is allowed ax-ac, but ac2 must be allowed it as well; compressed
+    // proofs are reported at the letters of the step, even when they are
+    // split by white space, and reg2 is tagged so it may use ax-reg
+    assert_eq!(
+        check(DbOptions::default()),
+        vec![
+            diag("ac2", "ac1", "ax-ac"),
+            diag("reg1", "B", "ax-reg"),
+            diag("reg3", "U\n      A", "ax-reg"),
+        ]
+    );
+
+    let options = DbOptions {
+        forbidden_axioms: vec!["ax-ok".to_owned(), "wph".to_owned(), "ax-reg".to_owned()],
+        ..DbOptions::default()
+    };
+    assert_eq!(
+        check(options),
+        vec![
+            diag("ac2", "ac1", "ax-ac"),
+            diag("ac2", "ax-ok", "ax-ok"),
+            diag("reg1", "B", "ax-reg"),
+            diag("reg2", "ax-reg", "ax-reg"),
+            diag("reg3", "U\n      A", "ax-reg"),
+            diag("ok1", "ax-ok", "ax-ok"),
+        ]
+    );
+}
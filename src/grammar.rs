@@ -0,0 +1,919 @@
+//! Parsing of math strings into syntax trees, using the grammar defined by the
+//! syntax axioms of the database.
+//!
+//! Every `$a` statement whose typecode is not the provable typecode is treated
+//! as a production of a context-free grammar: its typecode is the
+//! nonterminal being defined, and its math string is a sequence of constants
+//! and variables, each variable standing for the nonterminal given by the
+//! typecode of its `$f`.  The `$f` statements themselves are the productions
+//! which turn a single variable into a nonterminal.
+//!
+//! Math strings are parsed by a packrat (memoizing top-down) parser over
+//! atoms.  The parser collects every derivation of each nonterminal from each
+//! position, merging derivations which end at the same place, so that it can
+//! report ambiguity as well as failure.  Left-recursive productions are handled
+//! by seed growing, although the syntax axioms of set.mm have none.
+//!
+//! The resulting syntax trees are stored in a `ProofTreeArray`, whose nodes are
+//! the addresses of the syntax axioms and `$f` statements applied, so that a
+//! syntax tree has the same shape as the syntax proof of the statement.
+//! Statements with the provable typecode are parsed as whichever syntax
+//! typecode accepts them, which for set.mm is `wff`.
+//!
+//! The provable typecode is declared by a `$j` command of the form
+//! `syntax '|-' as 'wff';`, as in set.mm; a database without one is taken to
+//! use `|-`.
+
+use commands::CommandResult;
+use diag::Diagnostic;
+use nameck::Atom;
+use nameck::NameReader;
+use nameck::NameUsage;
+use nameck::Nameset;
+use parser;
+use parser::Comparer;
+use parser::Segment;
+use parser::SegmentId;
+use parser::SegmentOrder;
+use parser::StatementAddress;
+use parser::StatementRef;
+use parser::StatementType;
+use parser::SymbolType;
+use parser::TokenIndex;
+use parser::TokenPtr;
+use proof::ProofTreeArray;
+use scopeck::Frame;
+use scopeck::Hyp;
+use scopeck::ScopeReader;
+use scopeck::ScopeResult;
+use scopeck::ScopeUsage;
+use scopeck::VarIndex;
+use segment_set::SegmentSet;
+use std::cmp::Ordering;
+use std::mem;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::Arc;
+use util::new_map;
+use util::ptr_eq;
+use util::HashMap;
+use verify::ProofBuilder;
+
+/// `$j` keyword of the commands declaring typecodes; the provable typecode is
+/// the first argument of the form `syntax 'X' as 'Y';`.
+const SYNTAX_KEYWORD: &[u8] = b"syntax";
+
+/// The typecode of logical statements in a database which does not declare
+/// one; every other typecode used by an `$a` is a syntax typecode.
+const DEFAULT_PROVABLE_TYPECODE: &[u8] = b"|-";
+
+/// Finds the provable typecode of a database from its `$j` commands.
+pub fn provable_typecode<'a>(sset: &'a SegmentSet, commands: &CommandResult) -> TokenPtr<'a> {
+    commands
+        .get(SYNTAX_KEYWORD)
+        .iter()
+        .map(|entry| entry.args(sset))
+        .find(|args| args.len() == 3 && args[1] == b"as")
+        .map_or(DEFAULT_PROVABLE_TYPECODE, |args| args[0])
+}
+
+/// A symbol in the right-hand side of a production.
+#[derive(Clone, Debug, PartialEq)]
+enum Symbol {
+    /// A constant which must appear literally.
+    Const(Atom),
+    /// A variable of the syntax axiom, and its typecode.
+    Var(VarIndex, Atom),
+}
+
+/// A production of the grammar, generated from a syntax axiom.
+#[derive(Clone, Debug, PartialEq)]
+struct Rule {
+    /// The syntax axiom.
+    address: StatementAddress,
+    /// The typecode of the axiom.
+    typecode: Atom,
+    /// The math string of the axiom after the typecode.
+    symbols: Vec<Symbol>,
+    /// The variable typed by each `$f` hypothesis of the axiom, with the
+    /// address of the hypothesis, in frame order; this is the order of the
+    /// children in a syntax tree.
+    hyps: Vec<(VarIndex, StatementAddress)>,
+}
+
+impl Rule {
+    /// Returns true if two productions match the same strings with the same
+    /// children, regardless of the addresses of their statements.
+    fn same_production(&self, other: &Rule) -> bool {
+        self.typecode == other.typecode
+            && self.symbols == other.symbols
+            && self.hyps.len() == other.hyps.len()
+            && self
+                .hyps
+                .iter()
+                .zip(&other.hyps)
+                .all(|(hyp, other)| hyp.0 == other.0)
+    }
+
+    /// Returns the math string of the axiom as parser input, with its
+    /// variables standing for themselves.
+    fn input(&self) -> Vec<Input> {
+        self.symbols
+            .iter()
+            .map(|symbol| match *symbol {
+                Symbol::Const(atom) => Input::Const(atom),
+                Symbol::Var(var, typecode) => {
+                    let addr = self.hyps.iter().find(|hyp| hyp.0 == var).unwrap().1;
+                    Input::Var(addr, typecode)
+                }
+            })
+            .collect()
+    }
+}
+
+/// The grammar of a database, as a set of productions for each syntax
+/// typecode.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Grammar {
+    /// Atom for the provable typecode, if it is declared.
+    provable: Option<Atom>,
+    /// Syntax typecodes in order of first use by a syntax axiom.
+    typecodes: Vec<Atom>,
+    /// Productions for each syntax typecode, in database order.
+    rules: HashMap<Atom, Vec<Rule>>,
+}
+
+/// A symbol of a math string being parsed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Input {
+    /// A constant, or a variable with no `$f` hypothesis.
+    Const(Atom),
+    /// A variable, with the address and typecode of its `$f` hypothesis.
+    Var(StatementAddress, Atom),
+}
+
+/// A node of a syntax tree under construction.
+struct Node {
+    address: StatementAddress,
+    children: Vec<Rc<Node>>,
+    /// Range of input indices covered by this node.
+    span: Range<usize>,
+}
+
+/// One way of parsing a nonterminal starting from a given position.  If there
+/// are several derivations ending at the same position, only the first is kept
+/// and the roots of the first two which differ are recorded in `ambiguous`.
+#[derive(Clone)]
+struct Parse {
+    end: usize,
+    node: Rc<Node>,
+    ambiguous: Option<(StatementAddress, StatementAddress)>,
+}
+
+/// Working memory for parsing a single math string.
+struct ParseState<'a> {
+    grammar: &'a Grammar,
+    /// If set, productions from syntax axioms after this one are ignored.
+    limit: Option<(&'a SegmentOrder, StatementAddress)>,
+    input: &'a [Input],
+    /// Parses of each nonterminal from each position.  While a nonterminal is
+    /// being expanded, its entry holds the parses found so far, which are used
+    /// as the seed for any left recursion.
+    memo: HashMap<(Atom, usize), Rc<Vec<Parse>>>,
+    /// Nonterminals which are currently being expanded.
+    active: Vec<(Atom, usize)>,
+    /// Nonterminals whose expansion was found to be left-recursive.
+    recursive: Vec<(Atom, usize)>,
+    /// One past the last input symbol which was matched by any production.
+    furthest: usize,
+}
+
+fn add_parse(out: &mut Vec<Parse>, parse: Parse) {
+    match out.iter_mut().find(|old| old.end == parse.end) {
+        Some(old) => {
+            if old.ambiguous.is_none() {
+                old.ambiguous = Some((old.node.address, parse.node.address));
+            }
+        }
+        None => out.push(parse),
+    }
+}
+
+impl<'a> ParseState<'a> {
+    fn new(grammar: &'a Grammar, input: &'a [Input]) -> Self {
+        ParseState {
+            grammar,
+            limit: None,
+            input,
+            memo: new_map(),
+            active: Vec::new(),
+            recursive: Vec::new(),
+            furthest: 0,
+        }
+    }
+
+    /// Returns all parses of a nonterminal starting at `pos`.
+    ///
+    /// Left recursion is handled by growing the seed: the expansion is
+    /// repeated, using the parses from the previous round for the recursive
+    /// occurrence, until no new parses are found.
+    fn parse(&mut self, typecode: Atom, pos: usize) -> Rc<Vec<Parse>> {
+        let key = (typecode, pos);
+        if let Some(parses) = self.memo.get(&key) {
+            if self.active.contains(&key) && !self.recursive.contains(&key) {
+                self.recursive.push(key);
+            }
+            return parses.clone();
+        }
+        self.memo.insert(key, Rc::new(Vec::new()));
+        self.active.push(key);
+        loop {
+            let out = self.expand(typecode, pos);
+            let grew = out.len() > self.memo[&key].len();
+            self.memo.insert(key, Rc::new(out));
+            if !grew || !self.recursive.contains(&key) {
+                break;
+            }
+        }
+        self.active.pop();
+        self.memo[&key].clone()
+    }
+
+    /// Tries every production of a nonterminal once at `pos`.
+    fn expand(&mut self, typecode: Atom, pos: usize) -> Vec<Parse> {
+        let mut out = Vec::new();
+        if let Some(&Input::Var(address, vtype)) = self.input.get(pos) {
+            if vtype == typecode {
+                self.furthest = self.furthest.max(pos + 1);
+                out.push(Parse {
+                    end: pos + 1,
+                    node: Rc::new(Node {
+                        address,
+                        children: Vec::new(),
+                        span: pos..pos + 1,
+                    }),
+                    ambiguous: None,
+                });
+            }
+        }
+
+        let grammar = self.grammar;
+        if let Some(rules) = grammar.rules.get(&typecode) {
+            for rule in rules {
+                if let Some((order, last)) = self.limit {
+                    if order.cmp(&rule.address, &last) == Ordering::Greater {
+                        continue;
+                    }
+                }
+                self.match_rule(rule, 0, pos, pos, &mut Vec::new(), None, &mut out);
+            }
+        }
+        out
+    }
+
+    /// Matches the symbols of a production from `ix` onwards, starting at
+    /// `pos`, and adds a parse to `out` for each way of completing it.
+    #[allow(clippy::too_many_arguments)]
+    fn match_rule(
+        &mut self,
+        rule: &Rule,
+        ix: usize,
+        start: usize,
+        pos: usize,
+        slots: &mut Vec<(VarIndex, Rc<Node>)>,
+        ambiguous: Option<(StatementAddress, StatementAddress)>,
+        out: &mut Vec<Parse>,
+    ) {
+        match rule.symbols.get(ix) {
+            None => {
+                if let Some(children) = self.collect_children(rule, slots) {
+                    add_parse(
+                        out,
+                        Parse {
+                            end: pos,
+                            node: Rc::new(Node {
+                                address: rule.address,
+                                children,
+                                span: start..pos,
+                            }),
+                            ambiguous,
+                        },
+                    );
+                }
+            }
+            Some(&Symbol::Const(atom)) => {
+                if self.input.get(pos) == Some(&Input::Const(atom)) {
+                    self.furthest = self.furthest.max(pos + 1);
+                    self.match_rule(rule, ix + 1, start, pos + 1, slots, ambiguous, out);
+                }
+            }
+            Some(&Symbol::Var(var, typecode)) => {
+                for parse in self.parse(typecode, pos).iter() {
+                    slots.push((var, parse.node.clone()));
+                    self.match_rule(
+                        rule,
+                        ix + 1,
+                        start,
+                        parse.end,
+                        slots,
+                        ambiguous.or(parse.ambiguous),
+                        out,
+                    );
+                    slots.pop();
+                }
+            }
+        }
+    }
+
+    /// Orders the subtrees matched for the variables of a production by the
+    /// hypotheses of the syntax axiom.  A variable which occurs more than once
+    /// must match the same symbols each time.
+    fn collect_children(
+        &self,
+        rule: &Rule,
+        slots: &[(VarIndex, Rc<Node>)],
+    ) -> Option<Vec<Rc<Node>>> {
+        for (ix, &(var, ref node)) in slots.iter().enumerate() {
+            for &(var2, ref node2) in &slots[..ix] {
+                if var == var2 && self.input[node.span.clone()] != self.input[node2.span.clone()] {
+                    return None;
+                }
+            }
+        }
+        rule.hyps
+            .iter()
+            .map(|&(var, _)| {
+                slots
+                    .iter()
+                    .find(|slot| slot.0 == var)
+                    .map(|slot| slot.1.clone())
+            })
+            .collect()
+    }
+
+    /// Returns the parse of the whole input as the given typecode, if any.
+    fn parse_all(&mut self, typecode: Atom) -> Option<Parse> {
+        let len = self.input.len();
+        self.parse(typecode, 0)
+            .iter()
+            .find(|parse| parse.end == len)
+            .cloned()
+    }
+}
+
+/// Splits the constant pool of a frame into symbols.
+fn push_consts(out: &mut Vec<Symbol>, nset: &Nameset, pool: &[u8], range: Range<usize>) {
+    let mut token = Vec::new();
+    for &chr in &pool[range] {
+        token.push(chr & 0x7F);
+        if chr & 0x80 != 0 {
+            let atom = nset.lookup_symbol(&token).map(|lookup| lookup.atom);
+            out.push(Symbol::Const(atom.unwrap_or_default()));
+            token.clear();
+        }
+    }
+}
+
+/// Builds the production for a syntax axiom.
+fn make_rule(nset: &Nameset, address: StatementAddress, frame: &Frame) -> Rule {
+    let var_type = |var: VarIndex| {
+        frame
+            .hypotheses
+            .iter()
+            .filter_map(|hyp| match *hyp {
+                Hyp::Floating(_, fvar, typecode) if fvar == var => Some(typecode),
+                _ => None,
+            })
+            .next()
+            .unwrap_or_default()
+    };
+
+    let mut symbols = Vec::new();
+    for part in &*frame.target.tail {
+        push_consts(&mut symbols, nset, &frame.const_pool, part.prefix.clone());
+        symbols.push(Symbol::Var(part.var, var_type(part.var)));
+    }
+    push_consts(
+        &mut symbols,
+        nset,
+        &frame.const_pool,
+        frame.target.rump.clone(),
+    );
+
+    Rule {
+        address,
+        typecode: frame.target.typecode,
+        symbols,
+        hyps: frame
+            .hypotheses
+            .iter()
+            .filter_map(|hyp| match *hyp {
+                Hyp::Floating(addr, var, _) => Some((var, addr)),
+                Hyp::Essential(..) => None,
+            })
+            .collect(),
+    }
+}
+
+impl Grammar {
+    /// Collects the syntax axioms of a database into a grammar.
+    ///
+    /// An `$a` statement is a syntax axiom if its typecode is not the provable
+    /// typecode and it has no `$e` hypotheses.
+    pub fn new(
+        sset: &SegmentSet,
+        nset: &Nameset,
+        scope: &ScopeResult,
+        commands: &CommandResult,
+    ) -> Grammar {
+        let mut grammar = Grammar {
+            provable: nset
+                .lookup_symbol(provable_typecode(sset, commands))
+                .map(|lookup| lookup.atom),
+            typecodes: Vec::new(),
+            rules: new_map(),
+        };
+
+        for sref in sset.segments() {
+            for stmt in sref {
+                if stmt.statement_type() != StatementType::Axiom {
+                    continue;
+                }
+                let frame = match scope.get(stmt.label()) {
+                    Some(frame) => frame,
+                    None => continue,
+                };
+                let typecode = frame.target.typecode;
+                if Some(typecode) == grammar.provable
+                    || frame.hypotheses.iter().any(|hyp| match *hyp {
+                        Hyp::Essential(..) => true,
+                        Hyp::Floating(..) => false,
+                    })
+                {
+                    continue;
+                }
+                if !grammar.typecodes.contains(&typecode) {
+                    grammar.typecodes.push(typecode);
+                }
+                grammar.rules.entry(typecode).or_default().push(make_rule(
+                    nset,
+                    stmt.address(),
+                    frame,
+                ));
+            }
+        }
+        grammar
+    }
+
+    /// Returns true if two grammars have the same productions in the same
+    /// order, although their syntax axioms may be at different addresses; the
+    /// statements parsed by one are then parsed in the same way by the other.
+    fn same_productions(&self, other: &Grammar) -> bool {
+        self.provable == other.provable
+            && self.typecodes == other.typecodes
+            && self.rules.len() == other.rules.len()
+            && self.rules.iter().all(|(typecode, rules)| {
+                other.rules.get(typecode).is_some_and(|others| {
+                    rules.len() == others.len()
+                        && rules
+                            .iter()
+                            .zip(others)
+                            .all(|(rule, other)| rule.same_production(other))
+                })
+            })
+    }
+
+    /// Returns true if the typecode is defined by syntax axioms.
+    pub fn is_syntax_typecode(&self, typecode: Atom) -> bool {
+        self.rules.contains_key(&typecode)
+    }
+
+    /// Returns true if the typecode is the provable typecode, usually `|-`.
+    pub fn is_provable_typecode(&self, typecode: Atom) -> bool {
+        Some(typecode) == self.provable
+    }
+
+    /// Returns true for the statements which make logical claims: hypotheses,
+    /// theorems, and axioms with the provable typecode.
+    pub fn is_logical(&self, nset: &Nameset, stmt: StatementRef) -> bool {
+        self.is_logical_read(&mut NameReader::new(nset), stmt)
+    }
+
+    /// Classifies a statement as for `is_logical`, recording the names used.
+    fn is_logical_read(&self, names: &mut NameReader, stmt: StatementRef) -> bool {
+        match stmt.statement_type() {
+            StatementType::Essential | StatementType::Provable => true,
+            StatementType::Axiom => {
+                stmt.math_len() > 0
+                    && names
+                        .lookup_symbol(&stmt.math_at(0))
+                        .is_some_and(|lookup| self.is_provable_typecode(lookup.atom))
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses the math string of an `$e`, `$a` or `$p` statement.
+    ///
+    /// Returns the syntax tree of the statement, whose `qed` step is the root;
+    /// the typecode is not part of the tree.  Variables are resolved using the
+    /// `$f` hypotheses of the statement's frame if it has one, and otherwise
+    /// the global `$f` statements.
+    pub fn parse_statement(
+        &self,
+        nset: &Nameset,
+        scope: &ScopeResult,
+        stmt: StatementRef,
+    ) -> Result<ProofTreeArray, Diagnostic> {
+        self.parse_statement_read(
+            nset,
+            &mut NameReader::new(nset),
+            &mut ScopeReader::new(scope),
+            stmt,
+        )
+    }
+
+    /// Parses a statement as for `parse_statement`, recording the names and
+    /// frames used.
+    fn parse_statement_read(
+        &self,
+        nset: &Nameset,
+        names: &mut NameReader,
+        scoper: &mut ScopeReader,
+        stmt: StatementRef,
+    ) -> Result<ProofTreeArray, Diagnostic> {
+        let mut floats = new_map();
+        if let Some(frame) = scoper.get(stmt.label()) {
+            for hyp in &*frame.hypotheses {
+                if let Hyp::Floating(addr, var, typecode) = *hyp {
+                    floats.insert(frame.var_list[var], (addr, typecode));
+                }
+            }
+        }
+
+        let mut tokens = Vec::new();
+        for tref in stmt.math_iter() {
+            let lookup = names
+                .lookup_symbol(&tref)
+                .ok_or(Diagnostic::GrammarUnparseable(tref.index()))?;
+            if lookup.stype == SymbolType::Variable && !floats.contains_key(&lookup.atom) {
+                if let Some(float) = names.lookup_float(&tref) {
+                    floats.insert(lookup.atom, (float.address, float.typecode_atom));
+                }
+            }
+            tokens.push(lookup.atom);
+        }
+        if tokens.is_empty() {
+            return Err(Diagnostic::GrammarUnparseable(0));
+        }
+
+        let typecodes = if self.is_provable_typecode(tokens[0]) {
+            self.typecodes.clone()
+        } else if self.is_syntax_typecode(tokens[0]) {
+            vec![tokens[0]]
+        } else {
+            return Err(Diagnostic::GrammarUnparseable(0));
+        };
+
+        let input = tokens[1..]
+            .iter()
+            .map(|atom| match floats.get(atom) {
+                Some(&(addr, typecode)) => Input::Var(addr, typecode),
+                None => Input::Const(*atom),
+            })
+            .collect::<Vec<_>>();
+        let mut state = ParseState::new(self, &input);
+        let mut found: Option<Parse> = None;
+        for typecode in typecodes {
+            if let Some(parse) = state.parse_all(typecode) {
+                if found.is_some() || parse.ambiguous.is_some() {
+                    return Err(Diagnostic::GrammarAmbiguous);
+                }
+                found = Some(parse);
+            }
+        }
+        let root = match found {
+            Some(parse) => parse.node,
+            None => {
+                let furthest = (state.furthest + 1).min(tokens.len() - 1);
+                return Err(Diagnostic::GrammarUnparseable(furthest as TokenIndex));
+            }
+        };
+
+        // the expression strings of the tree use the verifier's representation,
+        // with the high bit marking the end of each token
+        let mut pool = Vec::new();
+        let mut offsets = vec![0];
+        for &atom in &tokens[1..] {
+            pool.extend_from_slice(nset.atom_name(atom));
+            *pool.last_mut().unwrap() |= 0x80;
+            offsets.push(pool.len());
+        }
+        fn add_node(
+            arr: &mut ProofTreeArray,
+            node: &Node,
+            pool: &[u8],
+            offsets: &[usize],
+        ) -> usize {
+            let children = node
+                .children
+                .iter()
+                .map(|child| add_node(arr, child, pool, offsets))
+                .collect();
+            let expr = offsets[node.span.start]..offsets[node.span.end];
+            arr.build(node.address, children, pool, expr)
+        }
+        let mut arr = ProofTreeArray::default();
+        let qed = add_node(&mut arr, &root, &pool, &offsets);
+        arr.set_qed(qed);
+        Ok(arr)
+    }
+}
+
+/// Stored result of parsing the statements of a segment.
+struct GrammarSegment {
+    source: Arc<Segment>,
+    name_usage: NameUsage,
+    scope_usage: ScopeUsage,
+    diagnostics: Vec<(StatementAddress, Diagnostic)>,
+}
+
+/// Analysis pass result for the grammar.
+#[derive(Default, Clone)]
+pub struct GrammarResult {
+    grammar: Arc<Grammar>,
+    segments: HashMap<SegmentId, Arc<GrammarSegment>>,
+}
+
+impl GrammarResult {
+    /// Returns the grammar built from the syntax axioms.
+    pub fn grammar(&self) -> &Arc<Grammar> {
+        &self.grammar
+    }
+
+    /// Report statements which could not be parsed unambiguously.
+    pub fn diagnostics(&self) -> Vec<(StatementAddress, Diagnostic)> {
+        let mut out = Vec::new();
+        for gsr in self.segments.values() {
+            out.extend(gsr.diagnostics.iter().cloned());
+        }
+        out
+    }
+}
+
+/// Driver which parses each logical statement in a segment.
+fn grammar_segment(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scope: &ScopeResult,
+    grammar: &Grammar,
+    sid: SegmentId,
+) -> GrammarSegment {
+    let mut diagnostics = Vec::new();
+    let mut names = NameReader::new(nset);
+    let mut scoper = ScopeReader::new(scope);
+    let sref = sset.segment(sid);
+    for stmt in sref {
+        if grammar.is_logical_read(&mut names, stmt) {
+            if let Err(diag) = grammar.parse_statement_read(nset, &mut names, &mut scoper, stmt) {
+                diagnostics.push((stmt.address(), diag));
+            }
+        }
+    }
+    GrammarSegment {
+        source: sref.segment.clone(),
+        name_usage: names.into_usage(),
+        scope_usage: scoper.into_usage(),
+        diagnostics,
+    }
+}
+
+/// Calculates the grammar for a database and parses its statements.
+///
+/// The grammar is rebuilt from the syntax axioms on every call, which is cheap
+/// relative to parsing.  While its productions are unchanged, the results for
+/// a segment are reused if the segment and the names and frames it used are
+/// unchanged; moving a syntax axiom to a new address does not count as a
+/// change, but any other change to the syntax axioms causes every segment to
+/// be parsed again.
+pub fn grammar_check(
+    result: &mut GrammarResult,
+    segments: &Arc<SegmentSet>,
+    nset: &Arc<Nameset>,
+    scope: &Arc<ScopeResult>,
+    commands: &CommandResult,
+) {
+    let grammar = Arc::new(Grammar::new(segments, nset, scope, commands));
+    let same_grammar = result.grammar.same_productions(&grammar);
+    let old = mem::replace(&mut result.segments, new_map());
+    let mut gsrq = Vec::new();
+    for sref in segments.segments() {
+        let segments2 = segments.clone();
+        let nset = nset.clone();
+        let scope = scope.clone();
+        let grammar = grammar.clone();
+        let id = sref.id;
+        let old_res_o = old.get(&id).cloned().filter(|_| same_grammar);
+        gsrq.push(segments.exec.exec(sref.bytes(), move || {
+            let sref = segments2.segment(id);
+            if let Some(old_res) = old_res_o {
+                if old_res.name_usage.valid(&nset)
+                    && old_res.scope_usage.valid(&nset, &scope)
+                    && ptr_eq::<Segment>(&old_res.source, &sref)
+                {
+                    return (id, old_res);
+                }
+            }
+            if segments2.options.trace_recalc {
+                println!("grammar({:?})", parser::guess_buffer_name(&sref.buffer));
+            }
+            (
+                id,
+                Arc::new(grammar_segment(&segments2, &nset, &scope, &grammar, id)),
+            )
+        }))
+    }
+
+    for promise in gsrq {
+        let (id, arc) = promise.wait();
+        result.segments.insert(id, arc);
+    }
+    result.grammar = grammar;
+}
+
+/// Returns the root axioms of two different parses of the math string as the
+/// given typecode, using only the syntax axioms up to `last`.
+fn is_ambiguous(
+    grammar: &Grammar,
+    order: &SegmentOrder,
+    last: StatementAddress,
+    typecode: Atom,
+    input: &[Input],
+) -> Option<(StatementAddress, StatementAddress)> {
+    let mut state = ParseState::new(grammar, input);
+    state.limit = Some((order, last));
+    state.parse_all(typecode).and_then(|parse| parse.ambiguous)
+}
+
+/// Returns true if substituting the production `inner` for the first or last
+/// symbol of `outer` gives an ambiguous math string.
+fn is_ambiguous_composition(
+    grammar: &Grammar,
+    order: &SegmentOrder,
+    last: StatementAddress,
+    outer: &Rule,
+    inner: &Rule,
+) -> bool {
+    let outer_input = outer.input();
+    let inner_input = inner.input();
+    if let Some(&Symbol::Var(_, typecode)) = outer.symbols.last() {
+        if typecode == inner.typecode {
+            let mut input = outer_input[..outer_input.len() - 1].to_vec();
+            input.extend_from_slice(&inner_input);
+            if is_ambiguous(grammar, order, last, outer.typecode, &input).is_some() {
+                return true;
+            }
+        }
+    }
+    if let Some(&Symbol::Var(_, typecode)) = outer.symbols.first() {
+        if typecode == inner.typecode {
+            let mut input = inner_input;
+            input.extend_from_slice(&outer_input[1..]);
+            if is_ambiguous(grammar, order, last, outer.typecode, &input).is_some() {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Finds the syntax axioms, not later than `rule`, which it overlaps with
+/// ambiguously.
+///
+/// This is not a decision procedure, since ambiguity of context-free grammars
+/// is undecidable.  Only the syntax axioms up to `rule` are used, so that each
+/// ambiguity is found at the axiom which introduces it.  Two kinds of overlap
+/// are looked for: the math string of the axiom itself having a second parse,
+/// and the math string formed by substituting one axiom into a variable at
+/// either end of the other having two parses, as happens for infix operators
+/// without parentheses.  Axioms whose own math string is ambiguous are not
+/// used for the second kind, as they have already been reported.
+fn axiom_ambiguities(
+    grammar: &Grammar,
+    order: &SegmentOrder,
+    rule: &Rule,
+) -> Vec<StatementAddress> {
+    let last = rule.address;
+    let self_ambiguous =
+        |rule: &Rule| is_ambiguous(grammar, order, rule.address, rule.typecode, &rule.input());
+
+    if let Some((first, second)) = self_ambiguous(rule) {
+        return vec![if first == last { second } else { first }];
+    }
+
+    let mut out = Vec::new();
+    for other in grammar.rules.values().flat_map(|rules| rules.iter()) {
+        if order.cmp(&other.address, &last) == Ordering::Greater {
+            continue;
+        }
+        if (is_ambiguous_composition(grammar, order, last, other, rule)
+            || is_ambiguous_composition(grammar, order, last, rule, other))
+            && self_ambiguous(other).is_none()
+        {
+            out.push(other.address);
+        }
+    }
+    out.sort_by(|x, y| order.cmp(x, y));
+    out
+}
+
+/// Stored result of checking the syntax axioms of a segment for ambiguity.
+struct AmbiguitySegment {
+    source: Arc<Segment>,
+    diagnostics: Vec<(StatementAddress, Diagnostic)>,
+}
+
+/// Analysis pass result for the unambiguity check of the grammar.
+#[derive(Default, Clone)]
+pub struct AmbiguityResult {
+    grammar: Arc<Grammar>,
+    segments: HashMap<SegmentId, Arc<AmbiguitySegment>>,
+}
+
+impl AmbiguityResult {
+    /// Report syntax axioms which overlap ambiguously with earlier ones.
+    pub fn diagnostics(&self) -> Vec<(StatementAddress, Diagnostic)> {
+        let mut out = Vec::new();
+        for asr in self.segments.values() {
+            out.extend(asr.diagnostics.iter().cloned());
+        }
+        out
+    }
+}
+
+/// Driver which checks each syntax axiom in a segment.
+fn ambiguity_segment(sset: &SegmentSet, grammar: &Grammar, sid: SegmentId) -> AmbiguitySegment {
+    let mut rules = grammar
+        .rules
+        .values()
+        .flat_map(|rules| rules.iter())
+        .filter(|rule| rule.address.segment_id == sid)
+        .collect::<Vec<_>>();
+    rules.sort_by_key(|rule| rule.address.index);
+
+    let mut diagnostics = Vec::new();
+    for rule in rules {
+        for other in axiom_ambiguities(grammar, &sset.order, rule) {
+            diagnostics.push((rule.address, Diagnostic::GrammarAmbiguousAxioms(other)));
+        }
+    }
+    AmbiguitySegment {
+        source: sset.segment(sid).segment.clone(),
+        diagnostics,
+    }
+}
+
+/// Checks the syntax axioms of a database for ambiguous overlaps.
+///
+/// Each segment is checked in parallel.  Since the ambiguity of an axiom
+/// depends on the whole grammar, results for a segment are reused only if the
+/// segment and the grammar are both unchanged.
+///
+/// This is expensive: each syntax axiom is composed with every earlier one of
+/// a matching typecode, so n syntax axioms take O(n²) parses of short strings.
+/// set.mm has well over a thousand syntax axioms, making this millions of
+/// parses, so the check is only run when the grammar diagnostics are asked
+/// for, and its results are kept across edits which leave the grammar alone.
+pub fn ambiguity_check(
+    result: &mut AmbiguityResult,
+    segments: &Arc<SegmentSet>,
+    grammar: &Arc<Grammar>,
+) {
+    let same_grammar = *result.grammar == **grammar;
+    let old = mem::replace(&mut result.segments, new_map());
+    let mut asrq = Vec::new();
+    for sref in segments.segments() {
+        let segments2 = segments.clone();
+        let grammar = grammar.clone();
+        let id = sref.id;
+        let old_res_o = old.get(&id).cloned().filter(|_| same_grammar);
+        asrq.push(segments.exec.exec(sref.bytes(), move || {
+            let sref = segments2.segment(id);
+            if let Some(old_res) = old_res_o {
+                if ptr_eq::<Segment>(&old_res.source, &sref) {
+                    return (id, old_res);
+                }
+            }
+            if segments2.options.trace_recalc {
+                println!("ambiguity({:?})", parser::guess_buffer_name(&sref.buffer));
+            }
+            (id, Arc::new(ambiguity_segment(&segments2, &grammar, id)))
+        }))
+    }
+
+    for promise in asrq {
+        let (id, arc) = promise.wait();
+        result.segments.insert(id, arc);
+    }
+    result.grammar = grammar.clone();
+}
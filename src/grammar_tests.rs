@@ -0,0 +1,176 @@
+use database::Database;
+use database::DbOptions;
+use diag::Diagnostic;
+use diag::DiagnosticClass;
+use parser::StatementAddress;
+use proof::RPNStep;
+use std::sync::Arc;
+use test_util::mkdb;
+
+const DEMO0: &str = "
+  $c 0 + = -> ( ) /\\ \\/ term wff |- $.
+  $v t r s P Q $.
+  tt $f term t $.
+  tr $f term r $.
+  ts $f term s $.
+  wp $f wff P $.
+  wq $f wff Q $.
+  tze $a term 0 $.
+  tpl $a term ( t + r ) $.
+  weq $a wff t = r $.
+  wim $a wff ( P -> Q ) $.
+  a1 $a |- ( t = r -> ( t = s -> r = s ) ) $.
+  a2 $a |- ( t + 0 ) = t $.
+  ${
+    min $e |- P $.
+    maj $e |- ( P -> Q ) $.
+    mp  $a |- Q $.
+  $}
+  EXTRA
+";
+
+fn parse(db: &mut Database, label: &str) -> Result<String, Diagnostic> {
+    let sset = db.parse_result().clone();
+    let nset = db.name_result().clone();
+    let scope = db.scope_result().clone();
+    let grammar = db.grammar_result().clone();
+    let sref = sset.statement(nset.lookup_label(label.as_bytes()).unwrap().address);
+    let arr = grammar.grammar().parse_statement(&nset, &scope, sref)?;
+    Ok(arr
+        .normal_iter(false)
+        .map(|step| match step {
+            RPNStep::Normal { addr, .. } => {
+                String::from_utf8(sset.statement(addr).label().to_owned()).unwrap()
+            }
+            RPNStep::Backref { .. } => unreachable!(),
+        })
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+#[test]
+fn test_parse_statements() {
+    let mut db = mkdb(&DEMO0.replace("EXTRA", ""), DbOptions::default());
+    assert_eq!(parse(&mut db, "a2").unwrap(), "tt tze tpl tt weq");
+    assert_eq!(
+        parse(&mut db, "a1").unwrap(),
+        "tt tr weq tt ts weq tr ts weq wim wim"
+    );
+    assert_eq!(parse(&mut db, "maj").unwrap(), "wp wq wim");
+    assert_eq!(parse(&mut db, "mp").unwrap(), "wq");
+    assert!(db.diag_notations(vec![DiagnosticClass::Grammar]).is_empty());
+}
+
+#[test]
+fn test_declared_provable_typecode() {
+    let text = DEMO0.replace("|-", "|=");
+    let mut db = mkdb(&text.replace("EXTRA", ""), DbOptions::default());
+    // without a declaration, `|=` is taken as one more syntax typecode
+    assert_eq!(parse(&mut db, "a2").unwrap(), "tt a2");
+
+    let syntax = "$( $j syntax 'wff'; syntax '|=' as 'wff'; $)";
+    let mut db = mkdb(&text.replace("EXTRA", syntax), DbOptions::default());
+    assert_eq!(parse(&mut db, "a2").unwrap(), "tt tze tpl tt weq");
+    assert!(db.diag_notations(vec![DiagnosticClass::Grammar]).is_empty());
+}
+
+#[test]
+fn test_syntax_tree_exprs() {
+    let mut db = mkdb(&DEMO0.replace("EXTRA", ""), DbOptions::default());
+    let sset = db.parse_result().clone();
+    let nset = db.name_result().clone();
+    let scope = db.scope_result().clone();
+    let grammar = Arc::clone(db.grammar_result());
+    let sref = sset.statement(nset.lookup_label(b"a2").unwrap().address);
+    let arr = grammar
+        .grammar()
+        .parse_statement(&nset, &scope, sref)
+        .unwrap();
+    assert_eq!(arr.exprs[arr.qed], b" ( t + 0 ) = t".to_vec());
+    assert_eq!(arr.indent()[arr.qed], 0);
+}
+
+#[test]
+fn test_unparseable() {
+    let mut db = mkdb(
+        &DEMO0.replace("EXTRA", "bad $a |- ( t + = t ) $."),
+        DbOptions::default(),
+    );
+    assert_eq!(
+        parse(&mut db, "bad"),
+        Err(Diagnostic::GrammarUnparseable(4))
+    );
+    let notes = db.diag_notations(vec![DiagnosticClass::Grammar]);
+    assert_eq!(notes.len(), 1);
+}
+
+#[test]
+fn test_ambiguous() {
+    let mut db = mkdb(
+        &DEMO0.replace(
+            "EXTRA",
+            "tpl2 $a term ( t + r ) $.
+         amb $a |- ( t + r ) = t $.",
+        ),
+        DbOptions::default(),
+    );
+    assert_eq!(parse(&mut db, "amb"), Err(Diagnostic::GrammarAmbiguous));
+}
+
+fn ambiguities(db: &mut Database) -> Vec<(String, Diagnostic)> {
+    let sset = db.parse_result().clone();
+    let mut diags = db
+        .ambiguity_result()
+        .diagnostics()
+        .into_iter()
+        .map(|(addr, diag)| {
+            let label = String::from_utf8(sset.statement(addr).label().to_owned()).unwrap();
+            (label, diag)
+        })
+        .collect::<Vec<_>>();
+    diags.sort_by(|x, y| x.0.cmp(&y.0));
+    diags
+}
+
+fn address(db: &mut Database, label: &str) -> StatementAddress {
+    db.name_result()
+        .lookup_label(label.as_bytes())
+        .unwrap()
+        .address
+}
+
+#[test]
+fn test_unambiguous_grammar() {
+    let mut db = mkdb(&DEMO0.replace("EXTRA", ""), DbOptions::default());
+    assert!(ambiguities(&mut db).is_empty());
+}
+
+#[test]
+fn test_duplicate_axiom() {
+    let mut db = mkdb(
+        &DEMO0.replace("EXTRA", "tpl2 $a term ( t + r ) $."),
+        DbOptions::default(),
+    );
+    let tpl = address(&mut db, "tpl");
+    assert_eq!(
+        ambiguities(&mut db),
+        vec![("tpl2".to_owned(), Diagnostic::GrammarAmbiguousAxioms(tpl))]
+    );
+}
+
+#[test]
+fn test_infix_overlap() {
+    let mut db = mkdb(
+        &DEMO0.replace(
+            "EXTRA",
+            "wan $a wff ( P /\\ Q ) $.
+         wor $a wff P \\/ Q $.",
+        ),
+        DbOptions::default(),
+    );
+    let wor = address(&mut db, "wor");
+    assert_eq!(
+        ambiguities(&mut db),
+        vec![("wor".to_owned(), Diagnostic::GrammarAmbiguousAxioms(wor))]
+    );
+}
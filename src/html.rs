@@ -0,0 +1,321 @@
+//! Generation of HTML pages for theorems, in the style of the `mpeuni` pages
+//! written by metamath.exe.
+//!
+//! Each `$p` statement gets a page named after its label, with the comment
+//! describing it, its hypotheses and assertion, and a table of the logical
+//! steps of its proof.  Math symbols are typeset with the `althtmldef`
+//! definitions from the `$t` comments, falling back to `htmldef` and then to
+//! the symbol itself.
+//!
+//! Pages are written one segment at a time in parallel.  The generator
+//! remembers which segments it has written pages for, so that after an
+//! incremental reload only the pages in changed segments are regenerated,
+//! unless the typesetting table has changed.
+
+use nameck::Nameset;
+use parser::as_str;
+use parser::Segment;
+use parser::SegmentId;
+use parser::StatementRef;
+use parser::StatementType;
+use proof::ProofTreeArray;
+use scopeck::Hyp;
+use scopeck::ScopeResult;
+use segment_set::SegmentSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use typesetting::TypesettingKind;
+use typesetting::TypesettingResult;
+use util::new_map;
+use util::ptr_eq;
+use util::HashMap;
+
+/// Record of the pages written by previous runs of the generator.
+#[derive(Default, Clone)]
+pub struct HtmlState {
+    /// Directory the pages were written to.
+    dir: PathBuf,
+    /// Typesetting table used for the pages.
+    table: Option<Arc<TypesettingResult>>,
+    /// Source of each segment whose pages are up to date.
+    segments: HashMap<SegmentId, Arc<Segment>>,
+}
+
+/// Escapes text for inclusion in HTML.
+fn escape(text: &[u8]) -> String {
+    let mut out = String::new();
+    for chr in String::from_utf8_lossy(text).chars() {
+        match chr {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(chr),
+        }
+    }
+    out
+}
+
+/// Typesets a single math symbol.
+fn symbol(table: &TypesettingResult, token: &[u8]) -> String {
+    table
+        .symbol(TypesettingKind::AltHtml, token)
+        .or_else(|| table.symbol(TypesettingKind::Html, token))
+        .map(|def| String::from_utf8_lossy(&def.value).into_owned())
+        .unwrap_or_else(|| escape(token))
+}
+
+/// Typesets a math string given as space-separated tokens.
+fn math<'a, I: IntoIterator<Item = &'a [u8]>>(table: &TypesettingResult, tokens: I) -> String {
+    let mut out = String::from("<span class=\"math\">");
+    for token in tokens {
+        out.push_str(&symbol(table, token));
+        out.push(' ');
+    }
+    out.push_str("</span>");
+    out
+}
+
+/// Typesets the math string of a statement.
+fn statement_math(table: &TypesettingResult, stmt: StatementRef) -> String {
+    let tokens = stmt
+        .math_iter()
+        .map(|tref| tref.slice)
+        .collect::<Vec<&[u8]>>();
+    math(table, tokens)
+}
+
+/// Writes the proof table for a theorem.
+fn write_proof<W: Write>(
+    out: &mut W,
+    sset: &SegmentSet,
+    table: &TypesettingResult,
+    stmt: StatementRef,
+    arr: &ProofTreeArray,
+) -> io::Result<()> {
+    let provable_tc = stmt.math_at(0).slice;
+    let indent = arr.indent();
+
+    // maps each tree to its 1-based step number, or 0 for syntax steps
+    let mut steps = vec![0; arr.trees.len()];
+    let mut count = 0;
+    writeln!(
+        out,
+        "<table class=\"proof\">\n<tr><th>Step</th><th>Hyp</th><th>Ref</th>\
+         <th>Expression</th></tr>"
+    )?;
+    for (ix, tree) in arr.trees.iter().enumerate() {
+        let step = sset.statement(tree.address);
+        if step.math_len() == 0 || step.math_at(0).slice != provable_tc {
+            continue;
+        }
+        count += 1;
+        steps[ix] = count;
+
+        let hyps = tree
+            .children
+            .iter()
+            .filter(|&&child| steps[child] != 0)
+            .map(|&child| steps[child].to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let label = as_str(step.label());
+        let reference = if step.statement_type() == StatementType::Essential {
+            escape(step.label())
+        } else {
+            format!("<a href=\"{}.html\">{}</a>", label, escape(step.label()))
+        };
+        let expr = arr.exprs[ix]
+            .split(|&chr| chr == b' ')
+            .filter(|token| !token.is_empty());
+        let tokens = Some(provable_tc).into_iter().chain(expr);
+        writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td>{}</td>\
+             <td style=\"padding-left: {}em\">{}</td></tr>",
+            count,
+            hyps,
+            reference,
+            indent[ix],
+            math(table, tokens)
+        )?;
+    }
+    writeln!(out, "</table>")
+}
+
+/// Writes the page for a single theorem.
+fn write_page<W: Write>(
+    out: &mut W,
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scope: &ScopeResult,
+    table: &TypesettingResult,
+    stmt: StatementRef,
+) -> io::Result<()> {
+    let label = escape(stmt.label());
+    let title = table
+        .directive(b"htmltitle")
+        .map(|def| String::from_utf8_lossy(&def.value).into_owned())
+        .unwrap_or_default();
+    writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{} - {}</title>\n</head>\n<body>\n<h1>Theorem {}</h1>",
+        label, title, label
+    )?;
+
+    if let Some(comment) = stmt.associated_comment() {
+        let mut span = comment.span();
+        span.start += 2;
+        span.end -= 3;
+        let text = span.as_ref(&comment.segment().segment.buffer);
+        writeln!(out, "<p class=\"description\">{}</p>", escape(text).trim())?;
+    }
+
+    if let Some(frame) = scope.get(stmt.label()) {
+        let hyps = frame
+            .hypotheses
+            .iter()
+            .filter_map(|hyp| match *hyp {
+                Hyp::Essential(addr, _) => Some(sset.statement(addr)),
+                Hyp::Floating(..) => None,
+            })
+            .collect::<Vec<_>>();
+        if !hyps.is_empty() {
+            writeln!(
+                out,
+                "<table class=\"hypotheses\">\n<tr><th>Hypothesis</th><th>Ref</th>\
+                 <th>Expression</th></tr>"
+            )?;
+            for hyp in hyps {
+                writeln!(
+                    out,
+                    "<tr><td></td><td>{}</td><td>{}</td></tr>",
+                    escape(hyp.label()),
+                    statement_math(table, hyp)
+                )?;
+            }
+            writeln!(out, "</table>")?;
+        }
+    }
+    writeln!(
+        out,
+        "<table class=\"assertion\">\n<tr><th>Assertion</th><th>Ref</th>\
+         <th>Expression</th></tr>\n<tr><td></td><td>{}</td><td>{}</td></tr>\n</table>",
+        label,
+        statement_math(table, stmt)
+    )?;
+
+    match ProofTreeArray::new(sset, nset, scope, stmt) {
+        Ok(arr) => write_proof(out, sset, table, stmt, &arr)?,
+        Err(_) => writeln!(
+            out,
+            "<p class=\"warning\">The proof of this theorem is incomplete or invalid.</p>"
+        )?,
+    }
+    writeln!(out, "</body>\n</html>")
+}
+
+/// Writes the pages for the theorems of a segment.
+fn html_segment(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scope: &ScopeResult,
+    table: &TypesettingResult,
+    dir: &Path,
+    sid: SegmentId,
+) -> io::Result<usize> {
+    let mut count = 0;
+    for stmt in sset.segment(sid) {
+        if stmt.statement_type() != StatementType::Provable {
+            continue;
+        }
+        let path = dir.join(format!("{}.html", as_str(stmt.label())));
+        let mut page = Vec::new();
+        write_page(&mut page, sset, nset, scope, table, stmt)?;
+        File::create(path)?.write_all(&page)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Writes the theorem pages of a database to a directory, returning the number
+/// of pages written.
+///
+/// Segments whose pages were already written to the same directory by a
+/// previous call, with the same typesetting table, are skipped if they are
+/// unchanged.
+pub fn html_pages(
+    state: &mut HtmlState,
+    segments: &Arc<SegmentSet>,
+    nset: &Arc<Nameset>,
+    scope: &Arc<ScopeResult>,
+    table: &Arc<TypesettingResult>,
+    dir: &Path,
+) -> io::Result<usize> {
+    let reuse = state.dir == dir
+        && state
+            .table
+            .as_ref()
+            .is_some_and(|old| old.same_table(table));
+    if !reuse {
+        state.segments.clear();
+    }
+    fs::create_dir_all(dir)?;
+
+    let mut jobs = Vec::new();
+    for sref in segments.segments() {
+        if state
+            .segments
+            .get(&sref.id)
+            .is_some_and(|old| ptr_eq::<Segment>(old, &sref))
+        {
+            continue;
+        }
+        let segments2 = segments.clone();
+        let nset = nset.clone();
+        let scope = scope.clone();
+        let table = table.clone();
+        let dir = dir.to_owned();
+        let id = sref.id;
+        jobs.push(segments.exec.exec(sref.bytes(), move || {
+            let sref = segments2.segment(id);
+            let result = html_segment(&segments2, &nset, &scope, &table, &dir, id);
+            (id, sref.segment.clone(), result)
+        }));
+    }
+
+    let mut written = new_map();
+    let mut count = 0;
+    let mut error = None;
+    for promise in jobs {
+        let (id, source, result) = promise.wait();
+        match result {
+            Ok(pages) => {
+                count += pages;
+                written.insert(id, source);
+            }
+            Err(err) => error = Some(err),
+        }
+    }
+
+    // forget segments which no longer exist
+    let live = segments
+        .segments()
+        .iter()
+        .map(|sref| sref.id)
+        .collect::<Vec<_>>();
+    state.segments.retain(|id, _| live.contains(id));
+    state.segments.extend(written);
+    state.dir = dir.to_owned();
+    state.table = Some(table.clone());
+    match error {
+        Some(err) => Err(err),
+        None => Ok(count),
+    }
+}
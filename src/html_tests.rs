@@ -0,0 +1,77 @@
+use database::DbOptions;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use test_util::mkdb;
+use test_util::reparse;
+
+const DB: &str = "$c wff |- ( -> ) $.
+    $v ph ps $.
+    $( $t htmltitle \"Test\"; althtmldef \"->\" as \" &rarr; \"; $)
+    wph $f wff ph $.
+    wps $f wff ps $.
+    wi $a wff ( ph -> ps ) $.
+    ${
+      mp.1 $e |- ph $.
+      mp.2 $e |- ( ph -> ps ) $.
+      ax-mp $a |- ps $.
+    $}
+    ${
+      id.1 $e |- ph $.
+      $( A theorem about <ph>. $)
+      id $p |- ph $= ( ) B $.
+    $}
+    ${
+      mp2.1 $e |- ph $.
+      mp2.2 $e |- ( ph -> ps ) $.
+      mp2 $p |- ps $= ( ax-mp ) ABCDE $.
+    $}";
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("smetamath-html-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn test_html_page() {
+    let dir = temp_dir("page");
+    let mut db = mkdb(DB, DbOptions::default());
+    assert_eq!(db.html(dir.to_str().unwrap().to_owned()).unwrap(), 2);
+
+    let page = fs::read_to_string(dir.join("id.html")).unwrap();
+    assert!(page.contains("<title>id - Test</title>"));
+    assert!(page.contains("A theorem about &lt;ph&gt;."));
+    assert!(page.contains("<td>id.1</td>"));
+
+    let page = fs::read_to_string(dir.join("mp2.html")).unwrap();
+    assert!(page.contains("<td>mp2.2</td>"));
+    assert!(page.contains("( ph  &rarr;  ps )"));
+    assert!(page.contains(
+        "<tr><td>3</td><td>1, 2</td><td><a href=\"ax-mp.html\">ax-mp</a></td>\
+         <td style=\"padding-left: 0em\">"
+    ));
+    assert!(page.contains("<tr><td>1</td><td></td><td>mp2.1</td><td style=\"padding-left: 1em\">"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_html_incremental() {
+    let dir = temp_dir("incremental");
+    let dir_str = dir.to_str().unwrap().to_owned();
+    let options = DbOptions {
+        incremental: true,
+        ..DbOptions::default()
+    };
+    let mut db = mkdb(DB, options);
+    assert_eq!(db.html(dir_str.clone()).unwrap(), 2);
+    reparse(&mut db, DB);
+    assert_eq!(db.html(dir_str.clone()).unwrap(), 0);
+
+    // changing the typesetting table regenerates every page
+    reparse(&mut db, &DB.replace("&rarr;", "&#8594;"));
+    assert_eq!(db.html(dir_str.clone()).unwrap(), 2);
+    let page = fs::read_to_string(dir.join("mp2.html")).unwrap();
+    assert!(page.contains("&#8594;"));
+    fs::remove_dir_all(&dir).unwrap();
+}
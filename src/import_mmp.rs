@@ -0,0 +1,559 @@
+//! Import support for mmj2 proof files.
+//!
+//! This reads the worksheets written by `export::export_mmp` back into a
+//! `ProofTreeArray`.  A worksheet lists only the logical steps of a proof, each
+//! as `step:hyps:ref` followed by the formula proved at that step; the
+//! substitution used at each step is recovered by unifying the formulas
+//! against the frame of the referenced assertion, and the syntax proofs of the
+//! substituted expressions are found by a backtracking search over the syntax
+//! axioms.  The resulting normal proof is then checked by the verifier.
+//!
+//! Any `$=` proof block in the worksheet is ignored, since the steps are the
+//! part that is meant to be edited.
+
+use diag::Diagnostic;
+use nameck::NameReader;
+use nameck::Nameset;
+use parser::Span;
+use parser::StatementAddress;
+use parser::StatementRef;
+use parser::StatementType;
+use parser::TokenPtr;
+use proof::ProofTreeArray;
+use scopeck::Frame;
+use scopeck::Hyp;
+use scopeck::ScopeResult;
+use scopeck::VarIndex;
+use scopeck::VerifyExpr;
+use segment_set::SegmentSet;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A math token in a worksheet or frame.
+type Tok<'a> = &'a [u8];
+
+/// A partial substitution being built by unification, indexed by the
+/// variables of a frame.
+type Subst<'s, 'a> = [Option<&'s [Tok<'a>]>];
+
+/// One symbol of a frame expression after splitting the constant pool.
+#[derive(Clone, Debug)]
+enum PatTok {
+    Const(Vec<u8>),
+    Var(VarIndex),
+}
+
+/// A proof step parsed from a worksheet.
+struct StepLine<'a> {
+    /// The step name, such as `h1`, `3` or `qed`
+    name: Span,
+    /// The span of the hypothesis list
+    hyps_span: Span,
+    /// The names of the steps used as logical hypotheses
+    hyps: Vec<Span>,
+    /// The label of the assertion or hypothesis applied at this step
+    label: Span,
+    /// The formula proved at this step, starting with the typecode
+    formula: Vec<Tok<'a>>,
+    /// The span of the formula
+    formula_span: Span,
+}
+
+/// A step which has been read from the worksheet.
+struct Imported<'a> {
+    /// The formula proved at this step, starting with the typecode
+    formula: Vec<Tok<'a>>,
+    /// The normal-mode proof of the step, or `None` if it had an error
+    proof: Option<Vec<TokenPtr<'a>>>,
+}
+
+/// Splits a run of constants from the constant pool of a frame into tokens.
+fn push_consts(out: &mut Vec<PatTok>, pool: &[u8], range: Range<usize>) {
+    let mut token = Vec::new();
+    for &chr in &pool[range] {
+        token.push(chr & 0x7F);
+        if chr & 0x80 != 0 {
+            out.push(PatTok::Const(token));
+            token = Vec::new();
+        }
+    }
+}
+
+/// Converts a frame expression, including its typecode, into a pattern.
+fn pattern(nset: &Nameset, frame: &Frame, expr: &VerifyExpr) -> Vec<PatTok> {
+    let mut out = vec![PatTok::Const(nset.atom_name(expr.typecode).to_vec())];
+    for part in &*expr.tail {
+        push_consts(&mut out, &frame.const_pool, part.prefix.clone());
+        out.push(PatTok::Var(part.var));
+    }
+    push_consts(&mut out, &frame.const_pool, expr.rump.clone());
+    out
+}
+
+/// Matches a list of patterns against token strings, extending `subst`.
+///
+/// Every complete match is passed to `accept`, which returns true to stop the
+/// search; the return value is true if some match was accepted.  Variables are
+/// never substituted with empty strings.
+fn unify<'s, 'a>(
+    pat: &[PatTok],
+    toks: &'s [Tok<'a>],
+    rest: &[(&[PatTok], &'s [Tok<'a>])],
+    subst: &mut Subst<'s, 'a>,
+    accept: &mut dyn FnMut(&Subst<'s, 'a>) -> bool,
+) -> bool {
+    match pat.split_first() {
+        None => {
+            if !toks.is_empty() {
+                false
+            } else if let Some((&(npat, ntoks), nrest)) = rest.split_first() {
+                unify(npat, ntoks, nrest, subst, accept)
+            } else {
+                accept(subst)
+            }
+        }
+        Some((PatTok::Const(chr), pat)) => {
+            !toks.is_empty() && toks[0] == &chr[..] && unify(pat, &toks[1..], rest, subst, accept)
+        }
+        Some((&PatTok::Var(var), pat)) => {
+            if let Some(value) = subst[var] {
+                return toks.starts_with(value)
+                    && unify(pat, &toks[value.len()..], rest, subst, accept);
+            }
+            for len in 1..toks.len() + 1 {
+                subst[var] = Some(&toks[..len]);
+                if unify(pat, &toks[len..], rest, subst, accept) {
+                    return true;
+                }
+            }
+            subst[var] = None;
+            false
+        }
+    }
+}
+
+/// A syntax axiom with the pattern for its target.
+type SyntaxAxiom<'a> = (&'a Frame, TokenPtr<'a>, Vec<PatTok>);
+
+/// Database context for importing the steps of a worksheet, which also finds
+/// syntax proofs for the expressions substituted into it.
+struct Importer<'a> {
+    sset: &'a SegmentSet,
+    nset: &'a Nameset,
+    scope: &'a ScopeResult,
+    thm_frame: &'a Frame,
+    names: NameReader<'a>,
+    /// The `$f` hypotheses of the theorem, by variable name
+    floats: HashMap<Tok<'a>, (Tok<'a>, TokenPtr<'a>)>,
+    /// Patterns for the target of every syntax axiom, by typecode
+    axioms: HashMap<Vec<u8>, Vec<SyntaxAxiom<'a>>>,
+    /// Results found so far; an entry of `None` is also used to cut off
+    /// searches which recurse into themselves
+    memo: HashMap<Vec<Tok<'a>>, Option<Vec<TokenPtr<'a>>>>,
+}
+
+impl<'a> Importer<'a> {
+    /// Collects the syntax axioms of the database, which are the `$a`
+    /// statements without logical hypotheses whose typecode is not the one
+    /// used for logical steps.
+    fn new(
+        sset: &'a SegmentSet,
+        nset: &'a Nameset,
+        scope: &'a ScopeResult,
+        thm_frame: &'a Frame,
+        logical_tc: Tok<'a>,
+    ) -> Self {
+        let mut floats = HashMap::new();
+        for hyp in &*thm_frame.hypotheses {
+            if let Hyp::Floating(addr, var, typecode) = *hyp {
+                floats.insert(
+                    nset.atom_name(thm_frame.var_list[var]),
+                    (nset.atom_name(typecode), sset.statement(addr).label()),
+                );
+            }
+        }
+
+        let mut axioms: HashMap<Vec<u8>, Vec<_>> = HashMap::new();
+        for sref in sset.segments() {
+            for stmt in sref {
+                if stmt.statement_type() != StatementType::Axiom {
+                    continue;
+                }
+                if let Some(frame) = scope.get(stmt.label()) {
+                    let typecode = nset.atom_name(frame.target.typecode);
+                    let essential = frame.hypotheses.iter().any(|hyp| match *hyp {
+                        Hyp::Essential(..) => true,
+                        Hyp::Floating(..) => false,
+                    });
+                    if typecode != logical_tc && !essential {
+                        axioms.entry(typecode.to_vec()).or_default().push((
+                            frame,
+                            stmt.label(),
+                            pattern(nset, frame, &frame.target),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Importer {
+            sset,
+            nset,
+            scope,
+            thm_frame,
+            names: NameReader::new(nset),
+            floats,
+            axioms,
+            memo: HashMap::new(),
+        }
+    }
+
+    /// Returns a normal-mode syntax proof of a typecode followed by an
+    /// expression.
+    fn prove(&mut self, formula: Vec<Tok<'a>>) -> Option<Vec<TokenPtr<'a>>> {
+        if let Some(result) = self.memo.get(&formula) {
+            return result.clone();
+        }
+        self.memo.insert(formula.clone(), None);
+
+        let mut result = None;
+        if formula.len() == 2 {
+            let var = formula[1];
+            if let Some(&(typecode, label)) = self.floats.get(var) {
+                if typecode == formula[0] {
+                    result = Some(vec![label]);
+                }
+            } else if let Some(float) = self.names.lookup_float(var) {
+                if float.typecode == formula[0] {
+                    result = Some(vec![self.sset.statement(float.address).label()]);
+                }
+            }
+        }
+
+        if result.is_none() {
+            let candidates = self.axioms.get(formula[0]).cloned().unwrap_or_default();
+            for (frame, label, pat) in candidates {
+                let mut subst = vec![None; frame.mandatory_count];
+                unify(&pat, &formula, &[], &mut subst, &mut |subst| {
+                    result = self.prove_floats(frame, subst, &[]).map(|mut steps| {
+                        steps.push(label);
+                        steps
+                    });
+                    result.is_some()
+                });
+                if result.is_some() {
+                    break;
+                }
+            }
+        }
+
+        self.memo.insert(formula, result.clone());
+        result
+    }
+
+    /// Returns syntax proofs for the `$f` hypotheses of a frame under a
+    /// substitution, in the order required by the frame.  The proofs of the
+    /// `$e` hypotheses are given by `essentials`, in order.
+    fn prove_floats(
+        &mut self,
+        frame: &Frame,
+        subst: &Subst<'_, 'a>,
+        essentials: &[&[TokenPtr<'a>]],
+    ) -> Option<Vec<TokenPtr<'a>>> {
+        let mut out = Vec::new();
+        let mut essentials = essentials.iter();
+        for hyp in &*frame.hypotheses {
+            match *hyp {
+                Hyp::Floating(_, var, typecode) => {
+                    let mut formula = vec![self.nset.atom_name(typecode)];
+                    formula.extend_from_slice(subst[var]?);
+                    out.extend(self.prove(formula)?);
+                }
+                Hyp::Essential(..) => out.extend_from_slice(essentials.next()?),
+            }
+        }
+        Some(out)
+    }
+
+    /// Unifies one worksheet step with the assertion or hypothesis it references,
+    /// returning its normal-mode proof, or `None` if it depends on a step which
+    /// could not be imported.
+    fn import_step(
+        &mut self,
+        steps: &HashMap<&[u8], Imported<'a>>,
+        text: &'a [u8],
+        step: &StepLine<'a>,
+    ) -> Result<Option<Vec<TokenPtr<'a>>>, Vec<Diagnostic>> {
+        let label = step.label.as_ref(text);
+
+        // a hypothesis of the theorem itself
+        for hyp in &*self.thm_frame.hypotheses {
+            if let Hyp::Essential(addr, _) = *hyp {
+                let hstmt = self.sset.statement(addr);
+                if hstmt.label() == label {
+                    if !step.hyps.is_empty() {
+                        return Err(vec![Diagnostic::MmpHypCount(step.hyps_span, 0)]);
+                    }
+                    if !hstmt
+                        .math_iter()
+                        .map(|tok| tok.slice)
+                        .eq(step.formula.iter().cloned())
+                    {
+                        return Err(vec![Diagnostic::MmpStepMismatch(step.formula_span)]);
+                    }
+                    return Ok(Some(vec![hstmt.label()]));
+                }
+            }
+        }
+
+        let frame = match self.scope.get(label) {
+            Some(frame)
+                if frame.stype == StatementType::Axiom
+                    || frame.stype == StatementType::Provable =>
+            {
+                frame
+            }
+            _ => return Err(vec![Diagnostic::MmpUnknownLabel(step.label)]),
+        };
+        let label = self.sset.statement(frame.valid.start).label();
+
+        let essentials: Vec<&VerifyExpr> = frame
+            .hypotheses
+            .iter()
+            .filter_map(|hyp| match *hyp {
+                Hyp::Essential(_, ref expr) => Some(expr),
+                Hyp::Floating(..) => None,
+            })
+            .collect();
+        if essentials.len() != step.hyps.len() {
+            return Err(vec![Diagnostic::MmpHypCount(
+                step.hyps_span,
+                essentials.len(),
+            )]);
+        }
+
+        let mut formulas = Vec::new();
+        let mut hyp_proofs = Vec::new();
+        let mut errors = Vec::new();
+        let mut complete = true;
+        for &hyp in &step.hyps {
+            match steps.get(hyp.as_ref(text)) {
+                None => errors.push(Diagnostic::MmpUnknownStep(hyp)),
+                Some(imported) => {
+                    formulas.push(&imported.formula[..]);
+                    match imported.proof {
+                        Some(ref proof) => hyp_proofs.push(&proof[..]),
+                        None => complete = false,
+                    }
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        if !complete {
+            return Ok(None);
+        }
+
+        let patterns: Vec<Vec<PatTok>> = essentials
+            .iter()
+            .map(|expr| pattern(self.nset, frame, expr))
+            .collect();
+        let goals: Vec<(&[PatTok], &[Tok<'a>])> = patterns
+            .iter()
+            .map(|pat| &pat[..])
+            .zip(formulas.iter().cloned())
+            .collect();
+
+        let target = pattern(self.nset, frame, &frame.target);
+        let mut subst = vec![None; frame.mandatory_count];
+        let mut matched = false;
+        let mut result = None;
+        unify(&target, &step.formula, &goals, &mut subst, &mut |subst| {
+            matched = true;
+            result = self.prove_floats(frame, subst, &hyp_proofs);
+            result.is_some()
+        });
+        match result {
+            Some(mut proof) => {
+                proof.push(label);
+                Ok(Some(proof))
+            }
+            None if matched => Err(vec![Diagnostic::MmpNoSyntax(step.formula_span)]),
+            None => Err(vec![Diagnostic::MmpStepMismatch(step.formula_span)]),
+        }
+    }
+}
+
+/// Splits a worksheet into chunks, each starting with a line which does not
+/// begin with whitespace, and the chunks into whitespace-separated tokens.
+fn chunks(text: &[u8]) -> Vec<Vec<Span>> {
+    let mut out: Vec<Vec<Span>> = Vec::new();
+    let mut pos = 0;
+    let mut line_start = true;
+    while pos < text.len() {
+        let chr = text[pos];
+        if chr <= b' ' {
+            line_start = chr == b'\n';
+            pos += 1;
+            continue;
+        }
+        let start = pos;
+        while pos < text.len() && text[pos] > b' ' {
+            pos += 1;
+        }
+        if line_start || out.is_empty() {
+            out.push(Vec::new());
+        }
+        out.last_mut().unwrap().push(Span::new(start, pos));
+        line_start = false;
+    }
+    out
+}
+
+/// Parses a step line, whose first token has the form `step:hyps:ref`.
+fn parse_step<'a>(text: &'a [u8], tokens: &[Span]) -> Option<StepLine<'a>> {
+    let head = tokens[0];
+    let start = head.start as usize;
+    let fields: Vec<&[u8]> = head.as_ref(text).split(|&chr| chr == b':').collect();
+    if fields.len() != 3 || fields[0].is_empty() || fields[2].is_empty() || tokens.len() < 2 {
+        return None;
+    }
+    let hyps_start = start + fields[0].len() + 1;
+    let hyps_span = Span::new(hyps_start, hyps_start + fields[1].len());
+    let mut hyps = Vec::new();
+    if !fields[1].is_empty() {
+        let mut pos = hyps_start;
+        for hyp in fields[1].split(|&chr| chr == b',') {
+            hyps.push(Span::new(pos, pos + hyp.len()));
+            pos += hyp.len() + 1;
+        }
+    }
+    Some(StepLine {
+        name: Span::new(start, start + fields[0].len()),
+        hyps_span,
+        hyps,
+        label: Span::new(hyps_span.end as usize + 1, head.end as usize),
+        formula: tokens[1..].iter().map(|span| span.as_ref(text)).collect(),
+        formula_span: Span::new(
+            tokens[1].start as usize,
+            tokens[tokens.len() - 1].end as usize,
+        ),
+    })
+}
+
+/// Finds the `$p` statement named in the `THEOREM=` field of the worksheet
+/// header.
+fn parse_header<'a>(
+    sset: &'a SegmentSet,
+    nset: &Nameset,
+    scope: &'a ScopeResult,
+    text: &[u8],
+    header: &[Span],
+) -> Result<(StatementRef<'a>, &'a Frame), Diagnostic> {
+    let bad_header = Diagnostic::MmpBadHeader(header[0]);
+    if header.len() < 4
+        || header[0].as_ref(text) != b"$("
+        || header[1].as_ref(text) != b"<MM>"
+        || header[2].as_ref(text) != b"<PROOF_ASST>"
+    {
+        return Err(bad_header);
+    }
+    let field = header[3];
+    if !field.as_ref(text).starts_with(b"THEOREM=") {
+        return Err(bad_header);
+    }
+    let label = Span::new(field.start as usize + 8, field.end as usize);
+    let unknown = Diagnostic::MmpUnknownTheorem(label);
+    let stmt = match nset.lookup_label(label.as_ref(text)) {
+        Some(lookup) => sset.statement(lookup.address),
+        None => return Err(unknown),
+    };
+    if stmt.statement_type() != StatementType::Provable {
+        return Err(unknown);
+    }
+    scope
+        .get(stmt.label())
+        .map(|frame| (stmt, frame))
+        .ok_or(unknown)
+}
+
+/// Reads an mmp file and rebuilds the proof of the theorem it describes.
+///
+/// Returns the address of the theorem and its checked proof.  Problems with
+/// the worksheet are reported with spans pointing into `text`; as many steps
+/// as possible are checked before giving up, but steps which depend on a step
+/// with an error are not reported again.  If the steps are consistent but the
+/// proof is still rejected by the verifier (for instance because of a `$d`
+/// violation), the verifier's diagnostic is wrapped in `MmpProofInvalid`.
+pub fn import_mmp(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scope: &ScopeResult,
+    text: &[u8],
+) -> Result<(StatementAddress, ProofTreeArray), Vec<Diagnostic>> {
+    let chunks = chunks(text);
+    if chunks.is_empty() {
+        return Err(vec![Diagnostic::MmpBadHeader(Span::null())]);
+    }
+    let (stmt, thm_frame) =
+        parse_header(sset, nset, scope, text, &chunks[0]).map_err(|d| vec![d])?;
+
+    let logical_tc = nset.atom_name(thm_frame.target.typecode);
+    let mut importer = Importer::new(sset, nset, scope, thm_frame, logical_tc);
+    let mut errors = Vec::new();
+    let mut steps: HashMap<&[u8], Imported> = HashMap::new();
+    let mut qed = None;
+
+    for chunk in &chunks[1..] {
+        let first = chunk[0].as_ref(text);
+        if first.starts_with(b"*") || first.starts_with(b"$=") {
+            continue;
+        }
+        if first.starts_with(b"$)") {
+            break;
+        }
+        let step = match parse_step(text, chunk) {
+            Some(step) => step,
+            None => {
+                errors.push(Diagnostic::MmpBadStep(chunk[0]));
+                continue;
+            }
+        };
+        let name = step.name.as_ref(text);
+        if steps.contains_key(name) {
+            errors.push(Diagnostic::MmpDuplicateStep(step.name));
+            continue;
+        }
+        let proof = match importer.import_step(&steps, text, &step) {
+            Ok(proof) => proof,
+            Err(diag) => {
+                errors.extend(diag);
+                None
+            }
+        };
+        if name == b"qed" {
+            qed = Some((step.name, proof.clone()));
+        }
+        steps.insert(
+            name,
+            Imported {
+                formula: step.formula,
+                proof,
+            },
+        );
+    }
+
+    match qed {
+        None => errors.push(Diagnostic::MmpMissingQed(chunks[0][0])),
+        Some((span, Some(proof))) => {
+            if errors.is_empty() {
+                return ProofTreeArray::from_steps(sset, nset, scope, stmt, &proof)
+                    .map(|arr| (stmt.address(), arr))
+                    .map_err(|diag| vec![Diagnostic::MmpProofInvalid(span, Box::new(diag))]);
+            }
+        }
+        Some((_, None)) => {}
+    }
+    Err(errors)
+}
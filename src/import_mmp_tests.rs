@@ -0,0 +1,100 @@
+use database::Database;
+use database::DbOptions;
+use diag::Diagnostic;
+use diag::DiagnosticClass;
+use export;
+use import_mmp;
+use parser::Span;
+use proof::ProofStyle;
+use proof::ProofTreeArray;
+use test_util::mkdb;
+
+const DB: &str = "
+  $c 0 + = -> ( ) term wff |- $.
+  $v t r s P Q $.
+  tt $f term t $.
+  tr $f term r $.
+  ts $f term s $.
+  wp $f wff P $.
+  wq $f wff Q $.
+  tze $a term 0 $.
+  tpl $a term ( t + r ) $.
+  weq $a wff t = r $.
+  wim $a wff ( P -> Q ) $.
+  a1 $a |- ( t = r -> ( t = s -> r = s ) ) $.
+  a2 $a |- ( t + 0 ) = t $.
+  ${
+    min $e |- P $.
+    maj $e |- ( P -> Q ) $.
+    mp  $a |- Q $.
+  $}
+  th1 $p |- t = t $=
+    tt tze tpl tt weq tt tt weq tt a2 tt tze tpl tt weq tt tze tpl tt weq tt tt
+    weq wim tt a2 tt tze tpl tt tt a1 mp mp $.
+";
+
+fn export_th1(db: &mut Database) -> String {
+    let sset = db.parse_result().clone();
+    let nset = db.name_result().clone();
+    let scope = db.scope_result().clone();
+    let sref = sset.statement(nset.lookup_label(b"th1").unwrap().address);
+    let mut out = Vec::new();
+    export::export_mmp(&sset, &nset, &scope, sref, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn import(db: &mut Database, text: &str) -> Result<ProofTreeArray, Vec<Diagnostic>> {
+    let sset = db.parse_result().clone();
+    let nset = db.name_result().clone();
+    let scope = db.scope_result().clone();
+    import_mmp::import_mmp(&sset, &nset, &scope, text.as_bytes()).map(|(addr, arr)| {
+        assert_eq!(sset.statement(addr).label(), b"th1");
+        arr
+    })
+}
+
+#[test]
+fn test_mmp_roundtrip() {
+    let mut db = mkdb(DB, DbOptions::default());
+    let mmp = export_th1(&mut db);
+    let arr = import(&mut db, &mmp).unwrap();
+    let files = db
+        .rewrite_proofs(&[("th1".to_owned(), arr)], ProofStyle::Normal)
+        .unwrap();
+
+    let text = String::from_utf8(files[0].1.clone()).unwrap();
+    let mut db2 = mkdb(&text, DbOptions::default());
+    assert!(db2.diag_notations(vec![DiagnosticClass::Verify]).is_empty());
+    assert_eq!(
+        text.split_whitespace().collect::<Vec<_>>(),
+        DB.split_whitespace().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_mmp_errors() {
+    let mut db = mkdb(DB, DbOptions::default());
+    let mmp = export_th1(&mut db);
+
+    let bad = mmp.replacen("|- ( t + 0 ) = t", "|- ( t + 0 ) = 0", 1);
+    let pos = bad.find("= 0").unwrap();
+    match &import(&mut db, &bad).unwrap_err()[..] {
+        &[Diagnostic::MmpStepMismatch(span)] => {
+            assert!((span.start as usize) < pos && pos < span.end as usize)
+        }
+        diags => panic!("unexpected diagnostics {:?}", diags),
+    }
+
+    let bad = mmp.replacen("qed:", "5:", 1);
+    match &import(&mut db, &bad).unwrap_err()[..] {
+        &[Diagnostic::MmpMissingQed(_)] => {}
+        diags => panic!("unexpected diagnostics {:?}", diags),
+    }
+
+    let bad = mmp.replacen("THEOREM=th1", "THEOREM=a2", 1);
+    let pos = bad.find("a2").unwrap();
+    assert_eq!(
+        import(&mut db, &bad).unwrap_err(),
+        vec![Diagnostic::MmpUnknownTheorem(Span::new(pos, pos + 2))]
+    );
+}
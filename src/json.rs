@@ -0,0 +1,335 @@
+//! A minimal JSON representation, for the protocols and report formats which
+//! need it.
+//!
+//! Objects keep their members in insertion order, so that output is
+//! deterministic.  Numbers are stored as `f64`, which is exact for the line
+//! numbers and request IDs we deal in.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A JSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    /// `null`.
+    Null,
+    /// `true` or `false`.
+    Bool(bool),
+    /// Any number.
+    Number(f64),
+    /// A string.
+    String(String),
+    /// An array.
+    Array(Vec<Json>),
+    /// An object, as a list of members in order.
+    Object(Vec<(String, Json)>),
+}
+
+/// The error returned when parsing text which is not valid JSON, with the
+/// character offset where parsing failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct JsonError(pub usize);
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid JSON at character {}", self.0)
+    }
+}
+
+impl Json {
+    /// Constructs an object from a list of members.
+    pub fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect(),
+        )
+    }
+
+    /// Looks up a member of an object; returns `None` if this is not an object
+    /// or the member is missing.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref members) => members.iter().find(|m| m.0 == key).map(|m| &m.1),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if this is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if this is a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(num) => Some(num),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if this is a non-negative integer.
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64()
+            .filter(|&num| num >= 0.0 && num.fract() == 0.0)
+            .map(|num| num as usize)
+    }
+
+    /// Returns the elements if this is an array.
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match *self {
+            Json::Array(ref elems) => Some(elems),
+            _ => None,
+        }
+    }
+
+    /// Parses a JSON text.
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut parser = Parser {
+            chars: text.chars().peekable(),
+            pos: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_ws();
+        match parser.chars.peek() {
+            None => Ok(value),
+            Some(_) => Err(JsonError(parser.pos)),
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Json {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(value: u32) -> Json {
+        Json::Number(value as f64)
+    }
+}
+
+impl<'a> From<&'a str> for Json {
+    fn from(value: &'a str) -> Json {
+        Json::String(value.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Json {
+        Json::String(value)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(value: Vec<Json>) -> Json {
+        Json::Array(value)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, string: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for chr in string.chars() {
+        match chr {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            chr if (chr as u32) < 0x20 => write!(f, "\\u{:04x}", chr as u32)?,
+            chr => write!(f, "{}", chr)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Serializes compactly, with no whitespace between tokens.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(num) if num.is_finite() => write!(f, "{}", num),
+            Json::Number(_) => f.write_str("null"),
+            Json::String(ref string) => write_string(f, string),
+            Json::Array(ref elems) => {
+                f.write_str("[")?;
+                for (ix, elem) in elems.iter().enumerate() {
+                    if ix > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", elem)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(ref members) => {
+                f.write_str("{")?;
+                for (ix, (key, value)) in members.iter().enumerate() {
+                    if ix > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// Recursive descent parser state.
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<char> {
+        self.pos += 1;
+        self.chars.next()
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(&chr) = self.chars.peek() {
+            if !" \t\r\n".contains(chr) {
+                break;
+            }
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), JsonError> {
+        for chr in word.chars() {
+            if self.next() != Some(chr) {
+                return Err(JsonError(self.pos));
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_ws();
+        match self.chars.peek().cloned() {
+            Some('n') => self.expect("null").map(|_| Json::Null),
+            Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('[') => {
+                self.next();
+                let mut elems = Vec::new();
+                self.skip_ws();
+                if self.chars.peek() == Some(&']') {
+                    self.next();
+                    return Ok(Json::Array(elems));
+                }
+                loop {
+                    elems.push(self.value()?);
+                    self.skip_ws();
+                    match self.next() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Json::Array(elems)),
+                        _ => return Err(JsonError(self.pos)),
+                    }
+                }
+            }
+            Some('{') => {
+                self.next();
+                let mut members = Vec::new();
+                self.skip_ws();
+                if self.chars.peek() == Some(&'}') {
+                    self.next();
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_ws();
+                    let key = self.string()?;
+                    self.skip_ws();
+                    self.expect(":")?;
+                    members.push((key, self.value()?));
+                    self.skip_ws();
+                    match self.next() {
+                        Some(',') => {}
+                        Some('}') => return Ok(Json::Object(members)),
+                        _ => return Err(JsonError(self.pos)),
+                    }
+                }
+            }
+            Some(chr) if chr == '-' || chr.is_ascii_digit() => {
+                let mut text = String::new();
+                while let Some(&chr) = self.chars.peek() {
+                    if !(chr.is_ascii_digit() || "+-.eE".contains(chr)) {
+                        break;
+                    }
+                    text.push(chr);
+                    self.next();
+                }
+                text.parse()
+                    .map(Json::Number)
+                    .map_err(|_| JsonError(self.pos))
+            }
+            _ => Err(JsonError(self.pos)),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()
+                .and_then(|chr| chr.to_digit(16))
+                .ok_or(JsonError(self.pos))?;
+            value = value * 16 + digit;
+        }
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect("\"")?;
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                None => return Err(JsonError(self.pos)),
+                Some('"') => return Ok(out),
+                Some('\\') => {
+                    let chr = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let mut code = self.hex4()?;
+                            if (0xD800..0xDC00).contains(&code) {
+                                // a surrogate pair
+                                self.expect("\\u")?;
+                                let low = self.hex4()?;
+                                code = 0x10000
+                                    + ((code - 0xD800) << 10)
+                                    + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            ::std::char::from_u32(code).unwrap_or('\u{FFFD}')
+                        }
+                        _ => return Err(JsonError(self.pos)),
+                    };
+                    out.push(chr);
+                }
+                Some(chr) => out.push(chr),
+            }
+        }
+    }
+}
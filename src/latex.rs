@@ -0,0 +1,148 @@
+//! Export of statements and proofs as LaTeX.
+//!
+//! Math symbols are typeset with the `latexdef` definitions from the `$t`
+//! comments; symbols without a definition are written as text using `\text`
+//! from `amsmath`.  The output is a fragment to be `\input` into a document:
+//! the hypotheses and assertion are displayed equations, followed for a `$p`
+//! statement by a `tabular` environment with one row per logical step of the
+//! proof.
+
+use export::ExportError;
+use nameck::Nameset;
+use parser::StatementRef;
+use parser::StatementType;
+use proof::ProofTreeArray;
+use scopeck::Hyp;
+use scopeck::ScopeResult;
+use segment_set::SegmentSet;
+use std::io::Write;
+use typesetting::TypesettingKind;
+use typesetting::TypesettingResult;
+
+/// Escapes text for inclusion in LaTeX outside math mode.
+fn escape(text: &[u8]) -> String {
+    let mut out = String::new();
+    for chr in String::from_utf8_lossy(text).chars() {
+        match chr {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            '|' => out.push_str("\\textbar{}"),
+            '<' => out.push_str("\\textless{}"),
+            '>' => out.push_str("\\textgreater{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(chr);
+            }
+            _ => out.push(chr),
+        }
+    }
+    out
+}
+
+/// Typesets a math string as the contents of a math-mode environment.
+pub fn latex_math<'a, I: IntoIterator<Item = &'a [u8]>>(
+    table: &TypesettingResult,
+    tokens: I,
+) -> String {
+    let mut out = String::new();
+    for token in tokens {
+        match table.symbol(TypesettingKind::Latex, token) {
+            Some(def) => out.push_str(&String::from_utf8_lossy(&def.value)),
+            None => {
+                out.push_str("\\text{");
+                out.push_str(&escape(token));
+                out.push('}');
+            }
+        }
+        out.push(' ');
+    }
+    out.trim_end().to_owned()
+}
+
+/// Typesets the math string of a statement.
+fn statement_math(table: &TypesettingResult, stmt: StatementRef) -> String {
+    latex_math(table, stmt.math_iter().map(|tref| tref.slice))
+}
+
+/// Writes the proof of a theorem as a `tabular` environment.
+fn write_proof<W: Write>(
+    out: &mut W,
+    sset: &SegmentSet,
+    table: &TypesettingResult,
+    stmt: StatementRef,
+    arr: &ProofTreeArray,
+) -> Result<(), ExportError> {
+    let provable_tc = stmt.math_at(0).slice;
+    let indent = arr.indent();
+
+    // maps each tree to its 1-based step number, or 0 for syntax steps
+    let mut steps = vec![0; arr.trees.len()];
+    let mut count = 0;
+    writeln!(out, "\\begin{{tabular}}{{rlll}}")?;
+    writeln!(out, "Step & Hyp & Ref & Expression \\\\ \\hline")?;
+    for (ix, tree) in arr.trees.iter().enumerate() {
+        let step = sset.statement(tree.address);
+        if step.math_len() == 0 || step.math_at(0).slice != provable_tc {
+            continue;
+        }
+        count += 1;
+        steps[ix] = count;
+
+        let hyps = tree
+            .children
+            .iter()
+            .filter(|&&child| steps[child] != 0)
+            .map(|&child| steps[child].to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let expr = arr.exprs[ix]
+            .split(|&chr| chr == b' ')
+            .filter(|token| !token.is_empty());
+        writeln!(
+            out,
+            "{} & {} & {} & \\hspace{{{}em}}${}$ \\\\",
+            count,
+            hyps,
+            escape(step.label()),
+            indent[ix],
+            latex_math(table, Some(provable_tc).into_iter().chain(expr))
+        )?;
+    }
+    writeln!(out, "\\end{{tabular}}")?;
+    Ok(())
+}
+
+/// Export a LaTeX fragment for a given statement, with its proof if it is a
+/// `$p` statement.
+pub fn export_latex<W: Write>(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scope: &ScopeResult,
+    table: &TypesettingResult,
+    stmt: StatementRef,
+    out: &mut W,
+) -> Result<(), ExportError> {
+    writeln!(out, "% {}", String::from_utf8_lossy(stmt.label()))?;
+    writeln!(out, "\\noindent\\textbf{{{}}}", escape(stmt.label()))?;
+    if let Some(frame) = scope.get(stmt.label()) {
+        for hyp in &frame.hypotheses {
+            if let Hyp::Essential(addr, _) = *hyp {
+                let hyp = sset.statement(addr);
+                writeln!(
+                    out,
+                    "\\[ {} \\quad \\textrm{{({})}} \\]",
+                    statement_math(table, hyp),
+                    escape(hyp.label())
+                )?;
+            }
+        }
+    }
+    writeln!(out, "\\[ {} \\]", statement_math(table, stmt))?;
+
+    if stmt.statement_type() == StatementType::Provable {
+        let arr = ProofTreeArray::new(sset, nset, scope, stmt)?;
+        write_proof(out, sset, table, stmt, &arr)?;
+    }
+    Ok(())
+}
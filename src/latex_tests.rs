@@ -0,0 +1,80 @@
+use database::Database;
+use database::DbOptions;
+use latex;
+use test_util::mkdb;
+
+fn render(db: &mut Database, label: &str) -> String {
+    let parse = db.parse_result().clone();
+    let scope = db.scope_result().clone();
+    let name = db.name_result().clone();
+    let table = db.typesetting_result().clone();
+    let sref = db.statement(label).unwrap();
+    let mut out = Vec::new();
+    latex::export_latex(&parse, &name, &scope, &table, sref, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_latex_statement() {
+    let mut db = mkdb(
+        "$c wff |- ( -> ) $.
+         $v ph ps $.
+         $( $t latexdef \"->\" as \"\\rightarrow\";
+               latexdef \"|-\" as \"\\vdash\"; $)
+         wph $f wff ph $.
+         wps $f wff ps $.
+         wi $a wff ( ph -> ps ) $.
+         ${
+           mp_1 $e |- ph $.
+           mp_2 $e |- ( ph -> ps ) $.
+           ax-mp $a |- ps $.
+         $}",
+        DbOptions::default(),
+    );
+    assert_eq!(
+        render(&mut db, "ax-mp"),
+        "% ax-mp\n\
+         \\noindent\\textbf{ax-mp}\n\
+         \\[ \\vdash \\text{ph} \\quad \\textrm{(mp\\_1)} \\]\n\
+         \\[ \\vdash \\text{(} \\text{ph} \\rightarrow \\text{ps} \\text{)} \
+         \\quad \\textrm{(mp\\_2)} \\]\n\
+         \\[ \\vdash \\text{ps} \\]\n"
+    );
+}
+
+#[test]
+fn test_latex_proof() {
+    let mut db = mkdb(
+        "$c wff |- ( -> ) $.
+         $v ph ps $.
+         $( $t latexdef \"->\" as \"\\to\"; $)
+         wph $f wff ph $.
+         wps $f wff ps $.
+         wi $a wff ( ph -> ps ) $.
+         ${
+           mp.1 $e |- ph $.
+           mp.2 $e |- ( ph -> ps ) $.
+           ax-mp $a |- ps $.
+         $}
+         ${
+           mp2.1 $e |- ph $.
+           mp2.2 $e |- ( ph -> ps ) $.
+           mp2 $p |- ps $= ( ax-mp ) ABCDE $.
+         $}",
+        DbOptions::default(),
+    );
+    let out = render(&mut db, "mp2");
+    let table = out
+        .split("\\begin{tabular}{rlll}\n")
+        .nth(1)
+        .expect("proof table");
+    assert_eq!(
+        table,
+        "Step & Hyp & Ref & Expression \\\\ \\hline\n\
+         1 &  & mp2.1 & \\hspace{1em}$\\text{\\textbar{}-} \\text{ph}$ \\\\\n\
+         2 &  & mp2.2 & \\hspace{1em}$\\text{\\textbar{}-} \\text{(} \\text{ph} \\to \
+         \\text{ps} \\text{)}$ \\\\\n\
+         3 & 1, 2 & ax-mp & \\hspace{0em}$\\text{\\textbar{}-} \\text{ps}$ \\\\\n\
+         \\end{tabular}\n"
+    );
+}
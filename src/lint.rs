@@ -0,0 +1,344 @@
+//! A framework for optional style checks.
+//!
+//! Each lint is a trait object which is shown every statement of the database
+//! in turn, along with its frame if it has one, and may report diagnostics
+//! against the statement.  Lints cannot make a database invalid, so they are
+//! all warnings, and are only run when asked for by name.  Segments are linted
+//! in parallel, so lints must be stateless.  Lints read names and frames
+//! without recording which, so no result can be reused, and every segment is
+//! linted again whenever the result is recalculated.
+//!
+//! The lints provided are:
+//!
+//! * `label-convention`: `$a` statements with the provable typecode, usually
+//!   `|-`, should have labels starting with `ax-` or the definition prefix, and
+//!   no other statement should.
+//! * `obsolete-tags`: theorems whose labels end in `OLD` or `ALT` are obsolete
+//!   or alternative versions kept for reference, and should be tagged `(Proof
+//!   modification is discouraged.)` and `(New usage is discouraged.)`.
+//! * `unused-dv-variable`: every variable of a `$d` statement in a group should
+//!   be used by some later `$a` or `$p` statement of the group, as a mandatory
+//!   variable or as a dummy variable of a proof.
+//! * `unused-float`: the variable of a `$f` statement in a group should be used
+//!   by some later statement of the group.
+//!
+//! Top-level `$d` and `$f` statements are in scope for the rest of the
+//! database, and are not checked.
+
+use diag::Diagnostic;
+use discouraged;
+use nameck::Atom;
+use nameck::Nameset;
+use parser::SegmentId;
+use parser::StatementAddress;
+use parser::StatementRef;
+use parser::StatementType;
+use parser::TokenIndex;
+use scopeck::Frame;
+use scopeck::ScopeResult;
+use segment_set::SegmentSet;
+use std::sync::Arc;
+use util::new_set;
+use util::HashSet;
+use xref;
+
+/// Label prefix of axioms which are not definitions.
+const AXIOM_PREFIX: &[u8] = b"ax-";
+
+/// Tag for theorems whose proofs should be left as they are.
+const PROOF_MODIFICATION_TAG: &[u8] = b"(Proof modification is discouraged.)";
+
+/// Label suffixes of theorems which must carry both discouraged tags.
+const OBSOLETE_SUFFIXES: &[&[u8]] = &[b"OLD", b"ALT"];
+
+/// Database information available to lints.
+pub struct LintContext<'a> {
+    /// The parsed database.
+    pub sset: &'a SegmentSet,
+    /// Names of the database.
+    pub nset: &'a Nameset,
+    /// Frames of the database.
+    pub scopes: &'a ScopeResult,
+    /// Label prefix of definitions.
+    pub definition_prefix: &'a str,
+    /// The typecode of logical statements, as declared by a `$j` command.
+    pub provable_typecode: &'a [u8],
+}
+
+/// A style check which visits each statement of the database.
+pub trait Lint: Send + Sync {
+    /// The name which enables the lint.
+    fn name(&self) -> &'static str;
+
+    /// Checks a statement, whose frame is given if it is a `$a`, `$p` or `$f`
+    /// statement, adding any diagnostics to `out`.
+    fn check(
+        &self,
+        cx: &LintContext,
+        stmt: StatementRef,
+        frame: Option<&Frame>,
+        out: &mut Vec<Diagnostic>,
+    );
+}
+
+/// Returns every lint which can be enabled.
+pub fn all_lints() -> Vec<Arc<dyn Lint>> {
+    vec![
+        Arc::new(LabelConvention),
+        Arc::new(ObsoleteTags),
+        Arc::new(UnusedDvVariable),
+        Arc::new(UnusedFloat),
+    ]
+}
+
+/// Returns the lints with the given names, or every lint if none are given;
+/// unknown names are ignored.
+pub fn lints_named(names: &[String]) -> Vec<Arc<dyn Lint>> {
+    let mut lints = all_lints();
+    if !names.is_empty() {
+        lints.retain(|lint| names.iter().any(|name| name == lint.name()));
+    }
+    lints
+}
+
+/// Returns the statements after a statement up to the end of its group, or
+/// nothing if it is not in a group.
+fn rest_of_group<'a>(stmt: StatementRef<'a>) -> impl Iterator<Item = StatementRef<'a>> {
+    let sref = stmt.segment();
+    let end = if stmt.in_group() {
+        stmt.scope_range().end
+    } else {
+        stmt.index()
+    };
+    (stmt.index() + 1..end).map(move |index| sref.statement(index))
+}
+
+/// Returns the variables of the `$f` statements cited by a proof.
+fn proof_variables<'a>(cx: &LintContext<'a>, stmt: StatementRef<'a>) -> Vec<&'a [u8]> {
+    xref::cited_labels(stmt)
+        .into_iter()
+        .filter_map(|label| cx.nset.lookup_label(label))
+        .map(|lookup| cx.sset.statement(lookup.address))
+        .filter(|hyp| hyp.statement_type() == StatementType::Floating && hyp.math_len() == 2)
+        .map(|hyp| hyp.math_at(1).slice)
+        .collect()
+}
+
+/// Lint for the label prefixes of axioms.
+struct LabelConvention;
+
+impl Lint for LabelConvention {
+    fn name(&self) -> &'static str {
+        "label-convention"
+    }
+
+    fn check(
+        &self,
+        cx: &LintContext,
+        stmt: StatementRef,
+        _frame: Option<&Frame>,
+        out: &mut Vec<Diagnostic>,
+    ) {
+        let stype = stmt.statement_type();
+        if (stype != StatementType::Axiom && stype != StatementType::Provable)
+            || stmt.math_len() == 0
+        {
+            return;
+        }
+        let label = stmt.label();
+        let reserved =
+            label.starts_with(AXIOM_PREFIX) || label.starts_with(cx.definition_prefix.as_bytes());
+        let logical_axiom =
+            stype == StatementType::Axiom && stmt.math_at(0).slice == cx.provable_typecode;
+        if logical_axiom && !reserved {
+            out.push(Diagnostic::LintAxiomLabel);
+        } else if !logical_axiom && reserved {
+            out.push(Diagnostic::LintReservedLabel);
+        }
+    }
+}
+
+/// Lint for the tags of obsolete and alternative theorems.
+struct ObsoleteTags;
+
+impl Lint for ObsoleteTags {
+    fn name(&self) -> &'static str {
+        "obsolete-tags"
+    }
+
+    fn check(
+        &self,
+        _cx: &LintContext,
+        stmt: StatementRef,
+        _frame: Option<&Frame>,
+        out: &mut Vec<Diagnostic>,
+    ) {
+        if stmt.statement_type() != StatementType::Provable
+            || !OBSOLETE_SUFFIXES
+                .iter()
+                .any(|suffix| stmt.label().ends_with(suffix))
+        {
+            return;
+        }
+        if !discouraged::has_tag(stmt, PROOF_MODIFICATION_TAG) {
+            out.push(Diagnostic::LintMissingModificationTag);
+        }
+        if !discouraged::has_tag(stmt, discouraged::NEW_USAGE_TAG) {
+            out.push(Diagnostic::LintMissingUsageTag);
+        }
+    }
+}
+
+/// Lint for variables of `$d` statements which are never used.
+struct UnusedDvVariable;
+
+impl Lint for UnusedDvVariable {
+    fn name(&self) -> &'static str {
+        "unused-dv-variable"
+    }
+
+    fn check(
+        &self,
+        cx: &LintContext,
+        stmt: StatementRef,
+        _frame: Option<&Frame>,
+        out: &mut Vec<Diagnostic>,
+    ) {
+        if stmt.statement_type() != StatementType::Disjoint || !stmt.in_group() {
+            return;
+        }
+        // the variables used by the rest of the group, collected in one scan
+        let mut used: HashSet<Atom> = new_set();
+        for user in rest_of_group(stmt) {
+            let stype = user.statement_type();
+            if stype != StatementType::Axiom && stype != StatementType::Provable {
+                continue;
+            }
+            if let Some(frame) = cx.scopes.get(user.label()) {
+                used.extend(&frame.var_list[..frame.mandatory_count]);
+            }
+            if stype == StatementType::Provable {
+                used.extend(
+                    proof_variables(cx, user)
+                        .into_iter()
+                        .filter_map(|var| cx.nset.lookup_symbol(var))
+                        .map(|lookup| lookup.atom),
+                );
+            }
+        }
+        for (index, token) in stmt.math_iter().enumerate() {
+            if let Some(lookup) = cx.nset.lookup_symbol(token.slice) {
+                if !used.contains(&lookup.atom) {
+                    out.push(Diagnostic::LintUnusedDvVariable(index as TokenIndex));
+                }
+            }
+        }
+    }
+}
+
+/// Lint for `$f` statements whose variable is never used.
+struct UnusedFloat;
+
+impl Lint for UnusedFloat {
+    fn name(&self) -> &'static str {
+        "unused-float"
+    }
+
+    fn check(
+        &self,
+        cx: &LintContext,
+        stmt: StatementRef,
+        frame: Option<&Frame>,
+        out: &mut Vec<Diagnostic>,
+    ) {
+        if frame.is_none() || stmt.statement_type() != StatementType::Floating || !stmt.in_group() {
+            return;
+        }
+        let var = stmt.math_at(1).slice;
+        let used = rest_of_group(stmt).any(|user| match user.statement_type() {
+            StatementType::Essential | StatementType::Axiom => {
+                user.math_iter().any(|token| token.slice == var)
+            }
+            StatementType::Provable => {
+                user.math_iter().any(|token| token.slice == var)
+                    || proof_variables(cx, user).contains(&var)
+            }
+            _ => false,
+        });
+        if !used {
+            out.push(Diagnostic::LintUnusedFloat);
+        }
+    }
+}
+
+/// Analysis pass result for the lints.
+#[derive(Default, Clone)]
+pub struct LintResult {
+    diagnostics: Vec<(StatementAddress, Diagnostic)>,
+}
+
+impl LintResult {
+    /// Report the findings of the lints.
+    pub fn diagnostics(&self) -> Vec<(StatementAddress, Diagnostic)> {
+        self.diagnostics.clone()
+    }
+}
+
+/// Runs lints over the statements of a segment.
+fn lint_segment(
+    cx: &LintContext,
+    lints: &[Arc<dyn Lint>],
+    sid: SegmentId,
+) -> Vec<(StatementAddress, Diagnostic)> {
+    let mut out = Vec::new();
+    let mut diags = Vec::new();
+    for stmt in cx.sset.segment(sid) {
+        let frame = match stmt.statement_type() {
+            StatementType::Axiom | StatementType::Provable | StatementType::Floating => {
+                cx.scopes.get(stmt.label())
+            }
+            _ => None,
+        };
+        for lint in lints {
+            lint.check(cx, stmt, frame, &mut diags);
+        }
+        out.extend(diags.drain(..).map(|diag| (stmt.address(), diag)));
+    }
+    out
+}
+
+/// Runs lints over a database.
+pub fn lint(
+    result: &mut LintResult,
+    segments: &Arc<SegmentSet>,
+    nset: &Arc<Nameset>,
+    scopes: &Arc<ScopeResult>,
+    lints: &[Arc<dyn Lint>],
+    definition_prefix: &str,
+    provable_typecode: &[u8],
+) {
+    let mut dq = Vec::new();
+    for sref in segments.segments() {
+        let segments2 = segments.clone();
+        let nset = nset.clone();
+        let scopes = scopes.clone();
+        let lints = lints.to_vec();
+        let prefix = definition_prefix.to_owned();
+        let typecode = provable_typecode.to_owned();
+        let id = sref.id;
+        dq.push(segments.exec.exec(sref.bytes(), move || {
+            let cx = LintContext {
+                sset: &segments2,
+                nset: &nset,
+                scopes: &scopes,
+                definition_prefix: &prefix,
+                provable_typecode: &typecode,
+            };
+            lint_segment(&cx, &lints, id)
+        }));
+    }
+    result.diagnostics.clear();
+    for promise in dq {
+        result.diagnostics.extend(promise.wait());
+    }
+}
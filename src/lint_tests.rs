@@ -0,0 +1,94 @@
+use database::DbOptions;
+use diag::Diagnostic;
+use test_util::mkdb;
+
+const DB: &str = "$c wff |- ( -> ) $.
+    $v ph ps ch th $.
+    wph $f wff ph $.
+    wps $f wff ps $.
+    wi $a wff ( ph -> ps ) $.
+    ax-1 $a |- ( ph -> ( ps -> ph ) ) $.
+    simp $a |- ( ph -> ph ) $.
+    df-th $p |- ( ph -> ( ps -> ph ) ) $= wph wps ax-1 $.
+    $( Missing a tag.  (New usage is discouraged.) $)
+    th1OLD $p |- ( ph -> ( ps -> ph ) ) $= wph wps ax-1 $.
+    $( Complete.  (Proof modification is discouraged.)
+       (New usage is discouraged.) $)
+    th1ALT $p |- ( ph -> ( ps -> ph ) ) $= wph wps ax-1 $.
+    ${
+      $d ph ps ch $.
+      wch $f wff ch $.
+      wth $f wff th $.
+      th1 $p |- ( ph -> ( ps -> ph ) ) $= wph wps ax-1 $.
+      th2 $p |- ( ph -> ( ph -> ph ) ) $= wph wph ax-1 $.
+    $}";
+
+fn check_text(text: &str, lints: &[&str]) -> Vec<(String, Diagnostic)> {
+    let mut db = mkdb(
+        text,
+        DbOptions {
+            lints: lints.iter().map(|&name| name.to_owned()).collect(),
+            ..DbOptions::default()
+        },
+    );
+    let diags = db.lint_result().diagnostics();
+    let sset = db.parse_result();
+    diags
+        .into_iter()
+        .map(|(addr, diag)| {
+            let stmt = sset.statement(addr);
+            let label = match diag {
+                Diagnostic::LintUnusedDvVariable(index) => stmt.math_at(index).slice,
+                _ => stmt.label(),
+            };
+            (String::from_utf8_lossy(label).into_owned(), diag)
+        })
+        .collect()
+}
+
+fn check(lints: &[&str]) -> Vec<(String, Diagnostic)> {
+    check_text(DB, lints)
+}
+
+#[test]
+fn test_lints() {
+    assert_eq!(
+        check(&["label-convention"]),
+        vec![
+            ("simp".to_owned(), Diagnostic::LintAxiomLabel),
+            ("df-th".to_owned(), Diagnostic::LintReservedLabel),
+        ]
+    );
+    assert_eq!(
+        check(&["obsolete-tags"]),
+        vec![("th1OLD".to_owned(), Diagnostic::LintMissingModificationTag)]
+    );
+    // ch is not used by either theorem, and th is not used at all
+    assert_eq!(
+        check(&["unused-dv-variable", "unused-float"]),
+        vec![
+            ("ch".to_owned(), Diagnostic::LintUnusedDvVariable(2)),
+            ("wch".to_owned(), Diagnostic::LintUnusedFloat),
+            ("wth".to_owned(), Diagnostic::LintUnusedFloat),
+        ]
+    );
+    assert_eq!(check(&[]).len(), 6);
+}
+
+#[test]
+fn test_declared_provable_typecode() {
+    // without a declaration, `|=` is taken as a syntax typecode
+    let text = DB.replace("|-", "|=");
+    assert_eq!(
+        check_text(&text, &["label-convention"]),
+        vec![
+            ("ax-1".to_owned(), Diagnostic::LintReservedLabel),
+            ("df-th".to_owned(), Diagnostic::LintReservedLabel),
+        ]
+    );
+    let text = text + "\n$( $j syntax 'wff'; syntax '|=' as 'wff'; $)";
+    assert_eq!(
+        check_text(&text, &["label-convention"]),
+        check(&["label-convention"])
+    );
+}
@@ -0,0 +1,379 @@
+//! A Language Server Protocol server for Metamath databases.
+//!
+//! The server speaks JSON-RPC over a pair of streams, normally stdin and
+//! stdout, and keeps a single incremental `Database` alive for the session.
+//! The text of each open document is supplied to `Database::parse` in place of
+//! the file on disk, so after every change only the affected segments are
+//! reprocessed, and the diagnostics of every file are pushed to the client
+//! with `textDocument/publishDiagnostics`.  Go-to-definition is answered for
+//! labels and math symbols.
+//!
+//! File names in the database are resolved relative to the current directory,
+//! so the server should be started in the root directory of the database;
+//! documents are passed to the database under their path relative to it.
+//! Columns are counted in bytes, which agrees with the protocol's UTF-16
+//! columns for the ASCII text Metamath allows.
+
+use database::Database;
+use diag::DiagnosticClass;
+use diag::Level;
+use json::Json;
+use line_cache::LineCache;
+use parser::Span;
+use segment_set::SegmentSet;
+use segment_set::SourceInfo;
+use std::env;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// JSON-RPC error code for unparseable messages.
+const PARSE_ERROR: i32 = -32700;
+/// JSON-RPC error code for unsupported requests.
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// State of a language server session.
+pub struct Server<W: Write> {
+    db: Database,
+    /// Directory against which file names are resolved.
+    root: PathBuf,
+    /// Name of the main database file; if unset, the first document opened.
+    start: Option<String>,
+    /// Name and text of every open document.
+    open: Vec<(String, Vec<u8>)>,
+    /// URIs of the files for which diagnostics were last published.
+    published: Vec<String>,
+    out: W,
+}
+
+/// Reads a message with its `Content-Length` header; returns `None` at end of
+/// input.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::new();
+    let mut ix = 0;
+    while ix < bytes.len() {
+        let hex = bytes
+            .get(ix + 1..ix + 3)
+            .and_then(|hex| u8::from_str_radix(&String::from_utf8_lossy(hex), 16).ok());
+        match (bytes[ix], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                ix += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                ix += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut out = String::new();
+    for &byte in text.as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
+
+/// Finds the whitespace-delimited token containing or ending at an offset.
+fn token_at(text: &[u8], offset: usize) -> &[u8] {
+    let offset = offset.min(text.len());
+    let mut start = offset;
+    while start > 0 && !text[start - 1].is_ascii_whitespace() {
+        start -= 1;
+    }
+    let mut end = offset;
+    while end < text.len() && !text[end].is_ascii_whitespace() {
+        end += 1;
+    }
+    &text[start..end]
+}
+
+/// Converts a 0-based line and column to a byte offset.
+fn position_to_offset(text: &[u8], line: usize, character: usize) -> Option<usize> {
+    let mut start = 0;
+    for _ in 0..line {
+        start += text[start..].iter().position(|&chr| chr == b'\n')? + 1;
+    }
+    Some((start + character).min(LineCache::line_end(text, start)))
+}
+
+/// Converts a span relative to a source slice into a protocol range.
+fn range(lc: &mut LineCache, source: &SourceInfo, span: Span) -> Json {
+    let mut position = |pos: usize| {
+        let (row, col) = lc.from_offset(&source.text, pos + source.span.start as usize);
+        Json::object(vec![
+            ("line", (row - 1).into()),
+            ("character", (col - 1).into()),
+        ])
+    };
+    Json::object(vec![
+        ("start", position(span.start as usize)),
+        ("end", position(span.end as usize)),
+    ])
+}
+
+impl<W: Write> Server<W> {
+    /// Creates a server which will load `start`, or the first document opened
+    /// if `None`, and write its messages to `out`.
+    pub fn new(db: Database, start: Option<String>, out: W) -> io::Result<Server<W>> {
+        Ok(Server {
+            db,
+            root: env::current_dir()?,
+            start,
+            open: Vec::new(),
+            published: Vec::new(),
+            out,
+        })
+    }
+
+    /// Handles messages until the client sends `exit` or closes the input.
+    pub fn run<R: BufRead>(&mut self, mut input: R) -> io::Result<()> {
+        while let Some(body) = read_message(&mut input)? {
+            let text = String::from_utf8_lossy(&body);
+            match Json::parse(&text) {
+                Ok(msg) => {
+                    if !self.handle(&msg)? {
+                        break;
+                    }
+                }
+                Err(_) => self.error(Json::Null, PARSE_ERROR, "Invalid JSON")?,
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, msg: Json) -> io::Result<()> {
+        let body = msg.to_string();
+        write!(self.out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.out.flush()
+    }
+
+    fn reply(&mut self, id: Json, result: Json) -> io::Result<()> {
+        self.send(Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id),
+            ("result", result),
+        ]))
+    }
+
+    fn error(&mut self, id: Json, code: i32, message: &str) -> io::Result<()> {
+        let error = Json::object(vec![
+            ("code", Json::Number(code as f64)),
+            ("message", message.into()),
+        ]);
+        self.send(Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id),
+            ("error", error),
+        ]))
+    }
+
+    /// Handles one message; returns false if the session is over.
+    fn handle(&mut self, msg: &Json) -> io::Result<bool> {
+        let method = msg.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = msg.get("params").cloned().unwrap_or(Json::Null);
+        let id = msg.get("id").cloned();
+        let doc = params.get("textDocument");
+        let uri = doc.and_then(|doc| doc.get("uri")).and_then(Json::as_str);
+
+        match (method, id) {
+            ("exit", _) => return Ok(false),
+            ("initialize", Some(id)) => {
+                let capabilities = Json::object(vec![
+                    ("textDocumentSync", 1usize.into()),
+                    ("definitionProvider", true.into()),
+                ]);
+                let info = Json::object(vec![("name", "smetamath".into())]);
+                self.reply(
+                    id,
+                    Json::object(vec![("capabilities", capabilities), ("serverInfo", info)]),
+                )?;
+            }
+            ("shutdown", Some(id)) => self.reply(id, Json::Null)?,
+            ("textDocument/definition", Some(id)) => {
+                let result = self.definition(&params).unwrap_or(Json::Null);
+                self.reply(id, result)?;
+            }
+            ("textDocument/didOpen", None) => {
+                let text = doc.and_then(|doc| doc.get("text")).and_then(Json::as_str);
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    let name = self.uri_to_name(uri);
+                    self.set_document(name, Some(text.as_bytes().to_owned()));
+                    self.refresh()?;
+                }
+            }
+            ("textDocument/didChange", None) => {
+                // full synchronization: the last change holds the whole text
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str);
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    let name = self.uri_to_name(uri);
+                    self.set_document(name, Some(text.as_bytes().to_owned()));
+                    self.refresh()?;
+                }
+            }
+            ("textDocument/didClose", None) => {
+                if let Some(uri) = uri {
+                    let name = self.uri_to_name(uri);
+                    self.set_document(name, None);
+                    self.refresh()?;
+                }
+            }
+            (_, Some(id)) => self.error(id, METHOD_NOT_FOUND, "Unsupported request")?,
+            (_, None) => {}
+        }
+        Ok(true)
+    }
+
+    fn uri_to_name(&self, uri: &str) -> String {
+        let path = PathBuf::from(percent_decode(uri.strip_prefix("file://").unwrap_or(uri)));
+        match path.strip_prefix(&self.root) {
+            Ok(rel) => rel.to_string_lossy().into_owned(),
+            Err(_) => path.to_string_lossy().into_owned(),
+        }
+    }
+
+    fn name_to_uri(&self, name: &str) -> String {
+        let path = self.root.join(name);
+        format!("file://{}", percent_encode(&path.to_string_lossy()))
+    }
+
+    /// Sets or clears the in-memory text of a document.
+    fn set_document(&mut self, name: String, text: Option<Vec<u8>>) {
+        self.open.retain(|doc| doc.0 != name);
+        if let Some(text) = text {
+            self.open.push((name, text));
+        }
+    }
+
+    /// Reloads the database and publishes diagnostics for every file which has
+    /// them, or had them at the last reload.
+    fn refresh(&mut self) -> io::Result<()> {
+        let start = match self.start.clone() {
+            Some(start) => start,
+            None => match self.open.first() {
+                Some(doc) => doc.0.clone(),
+                None => return Ok(()),
+            },
+        };
+        self.db.parse(start, self.open.clone());
+        let notations = self.db.diag_notations(vec![
+            DiagnosticClass::Parse,
+            DiagnosticClass::Scope,
+            DiagnosticClass::Verify,
+        ]);
+
+        let mut lc = LineCache::default();
+        let mut files: Vec<(String, Vec<Json>)> = Vec::new();
+        for uri in &self.published {
+            files.push((uri.clone(), Vec::new()));
+        }
+        for notation in &notations {
+            let severity: usize = match notation.level {
+                Level::Error => 1,
+                Level::Warning => 2,
+                Level::Note => 3,
+            };
+            let diagnostic = Json::object(vec![
+                ("range", range(&mut lc, &notation.source, notation.span)),
+                ("severity", severity.into()),
+                ("source", "smetamath".into()),
+                ("message", notation.formatted_message().into()),
+            ]);
+            let uri = self.name_to_uri(&notation.source.name);
+            match files.iter_mut().find(|file| file.0 == uri) {
+                Some(file) => file.1.push(diagnostic),
+                None => files.push((uri, vec![diagnostic])),
+            }
+        }
+
+        self.published.clear();
+        for (uri, diagnostics) in files {
+            if !diagnostics.is_empty() {
+                self.published.push(uri.clone());
+            }
+            let params = Json::object(vec![
+                ("uri", uri.into()),
+                ("diagnostics", diagnostics.into()),
+            ]);
+            self.send(Json::object(vec![
+                ("jsonrpc", "2.0".into()),
+                ("method", "textDocument/publishDiagnostics".into()),
+                ("params", params),
+            ]))?;
+        }
+        Ok(())
+    }
+
+    /// Finds the declaration of the label or math symbol under the cursor.
+    fn definition(&mut self, params: &Json) -> Option<Json> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let position = params.get("position")?;
+        let line = position.get("line")?.as_usize()?;
+        let character = position.get("character")?.as_usize()?;
+        let name = self.uri_to_name(uri);
+
+        let sset: Arc<SegmentSet> = self.db.parse_result().clone();
+        let nset = self.db.name_result().clone();
+        let source = sset
+            .segments()
+            .iter()
+            .map(|sref| sset.source_info(sref.id))
+            .find(|source| source.name == name)?
+            .clone();
+        let offset = position_to_offset(&source.text, line, character)?;
+        let token = token_at(&source.text, offset);
+
+        let (address, span) = if let Some(lookup) = nset.lookup_label(token) {
+            let stmt = sset.statement(lookup.address);
+            let start = stmt.span().start as usize;
+            (lookup.address, Span::new(start, start + stmt.label().len()))
+        } else {
+            let address = nset.lookup_symbol(token)?.address;
+            let stmt = sset.statement(address.statement);
+            (address.statement, stmt.math_span(address.token_index))
+        };
+        let target = sset.source_info(address.segment_id).clone();
+        let mut lc = LineCache::default();
+        Some(Json::object(vec![
+            ("uri", self.name_to_uri(&target.name).into()),
+            ("range", range(&mut lc, &target, span)),
+        ]))
+    }
+}
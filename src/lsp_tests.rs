@@ -0,0 +1,165 @@
+use database::Database;
+use database::DbOptions;
+use json::Json;
+use lsp::Server;
+use std::env;
+use std::io::Cursor;
+
+fn frame(msg: Json) -> String {
+    let body = msg.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+fn notify(method: &str, params: Json) -> String {
+    frame(Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ]))
+}
+
+fn request(id: usize, method: &str, params: Json) -> String {
+    frame(Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id.into()),
+        ("method", method.into()),
+        ("params", params),
+    ]))
+}
+
+/// Runs a session and returns the messages sent by the server.
+fn session(input: &str) -> Vec<Json> {
+    let options = DbOptions {
+        incremental: true,
+        ..DbOptions::default()
+    };
+    let mut output = Vec::new();
+    Server::new(Database::new(options), None, &mut output)
+        .unwrap()
+        .run(Cursor::new(input.as_bytes()))
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    output
+        .split("Content-Length: ")
+        .skip(1)
+        .map(|msg| Json::parse(msg.split("\r\n\r\n").nth(1).unwrap()).unwrap())
+        .collect()
+}
+
+fn uri() -> String {
+    format!(
+        "file://{}/lsp-test.mm",
+        env::current_dir().unwrap().display()
+    )
+}
+
+fn open(text: &str) -> String {
+    let doc = Json::object(vec![
+        ("uri", uri().into()),
+        ("languageId", "metamath".into()),
+        ("version", 1usize.into()),
+        ("text", text.into()),
+    ]);
+    notify(
+        "textDocument/didOpen",
+        Json::object(vec![("textDocument", doc)]),
+    )
+}
+
+fn diagnostics(msg: &Json) -> Vec<String> {
+    assert_eq!(
+        msg.get("method").and_then(Json::as_str),
+        Some("textDocument/publishDiagnostics")
+    );
+    let params = msg.get("params").unwrap();
+    assert_eq!(params.get("uri").and_then(Json::as_str), Some(&uri()[..]));
+    params
+        .get("diagnostics")
+        .and_then(Json::as_array)
+        .unwrap()
+        .iter()
+        .map(|diag| {
+            diag.get("message")
+                .and_then(Json::as_str)
+                .unwrap()
+                .to_owned()
+        })
+        .collect()
+}
+
+#[test]
+fn test_json_roundtrip() {
+    let text = r#"{"a":[1,-2.5,true,null],"b":"x\"\né"}"#;
+    let value = Json::parse(text).unwrap();
+    assert_eq!(value.get("b").and_then(Json::as_str), Some("x\"\n\u{e9}"));
+    assert_eq!(Json::parse(&value.to_string()).unwrap(), value);
+    assert!(Json::parse("[1,]").is_err());
+}
+
+#[test]
+fn test_lsp_diagnostics() {
+    let change = |text: &str| {
+        let doc = Json::object(vec![("uri", uri().into()), ("version", 2usize.into())]);
+        let changes = vec![Json::object(vec![("text", text.into())])];
+        notify(
+            "textDocument/didChange",
+            Json::object(vec![
+                ("textDocument", doc),
+                ("contentChanges", changes.into()),
+            ]),
+        )
+    };
+    let input = request(1, "initialize", Json::object(vec![]))
+        + &open("$c A $. x $a A B $.")
+        + &change("$c A $. x $a A $.")
+        + &request(2, "shutdown", Json::Null)
+        + &notify("exit", Json::Null);
+    let out = session(&input);
+    assert_eq!(out.len(), 4);
+    let caps = out[0].get("result").unwrap().get("capabilities").unwrap();
+    assert_eq!(caps.get("definitionProvider"), Some(&Json::Bool(true)));
+    let first = diagnostics(&out[1]);
+    assert_eq!(first.len(), 1);
+    assert_eq!(
+        first[0],
+        "Token used here must be active in the current scope"
+    );
+    // the stale diagnostic is cleared
+    assert!(diagnostics(&out[2]).is_empty());
+    assert_eq!(out[3].get("id"), Some(&Json::Number(2.0)));
+}
+
+#[test]
+fn test_lsp_definition() {
+    let text = "$c A $.\n$v x $.\nvx $f A x $.\nax $a A x $.\nth $p A x $= vx ax $.\n";
+    let definition = |line: usize, character: usize, id: usize| {
+        let position = Json::object(vec![("line", line.into()), ("character", character.into())]);
+        request(
+            id,
+            "textDocument/definition",
+            Json::object(vec![
+                ("textDocument", Json::object(vec![("uri", uri().into())])),
+                ("position", position),
+            ]),
+        )
+    };
+    let input = open(text) + &definition(4, 17, 1) + &definition(2, 8, 2) + &definition(0, 0, 3);
+    // there are no diagnostics to publish
+    let out = session(&input);
+    assert_eq!(out.len(), 3);
+    let range = |msg: &Json| {
+        let result = msg.get("result").unwrap();
+        assert_eq!(result.get("uri").and_then(Json::as_str), Some(&uri()[..]));
+        let start = result.get("range").unwrap().get("start").unwrap();
+        (
+            start.get("line").and_then(Json::as_usize).unwrap(),
+            start.get("character").and_then(Json::as_usize).unwrap(),
+        )
+    };
+    // the label ax in the proof of th
+    assert_eq!(range(&out[0]), (3, 0));
+    // the variable x in vx
+    assert_eq!(range(&out[1]), (1, 3));
+    // the keyword $c is not a name
+    assert_eq!(out[2].get("result"), Some(&Json::Null));
+}
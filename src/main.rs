@@ -13,33 +13,111 @@ extern crate regex;
 extern crate alloc_system;
 
 pub mod bit_set;
+pub mod cache;
+pub mod commands;
 pub mod database;
+pub mod defck;
+pub mod deps;
 pub mod diag;
+pub mod discouraged;
+pub mod dupck;
 pub mod export;
+pub mod forbidck;
+pub mod grammar;
+pub mod html;
+pub mod import_mmp;
+pub mod json;
+pub mod latex;
 pub mod line_cache;
+pub mod lint;
+pub mod lsp;
+pub mod markupck;
 pub mod nameck;
 pub mod parser;
 pub mod proof;
+pub mod report;
+pub mod rewrite;
 pub mod scopeck;
 pub mod segment_set;
+pub mod typesetting;
 pub mod util;
 pub mod verify;
+pub mod xref;
 
+#[cfg(test)]
+mod cache_tests;
+#[cfg(test)]
+mod defck_tests;
+#[cfg(test)]
+mod deps_tests;
+#[cfg(test)]
+mod discouraged_tests;
+#[cfg(test)]
+mod dupck_tests;
+#[cfg(test)]
+mod forbidck_tests;
+#[cfg(test)]
+mod grammar_tests;
+#[cfg(test)]
+mod html_tests;
+#[cfg(test)]
+mod import_mmp_tests;
+#[cfg(test)]
+mod latex_tests;
+#[cfg(test)]
+mod lint_tests;
+#[cfg(test)]
+mod lsp_tests;
+#[cfg(test)]
+mod markupck_tests;
 #[cfg(test)]
 mod parser_tests;
 #[cfg(test)]
+mod proof_tests;
+#[cfg(test)]
+mod report_tests;
+#[cfg(test)]
+mod segment_set_tests;
+#[cfg(test)]
+mod test_util;
+#[cfg(test)]
+mod typesetting_tests;
+#[cfg(test)]
 mod util_tests;
+#[cfg(test)]
+mod verify_tests;
+#[cfg(test)]
+mod xref_tests;
 
 use clap::App;
+use clap::AppSettings;
 use clap::Arg;
+use clap::ErrorKind;
+use clap::SubCommand;
 use database::Database;
 use database::DbOptions;
 use diag::DiagnosticClass;
 use diag::Notation;
+use filetime::FileTime;
 use line_cache::LineCache;
+use parser::StatementAddress;
+use parser::StatementType;
+use report::Report;
+use std::fs;
 use std::io;
 use std::mem;
 use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use util::new_map;
+use util::HashMap;
+
+/// Options which print text to standard output, and so cannot be combined with
+/// a machine-readable format.
+const TEXT_OUTPUT_OPTIONS: &[&str] = &["used-by", "trace-axioms", "timing", "trace-recalc"];
+
+/// How often to check for modified files in watch mode.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
 
 fn positive_integer(val: String) -> Result<(), String> {
     u32::from_str(&val)
@@ -48,9 +126,12 @@ fn positive_integer(val: String) -> Result<(), String> {
 }
 
 fn main() {
+    let lints = lint::all_lints();
+    let lint_names = lints.iter().map(|lint| lint.name()).collect::<Vec<_>>();
     let matches = App::new("smetamath-rs")
         .version(crate_version!())
         .about("A Metamath database verifier and processing tool")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("DATABASE")
                 .help("Database file to load")
@@ -72,6 +153,88 @@ fn main() {
                 .long("verify")
                 .short("v"),
         )
+        .arg(
+            Arg::with_name("verify-only")
+                .help("Check the proofs of some theorems without verifying the whole database")
+                .long("verify-only")
+                .value_name("LABEL")
+                .multiple(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("grammar")
+                .help("Check that statements parse with the syntax axioms")
+                .long("grammar")
+                .short("g"),
+        )
+        .arg(
+            Arg::with_name("definitions")
+                .help("Check that definitions are conservative")
+                .long("definitions")
+                .short("d"),
+        )
+        .arg(
+            Arg::with_name("definition-prefix")
+                .help("Label prefix of definitions (default df-)")
+                .long("definition-prefix")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("typesetting")
+                .help("Check the typesetting definitions in $t comments")
+                .long("typesetting"),
+        )
+        .arg(
+            Arg::with_name("forbid-axiom")
+                .help("Check that no proof depends on a forbidden axiom, forbidding these as well")
+                .long("forbid-axiom")
+                .value_name("LABEL")
+                .takes_value(true)
+                .require_equals(true)
+                .min_values(0)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("discouraged")
+                .help("Check uses of statements tagged (New usage is discouraged.)")
+                .long("discouraged"),
+        )
+        .arg(
+            Arg::with_name("markup")
+                .help("Check label references and math in comments")
+                .long("markup"),
+        )
+        .arg(
+            Arg::with_name("lint")
+                .help("Run style lints, or only the named ones")
+                .long("lint")
+                .value_name("NAME")
+                .takes_value(true)
+                .require_equals(true)
+                .min_values(0)
+                .multiple(true)
+                .possible_values(&lint_names),
+        )
+        .arg(
+            Arg::with_name("duplicates")
+                .help("Check for assertions which are equivalent to earlier ones")
+                .long("duplicates"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .help("Output format of diagnostics")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["text", "json", "sarif"])
+                .default_value("text"),
+        )
+        .arg(
+            Arg::with_name("cache")
+                .help("Save results to a directory, and reuse them in later runs")
+                .long("cache")
+                .value_name("DIR")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("trace-recalc")
                 .help("Print segments as they are recalculated")
@@ -87,6 +250,13 @@ fn main() {
                 .help("Demonstrate incremental verifier")
                 .long("repeat"),
         )
+        .arg(
+            Arg::with_name("watch")
+                .help("Reload and print changed diagnostics whenever a source file changes")
+                .long("watch")
+                .short("w")
+                .conflicts_with_all(&["repeat", "import"]),
+        )
         .arg(
             Arg::with_name("jobs")
                 .help("Number of threads to use for verification")
@@ -103,6 +273,46 @@ fn main() {
                 .multiple(true)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("latex")
+                .help("Output a LaTeX file for a statement")
+                .long("latex")
+                .short("l")
+                .multiple(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("import")
+                .help("Replace a proof with the one from an mmj2 proof file")
+                .long("import")
+                .short("i")
+                .multiple(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("html")
+                .help("Write an HTML page for each theorem to a directory")
+                .long("html")
+                .value_name("DIR")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("used-by")
+                .help("List the theorems whose proofs use a label")
+                .long("used-by")
+                .value_name("LABEL")
+                .multiple(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trace-axioms")
+                .help("List the axioms and definitions a theorem depends on, or every theorem's")
+                .long("trace-axioms")
+                .value_name("LABEL")
+                .takes_value(true)
+                .require_equals(true)
+                .min_values(0),
+        )
         .arg(
             Arg::with_name("TEXT")
                 .long("text")
@@ -110,16 +320,47 @@ fn main() {
                 .value_names(&["NAME", "TEXT"])
                 .multiple(true),
         )
+        .subcommand(
+            SubCommand::with_name("lsp")
+                .about("Run a language server on stdin and stdout")
+                .arg(
+                    Arg::with_name("DATABASE")
+                        .help("Database file to load (default: the first document opened)"),
+                ),
+        )
         .get_matches();
 
     let mut options = DbOptions::default();
     options.autosplit = matches.is_present("split");
     options.timing = matches.is_present("timing");
     options.trace_recalc = matches.is_present("trace-recalc");
-    options.incremental = matches.is_present("repeat");
+    options.incremental = matches.is_present("repeat") || matches.is_present("watch");
+    options.definition_prefix = matches
+        .value_of("definition-prefix")
+        .unwrap_or("")
+        .to_owned();
+    options.forbidden_axioms = matches.values_of_lossy("forbid-axiom").unwrap_or_default();
+    options.lints = matches.values_of_lossy("lint").unwrap_or_default();
+    options.cache_dir = matches.value_of("cache").map(|dir| dir.to_owned());
     options.jobs = usize::from_str(matches.value_of("jobs").unwrap_or("1"))
         .expect("validator should check this");
 
+    if let Some(lsp_matches) = matches.subcommand_matches("lsp") {
+        // stdout carries the protocol, so nothing else may be printed to it
+        options.incremental = true;
+        options.timing = false;
+        options.trace_recalc = false;
+        let db = Database::new(options);
+        let start = lsp_matches.value_of("DATABASE").map(|x| x.to_owned());
+        let stdin = io::stdin();
+        let result = lsp::Server::new(db, start, io::stdout())
+            .and_then(|mut server| server.run(stdin.lock()));
+        if let Err(err) = result {
+            eprintln!("Language server failed: {}", err);
+        }
+        return;
+    }
+
     let mut db = Database::new(options);
 
     let mut data = Vec::new();
@@ -128,11 +369,26 @@ fn main() {
             data.push((kv[0].clone(), kv[1].clone().into_bytes()));
         }
     }
+    let format = matches.value_of("format").unwrap_or("text");
+    if format != "text" {
+        if let Some(arg) = TEXT_OUTPUT_OPTIONS
+            .iter()
+            .find(|&&arg| matches.is_present(arg))
+        {
+            clap::Error::with_description(
+                &format!("--{} cannot be used with --format {}", arg, format),
+                ErrorKind::ArgumentConflict,
+            )
+            .exit();
+        }
+    }
     let start = matches
         .value_of("DATABASE")
         .map(|x| x.to_owned())
         .unwrap_or_else(|| data[0].0.clone());
 
+    // diagnostics of the last run, in watch mode
+    let mut previous: Option<HashMap<NotationKey, Report>> = None;
     loop {
         db.parse(start.clone(), data.clone());
 
@@ -142,9 +398,114 @@ fn main() {
             types.push(DiagnosticClass::Verify);
         }
 
+        if matches.is_present("grammar") {
+            types.push(DiagnosticClass::Grammar);
+        }
+
+        if matches.is_present("definitions") {
+            types.push(DiagnosticClass::Definition);
+        }
+
+        if matches.is_present("typesetting") {
+            types.push(DiagnosticClass::Typesetting);
+        }
+
+        if matches.is_present("forbid-axiom") {
+            types.push(DiagnosticClass::Forbidden);
+        }
+
+        if matches.is_present("discouraged") {
+            types.push(DiagnosticClass::Discouraged);
+        }
+
+        if matches.is_present("markup") {
+            types.push(DiagnosticClass::Markup);
+        }
+
+        if matches.is_present("lint") {
+            types.push(DiagnosticClass::Lint);
+        }
+
+        if matches.is_present("duplicates") {
+            types.push(DiagnosticClass::Duplicate);
+        }
+
         let mut lc = LineCache::default();
-        for notation in db.diag_notations(types) {
-            print_annotation(&mut lc, notation);
+        let mut reports = Vec::new();
+        let mut current = new_map();
+        output_notations(
+            &mut lc,
+            format,
+            db.diag_notations(types),
+            &mut reports,
+            previous.as_ref(),
+            &mut current,
+        );
+
+        if let Some(labels) = matches.values_of_lossy("verify-only") {
+            let labels = labels.iter().map(String::as_str).collect::<Vec<_>>();
+            for &label in &labels {
+                let provable = db
+                    .statement(label)
+                    .is_some_and(|stmt| stmt.statement_type() == StatementType::Provable);
+                if !provable {
+                    eprintln!(
+                        "Label {} did not correspond to an existing $p statement",
+                        label
+                    );
+                }
+            }
+            let notations = db.verify_statements(&labels);
+            output_notations(
+                &mut lc,
+                format,
+                notations,
+                &mut reports,
+                previous.as_ref(),
+                &mut current,
+            );
+        }
+
+        if let Some(labels) = matches.values_of_lossy("used-by") {
+            for label in labels {
+                for stmt in db.usages(&label) {
+                    println!("{}: {}", label, String::from_utf8_lossy(stmt.label()));
+                }
+            }
+        }
+
+        if matches.is_present("trace-axioms") {
+            let labels = match matches.value_of("trace-axioms") {
+                Some(label) => vec![label.to_owned()],
+                None => db
+                    .parse_result()
+                    .segments()
+                    .into_iter()
+                    .flat_map(|sref| sref.into_iter())
+                    .filter(|stmt| stmt.statement_type() == StatementType::Provable)
+                    .map(|stmt| String::from_utf8_lossy(stmt.label()).into_owned())
+                    .collect(),
+            };
+            for label in labels {
+                match db.dependencies(&label) {
+                    Some(deps) => {
+                        let sset = db.parse_result();
+                        let names = |addrs: &[StatementAddress]| {
+                            addrs
+                                .iter()
+                                .map(|&addr| String::from_utf8_lossy(sset.statement(addr).label()))
+                                .collect::<Vec<_>>()
+                                .join(" ")
+                        };
+                        println!("{} axioms: {}", label, names(&deps.axioms));
+                        println!("{} definitions: {}", label, names(&deps.definitions));
+                    }
+                    None => println!(
+                        "Label {} did not correspond to an existing statement",
+                        label
+                    ),
+                }
+            }
         }
 
         if let Some(exps) = matches.values_of_lossy("export") {
@@ -153,7 +514,55 @@ fn main() {
             }
         }
 
-        if matches.is_present("repeat") {
+        if let Some(lats) = matches.values_of_lossy("latex") {
+            for file in lats {
+                db.latex(file);
+            }
+        }
+
+        if let Some(imps) = matches.values_of_lossy("import") {
+            for file in imps {
+                // the worksheet buffer is freed with the notations
+                let mut lc = LineCache::default();
+                let notations = db.import(file);
+                output_notations(&mut lc, format, notations, &mut reports, None, &mut current);
+            }
+        }
+
+        if let Some(dir) = matches.value_of("html") {
+            if let Err(err) = db.html(dir.to_owned()) {
+                eprintln!("Failed to write HTML pages to {}: {}", dir, err);
+            }
+        }
+
+        match format {
+            "json" => println!("{}", report::json_log(&reports)),
+            "sarif" => println!("{}", report::sarif_log(&reports)),
+            _ => {}
+        }
+
+        if let Some(ref previous) = previous {
+            if format == "text" {
+                let mut resolved = previous
+                    .iter()
+                    .filter(|&(key, _)| !current.contains_key(key))
+                    .map(|(_, report)| report)
+                    .collect::<Vec<_>>();
+                resolved.sort_by_key(|report| (&report.source, report.line, report.column));
+                for report in resolved {
+                    println!(
+                        "{}:{}:{}:Resolved:{}",
+                        report.source, report.line, report.column, report.message
+                    );
+                }
+            }
+        }
+
+        if matches.is_present("watch") {
+            previous = Some(current);
+            let sset = db.parse_result();
+            wait_for_change(&sset.file_times(), sset.missing_files());
+        } else if matches.is_present("repeat") {
             let mut input = String::new();
             if io::stdin().read_line(&mut input).unwrap() == 0 {
                 break;
@@ -168,6 +577,71 @@ fn main() {
     }
 }
 
+/// Identifies a notation between runs in watch mode, without its position so
+/// that a notation is not output again when an edit moves it to another line.
+#[derive(PartialEq, Eq, Hash)]
+struct NotationKey {
+    source: String,
+    label: String,
+    code: String,
+    args: Vec<(&'static str, String)>,
+}
+
+impl NotationKey {
+    fn new(notation: &Notation) -> Self {
+        NotationKey {
+            source: notation.source.name.clone(),
+            label: notation.label.clone(),
+            code: notation.code.clone(),
+            args: notation.args.clone(),
+        }
+    }
+}
+
+/// Prints notations as text, or collects them to be printed together in a
+/// machine-readable format.  Notations in `previous` were output by the last
+/// run and are skipped; all are added to `current`.
+fn output_notations(
+    lc: &mut LineCache,
+    format: &str,
+    notations: Vec<Notation>,
+    reports: &mut Vec<Report>,
+    previous: Option<&HashMap<NotationKey, Report>>,
+    current: &mut HashMap<NotationKey, Report>,
+) {
+    for notation in notations {
+        let key = NotationKey::new(&notation);
+        let report = Report::new(lc, &notation);
+        if previous.is_some_and(|previous| previous.contains_key(&key)) {
+            current.insert(key, report);
+            continue;
+        }
+        current.insert(key, report.clone());
+        if format == "text" {
+            print_annotation(lc, notation);
+        } else {
+            reports.push(report);
+        }
+    }
+}
+
+/// Polls files until one of them is modified or can no longer be read, or a
+/// missing file can be read.
+fn wait_for_change(files: &[(String, FileTime)], missing: &[String]) {
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let changed = files.iter().any(|&(ref path, time)| {
+            fs::metadata(path)
+                .map(|metadata| FileTime::from_last_modification_time(&metadata))
+                .ok()
+                != Some(time)
+        });
+        if changed || missing.iter().any(|path| fs::metadata(path).is_ok()) {
+            return;
+        }
+    }
+}
+
 fn print_annotation(lc: &mut LineCache, ann: Notation) {
     let mut args = String::new();
     for (id, val) in ann.args {
@@ -0,0 +1,201 @@
+//! Checks the markup of comments, like metamath.exe's `verify markup`.
+//!
+//! Comments are written in a light markup language: `~ label` refers to a
+//! statement, text between backticks such as `` ` ( ph -> ps ) ` `` is math,
+//! `[Bibref]` cites a bibliography entry and `_text_` is italic.  A doubled
+//! backtick or tilde stands for the character itself.  Within a comment,
+//! tokens are separated by white space, except that a single backtick always
+//! starts or ends math.
+//!
+//! This pass reports references to labels which do not exist or which are
+//! only defined later, math symbols which are not declared, and math which is
+//! not closed before the end of the comment.  A comment describing an `$a` or
+//! `$p` statement may refer to that statement.  Citations and italics have no
+//! meaning which can be checked here, and are passed over.  Segments are
+//! checked in parallel.
+//!
+//! The check is not incremental: every comment is checked again each time the
+//! result is recalculated.  A comment's diagnostics depend on the order of the
+//! segments as well as on the names it uses, and only the latter is tracked.
+
+use diag::Diagnostic;
+use nameck::Nameset;
+use parser::Comparer;
+use parser::SegmentId;
+use parser::Span;
+use parser::StatementAddress;
+use parser::StatementRef;
+use parser::StatementType;
+use segment_set::SegmentSet;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+/// Analysis pass result for the markup checker.
+#[derive(Default, Clone)]
+pub struct MarkupResult {
+    diagnostics: Vec<(StatementAddress, Diagnostic)>,
+}
+
+impl MarkupResult {
+    /// Report comments with bad references or math.
+    pub fn diagnostics(&self) -> Vec<(StatementAddress, Diagnostic)> {
+        self.diagnostics.clone()
+    }
+}
+
+/// An element of a comment which can be checked.
+enum Markup {
+    /// The label following a `~`.
+    Label(Span),
+    /// A symbol between backticks.
+    Symbol(Span),
+    /// A backtick which starts math that is never ended.
+    UnclosedMath(Span),
+}
+
+/// Splits the body of a comment into markup elements.
+///
+/// `start` and `end` delimit the text between `$(` and `$)` in the buffer.
+fn parse_markup(buffer: &[u8], start: usize, end: usize) -> Vec<Markup> {
+    let is_backtick = |pos: usize| buffer[pos] == b'`';
+    let is_doubled = |pos: usize| pos + 1 < end && buffer[pos + 1] == b'`';
+
+    let mut out = Vec::new();
+    let mut math_start = None;
+    let mut after_tilde = false;
+    let mut pos = start;
+    while pos < end {
+        if buffer[pos].is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        }
+        if is_backtick(pos) && !is_doubled(pos) {
+            math_start = match math_start {
+                Some(_) => None,
+                None => Some(pos),
+            };
+            after_tilde = false;
+            pos += 1;
+            continue;
+        }
+
+        let token_start = pos;
+        while pos < end && !buffer[pos].is_ascii_whitespace() {
+            if is_backtick(pos) {
+                if !is_doubled(pos) {
+                    break;
+                }
+                pos += 1;
+            }
+            pos += 1;
+        }
+        let span = Span::new(token_start, pos);
+        let token = span.as_ref(buffer);
+        if math_start.is_some() {
+            out.push(Markup::Symbol(span));
+        } else if after_tilde {
+            if !token.starts_with(b"http:") && !token.starts_with(b"https:") {
+                out.push(Markup::Label(span));
+            }
+            after_tilde = false;
+        } else {
+            after_tilde = token == b"~";
+        }
+    }
+    if let Some(pos) = math_start {
+        out.push(Markup::UnclosedMath(Span::new(pos, pos + 1)));
+    }
+    out
+}
+
+/// Checks a comment statement, given the statement which follows it.
+fn check_comment(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    stmt: StatementRef,
+    next: Option<StatementRef>,
+    out: &mut Vec<(StatementAddress, Diagnostic)>,
+) {
+    let address = stmt.address();
+    // references from the description of an assertion to the assertion
+    // itself are not forward references
+    let reference = match next {
+        Some(next)
+            if next.statement_type() == StatementType::Axiom
+                || next.statement_type() == StatementType::Provable =>
+        {
+            next.address()
+        }
+        _ => address,
+    };
+
+    let buffer = &stmt.segment().segment.buffer;
+    let span = stmt.span();
+    let start = span.start as usize + 2;
+    let mut end = span.end as usize;
+    if span.as_ref(buffer).ends_with(b"$)") {
+        end -= 2;
+    }
+    for markup in parse_markup(buffer, start, end.max(start)) {
+        match markup {
+            Markup::Label(span) => match nset.lookup_label(span.as_ref(buffer)) {
+                None => out.push((address, Diagnostic::MarkupUndefinedLabel(span))),
+                Some(lookup) => {
+                    if sset.order.cmp(&lookup.address, &reference) == Ordering::Greater {
+                        out.push((address, Diagnostic::MarkupLaterLabel(span, lookup.address)));
+                    }
+                }
+            },
+            Markup::Symbol(span) => {
+                let symbol = span.as_ref(buffer);
+                let mut unquoted = Vec::with_capacity(symbol.len());
+                let mut ix = 0;
+                while ix < symbol.len() {
+                    unquoted.push(symbol[ix]);
+                    ix += if symbol[ix] == b'`' { 2 } else { 1 };
+                }
+                if nset.lookup_symbol(&unquoted).is_none() {
+                    out.push((address, Diagnostic::MarkupUndefinedSymbol(span)));
+                }
+            }
+            Markup::UnclosedMath(span) => {
+                out.push((address, Diagnostic::MarkupUnclosedMath(span)));
+            }
+        }
+    }
+}
+
+/// Checks the comments of a segment.
+fn markup_segment(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    sid: SegmentId,
+) -> Vec<(StatementAddress, Diagnostic)> {
+    let mut out = Vec::new();
+    let mut iter = sset.segment(sid).into_iter().peekable();
+    while let Some(stmt) = iter.next() {
+        if stmt.statement_type() == StatementType::Comment {
+            check_comment(sset, nset, stmt, iter.peek().copied(), &mut out);
+        }
+    }
+    out
+}
+
+/// Checks the markup of every comment in a database.
+pub fn markup_check(result: &mut MarkupResult, segments: &Arc<SegmentSet>, nset: &Arc<Nameset>) {
+    let mut dq = Vec::new();
+    for sref in segments.segments() {
+        let segments2 = segments.clone();
+        let nset = nset.clone();
+        let id = sref.id;
+        dq.push(
+            segments
+                .exec
+                .exec(sref.bytes(), move || markup_segment(&segments2, &nset, id)),
+        );
+    }
+    result.diagnostics.clear();
+    for promise in dq {
+        result.diagnostics.extend(promise.wait());
+    }
+}
@@ -0,0 +1,45 @@
+use database::DbOptions;
+use diag::Diagnostic;
+use parser::Span;
+use test_util::mkdb;
+
+const DB: &str = "$c wff |- ( -> ) $.
+    $v ph ps $.
+    wph $f wff ph $.
+    wps $f wff ps $.
+    wi $a wff ( ph -> ps ) $.
+    $( Section header citing [Megill] for ~ ax-1 and ~ nothere . $)
+    $( Axiom _simp_ is ` ( ph -> ( ps -> ph ) ) ` , see ~ ax-1 and
+       ~ http://example.com and ~~ ax-1 . $)
+    ax-1 $a |- ( ph -> ( ps -> ph ) ) $.
+    $( Uses ` ph -> qq ` and a literal `` backtick. $)
+    th1 $p |- ( ph -> ( ps -> ph ) ) $= wph wps ax-1 $.
+    $( Unclosed ` ph $)";
+
+#[test]
+fn test_markup() {
+    let mut db = mkdb(DB, DbOptions::default());
+    let ax_1 = db.statement("ax-1").unwrap().address();
+    let text = |span: Span| &DB[span.start as usize..span.end as usize];
+    let diags = db
+        .markup_result()
+        .diagnostics()
+        .into_iter()
+        .map(|(_, diag)| match diag {
+            Diagnostic::MarkupLaterLabel(span, addr) => {
+                assert_eq!(addr, ax_1);
+                format!("later {}", text(span))
+            }
+            Diagnostic::MarkupUnclosedMath(span) => format!("unclosed {}", text(span)),
+            Diagnostic::MarkupUndefinedLabel(span) => format!("label {}", text(span)),
+            Diagnostic::MarkupUndefinedSymbol(span) => format!("symbol {}", text(span)),
+            _ => panic!("unexpected diagnostic {:?}", diag),
+        })
+        .collect::<Vec<_>>();
+
+    // the description of ax-1 may refer to it, but the section header may not
+    assert_eq!(
+        diags,
+        vec!["later ax-1", "label nothere", "symbol qq", "unclosed `"]
+    );
+}
@@ -378,6 +378,10 @@ pub struct NameReader<'a> {
 }
 
 /// Usage data extracted from a `NameReader` at cycle end.
+///
+/// The default usage is never valid, for results which were not computed from
+/// this nameset.
+#[derive(Default)]
 pub struct NameUsage {
     generation: usize,
     incremental: bool,
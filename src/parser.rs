@@ -45,6 +45,8 @@
 //! `SegmentId` and `SegmentRef` cover the same use cases for segments, although
 //! it makes no sense to have a segment-local segment reference.
 
+use cache::Decoder;
+use cache::Encoder;
 use diag::Diagnostic;
 use std::cmp;
 use std::cmp::Ordering;
@@ -377,6 +379,27 @@ pub struct SymbolDef {
     pub ordinal: TokenIndex,
 }
 
+/// An argument of a command in a `$j` or `$t` comment.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CommandArg {
+    /// Location of the argument; for a quoted string this excludes the quotes,
+    /// and doubled quotes inside the string are not unescaped.
+    pub span: Span,
+    /// True if the argument was a quoted string rather than a bare word.
+    pub quoted: bool,
+}
+
+/// A command from a `$j` or `$t` comment, such as `syntax 'wff';`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command {
+    /// Location of the whole command, including the final semicolon.
+    pub span: Span,
+    /// The bare word which starts the command.
+    pub keyword: Span,
+    /// The words and strings after the keyword.
+    pub args: Vec<CommandArg>,
+}
+
 /// Extracted information for a global `$f` statement in a segment.
 #[derive(Debug)]
 pub struct FloatDef {
@@ -437,6 +460,10 @@ pub struct Segment {
     pub labels: Vec<LabelDef>,
     /// Global `$f` statements extracted for nameck.
     pub floats: Vec<FloatDef>,
+    /// Commands from `$j` comments, with the index of the comment.
+    pub commands: Vec<(StatementIndex, Command)>,
+    /// Commands from `$t` comments, with the index of the comment.
+    pub typesetting: Vec<(StatementIndex, Command)>,
 }
 
 /// A pointer to a segment which knows its identity.
@@ -505,6 +532,11 @@ pub enum StatementType {
     /// A comment which starts with a `$t` token and must be interpreted
     /// specially by the HTML generator.
     TypesettingComment,
+    /// A comment which starts with a `$j` token and contains commands for
+    /// proof tools, such as the grammar of the database.
+    ///
+    /// The commands are extracted into `Segment::commands`.
+    AdditionalInfoComment,
     /// A `$[` directive; we process these as statements, and disallow them
     /// inside other statements, which violates the published Metamath spec but
     /// is allowed behavior as an erratum.
@@ -820,6 +852,10 @@ struct Scanner<'a> {
     diagnostics: Vec<(StatementIndex, Diagnostic)>,
     /// Accumulated spans for this segment.
     span_pool: Vec<Span>,
+    /// Accumulated `$j` commands for this segment.
+    commands: Vec<(StatementIndex, Command)>,
+    /// Accumulated `$t` commands for this segment.
+    typesetting: Vec<(StatementIndex, Command)>,
     /// A span which was encountered, but needs to be processed again.
     ///
     /// This is used for error recovery if you leave off the `$.` which ends a
@@ -987,6 +1023,142 @@ impl<'a> Scanner<'a> {
         ctype
     }
 
+    /// Splits the body of a `$j` or `$t` comment into commands.
+    ///
+    /// The body is a sequence of commands, each a bare keyword followed by
+    /// bare words and quoted strings and ended by `;`.  Strings may use single
+    /// or double quotes, with the quote doubled to include it in the string,
+    /// and `/* */` comments may appear between tokens.  The `marker` itself is
+    /// skipped as the first token of the body.
+    fn get_commands(&mut self, body: Span, marker: &[u8]) -> Vec<Command> {
+        let buffer = self.buffer;
+        let mut out = Vec::new();
+        let end = body.end as usize;
+        let mut ix = body.start as usize;
+        let mut current: Option<Command> = None;
+        let mut skipping = false;
+        let mut first = true;
+
+        loop {
+            while ix < end && buffer[ix] <= 32 {
+                ix += 1;
+            }
+            if ix >= end {
+                break;
+            }
+
+            if buffer[ix..end].starts_with(b"/*") {
+                match buffer[ix + 2..end].windows(2).position(|w| w == b"*/") {
+                    Some(pos) => ix += pos + 4,
+                    None => {
+                        self.diag(Diagnostic::CommandUnclosedComment(Span::new(ix, end)));
+                        ix = end;
+                    }
+                }
+                continue;
+            }
+
+            let start = ix;
+            let arg = match buffer[ix] {
+                b';' => {
+                    ix += 1;
+                    match current.take() {
+                        Some(mut cmd) => {
+                            cmd.span = Span::new(cmd.span.start as usize, ix);
+                            out.push(cmd);
+                        }
+                        None => {
+                            if !skipping {
+                                self.diag(Diagnostic::CommandExpectedKeyword(Span::new(start, ix)));
+                            }
+                        }
+                    }
+                    skipping = false;
+                    continue;
+                }
+                quote @ b'\'' | quote @ b'"' => {
+                    ix += 1;
+                    loop {
+                        if ix >= end {
+                            self.diag(Diagnostic::CommandUnterminatedString(Span::new(start, end)));
+                            return out;
+                        }
+                        if buffer[ix] == quote {
+                            if ix + 1 < end && buffer[ix + 1] == quote {
+                                ix += 2;
+                                continue;
+                            }
+                            break;
+                        }
+                        ix += 1;
+                    }
+                    ix += 1;
+                    CommandArg {
+                        span: Span::new(start + 1, ix - 1),
+                        quoted: true,
+                    }
+                }
+                _ => {
+                    while ix < end
+                        && buffer[ix] > 32
+                        && !b";'\"".contains(&buffer[ix])
+                        && !buffer[ix..end].starts_with(b"/*")
+                    {
+                        ix += 1;
+                    }
+                    CommandArg {
+                        span: Span::new(start, ix),
+                        quoted: false,
+                    }
+                }
+            };
+
+            if first {
+                first = false;
+                if !arg.quoted && arg.span.as_ref(buffer) == marker {
+                    continue;
+                }
+            }
+            if skipping {
+                continue;
+            }
+            match current {
+                Some(ref mut cmd) => {
+                    cmd.span = Span::new(cmd.span.start as usize, ix);
+                    cmd.args.push(arg);
+                }
+                None if arg.quoted => {
+                    self.diag(Diagnostic::CommandExpectedKeyword(Span::new(start, ix)));
+                    skipping = true;
+                }
+                None => {
+                    current = Some(Command {
+                        span: arg.span,
+                        keyword: arg.span,
+                        args: Vec::new(),
+                    });
+                }
+            }
+        }
+
+        if let Some(cmd) = current {
+            self.diag(Diagnostic::CommandMissingSemicolon(cmd.span));
+        }
+        out
+    }
+
+    /// Returns the body of the comment which was just read by `get_comment`,
+    /// from the end of the `$(` token to the start of the `$)` token.
+    fn comment_body(&self, opener: Span) -> Span {
+        let end = self.position as usize;
+        let close = if self.buffer[..end].ends_with(b"$)") {
+            end - 2
+        } else {
+            end
+        };
+        Span::new(opener.end as usize, close)
+    }
+
     /// Fetches a single normal token from the buffer, skipping over comments
     /// and handling unget.
     fn get(&mut self) -> Span {
@@ -1037,10 +1209,22 @@ impl<'a> Scanner<'a> {
             let ftok_ref = ftok.as_ref(self.buffer);
             if ftok_ref == b"$(" {
                 let ctype = self.get_comment(ftok, false);
-                let stype = if ctype == CommentType::Typesetting {
-                    TypesettingComment
-                } else {
-                    Comment
+                let stype = match ctype {
+                    CommentType::Typesetting => {
+                        let cmds = self.get_commands(self.comment_body(ftok), b"$t");
+                        let index = self.statement_index;
+                        self.typesetting
+                            .extend(cmds.into_iter().map(|cmd| (index, cmd)));
+                        TypesettingComment
+                    }
+                    CommentType::Extra => {
+                        let cmds = self.get_commands(self.comment_body(ftok), b"$j");
+                        let index = self.statement_index;
+                        self.commands
+                            .extend(cmds.into_iter().map(|cmd| (index, cmd)));
+                        AdditionalInfoComment
+                    }
+                    CommentType::Normal => Comment,
                 };
                 return Some(self.out_statement(stype, Span::new2(ftok.start, ftok.start)));
             } else {
@@ -1334,6 +1518,8 @@ impl<'a> Scanner<'a> {
             global_dvs: Vec::new(),
             labels: Vec::new(),
             floats: Vec::new(),
+            commands: Vec::new(),
+            typesetting: Vec::new(),
             buffer: self.buffer_ref.clone(),
             diagnostics: Vec::new(),
             span_pool: Vec::new(),
@@ -1409,6 +1595,8 @@ impl<'a> Scanner<'a> {
 
         seg.diagnostics = mem::replace(&mut self.diagnostics, Vec::new());
         seg.span_pool = mem::replace(&mut self.span_pool, Vec::new());
+        seg.commands = mem::take(&mut self.commands);
+        seg.typesetting = mem::take(&mut self.typesetting);
         seg.span_pool.shrink_to_fit();
         seg.statements.shrink_to_fit();
         collect_definitions(&mut seg);
@@ -1579,3 +1767,178 @@ pub fn dummy_segment(diag: Diagnostic) -> Arc<Segment> {
     Arc::get_mut(&mut seg).unwrap().diagnostics.push((0, diag));
     seg
 }
+
+/// Statement types, in the order of their encoding in the disk cache.
+const STATEMENT_TYPES: [StatementType; 15] = [
+    Eof,
+    Invalid,
+    Comment,
+    TypesettingComment,
+    AdditionalInfoComment,
+    FileInclude,
+    Axiom,
+    Provable,
+    Essential,
+    Floating,
+    Disjoint,
+    OpenGroup,
+    CloseGroup,
+    Constant,
+    Variable,
+];
+
+fn encode_command(enc: &mut Encoder, &(index, ref command): &(StatementIndex, Command)) {
+    enc.i32(index);
+    enc.span(command.span);
+    enc.span(command.keyword);
+    enc.seq(&command.args, |enc, arg| {
+        enc.span(arg.span);
+        enc.bool(arg.quoted);
+    });
+}
+
+fn decode_command(dec: &mut Decoder) -> Option<(StatementIndex, Command)> {
+    let index = dec.i32()?;
+    let command = Command {
+        span: dec.span()?,
+        keyword: dec.span()?,
+        args: dec.seq(|dec| {
+            Some(CommandArg {
+                span: dec.span()?,
+                quoted: dec.bool()?,
+            })
+        })?,
+    };
+    Some((index, command))
+}
+
+/// Serializes the segments parsed from a buffer for the disk cache, or returns
+/// `None` if any of them has diagnostics.
+pub fn encode_segments(segments: &[Arc<Segment>]) -> Option<Encoder> {
+    if segments.iter().any(|seg| !seg.diagnostics.is_empty()) {
+        return None;
+    }
+    let mut enc = Encoder::default();
+    enc.seq(segments, |enc, seg| {
+        enc.seq(&seg.statements, |enc, stmt| {
+            enc.u32(stmt.stype as u32);
+            enc.span(stmt.span);
+            enc.span(stmt.label);
+            enc.i32(stmt.group);
+            enc.i32(stmt.group_end);
+            enc.usize(stmt.math_start);
+            enc.usize(stmt.proof_start);
+            enc.usize(stmt.proof_end);
+        });
+        enc.seq(&seg.span_pool, |enc, &span| enc.span(span));
+        enc.span(seg.next_file);
+        enc.seq(&seg.global_dvs, |enc, dv| {
+            enc.i32(dv.start);
+            enc.seq(&dv.vars, |enc, var| enc.bytes(var));
+        });
+        enc.seq(&seg.symbols, |enc, sym| {
+            enc.bytes(&sym.name);
+            enc.bool(sym.stype == SymbolType::Constant);
+            enc.i32(sym.start);
+            enc.i32(sym.ordinal);
+        });
+        enc.seq(&seg.local_vars, |enc, var| {
+            enc.i32(var.index);
+            enc.i32(var.ordinal);
+        });
+        enc.seq(&seg.labels, |enc, label| enc.i32(label.index));
+        enc.seq(&seg.floats, |enc, float| {
+            enc.i32(float.start);
+            enc.bytes(&float.name);
+            enc.bytes(&float.label);
+            enc.bytes(&float.typecode);
+        });
+        enc.seq(&seg.commands, encode_command);
+        enc.seq(&seg.typesetting, encode_command);
+    });
+    Some(enc)
+}
+
+/// Rebuilds the segments saved by `encode_segments` for a buffer.
+pub fn decode_segments(buffer: &BufferRef, dec: &mut Decoder) -> Option<Vec<Arc<Segment>>> {
+    dec.seq(|dec| {
+        let seg = Segment {
+            buffer: buffer.clone(),
+            statements: dec.seq(|dec| {
+                Some(Statement {
+                    stype: *STATEMENT_TYPES.get(dec.u32()? as usize)?,
+                    span: dec.span()?,
+                    label: dec.span()?,
+                    group: dec.i32()?,
+                    group_end: dec.i32()?,
+                    math_start: dec.usize()?,
+                    proof_start: dec.usize()?,
+                    proof_end: dec.usize()?,
+                })
+            })?,
+            span_pool: dec.seq(|dec| dec.span())?,
+            diagnostics: Vec::new(),
+            next_file: dec.span()?,
+            global_dvs: dec.seq(|dec| {
+                Some(GlobalDv {
+                    start: dec.i32()?,
+                    vars: dec.seq(|dec| dec.token())?,
+                })
+            })?,
+            symbols: dec.seq(|dec| {
+                Some(SymbolDef {
+                    name: dec.token()?,
+                    stype: if dec.bool()? {
+                        SymbolType::Constant
+                    } else {
+                        SymbolType::Variable
+                    },
+                    start: dec.i32()?,
+                    ordinal: dec.i32()?,
+                })
+            })?,
+            local_vars: dec.seq(|dec| {
+                Some(LocalVarDef {
+                    index: dec.i32()?,
+                    ordinal: dec.i32()?,
+                })
+            })?,
+            labels: dec.seq(|dec| Some(LabelDef { index: dec.i32()? }))?,
+            floats: dec.seq(|dec| {
+                Some(FloatDef {
+                    start: dec.i32()?,
+                    name: dec.token()?,
+                    label: dec.token()?,
+                    typecode: dec.token()?,
+                })
+            })?,
+            commands: dec.seq(decode_command)?,
+            typesetting: dec.seq(decode_command)?,
+        };
+        // spans are trusted by the accessors, so check them once here
+        let in_buffer = |span: &Span| span.start <= span.end && span.end as usize <= buffer.len();
+        let command_ok = |&(index, ref command): &(StatementIndex, Command)| {
+            index >= 0
+                && (index as usize) < seg.statements.len()
+                && in_buffer(&command.span)
+                && in_buffer(&command.keyword)
+                && command.args.iter().all(|arg| in_buffer(&arg.span))
+        };
+        let valid = in_buffer(&seg.next_file)
+            && seg.span_pool.iter().all(in_buffer)
+            && seg.statements.iter().all(|stmt| {
+                in_buffer(&stmt.span)
+                    && in_buffer(&stmt.label)
+                    && stmt.math_start <= stmt.proof_start
+                    && stmt.proof_start <= stmt.proof_end
+                    && stmt.proof_end <= seg.span_pool.len()
+            })
+            && seg.commands.iter().all(command_ok)
+            && seg.typesetting.iter().all(command_ok);
+        if valid {
+            Some(Arc::new(seg))
+        } else {
+            None
+        }
+    })
+}
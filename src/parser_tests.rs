@@ -111,3 +111,51 @@ parse_test!(
     b"$c X Y\x7F $.",
     [(0, Diagnostic::BadCharacter(6, 0x7F))]
 );
+
+#[test]
+fn test_commands() {
+    let text = b"$( $j syntax 'wff'; /* note */ syntax '|-' as 'wff';
+                    definition 'df-it''s' for \"wit\"; $)";
+    let mut db = mkdb(text);
+    let seg = db.parse_result().segments()[0];
+    assert!(seg.diagnostics.is_empty());
+    let stmt = seg.into_iter().next().unwrap();
+    assert_eq!(stmt.statement_type(), StatementType::AdditionalInfoComment);
+
+    let cmds = db.commands_result().clone();
+    assert_eq!(cmds.keywords(), vec![&b"definition"[..], &b"syntax"[..]]);
+    let sset = db.parse_result().clone();
+    let syntax = cmds.get(b"syntax");
+    assert_eq!(syntax.len(), 2);
+    assert_eq!(syntax[1].args(&sset), vec![&b"|-"[..], b"as", b"wff"]);
+    assert_eq!(syntax[1].command.keyword, Span::new(31, 37));
+    assert_eq!(syntax[1].command.span, Span::new(31, 52));
+    assert!(syntax[1].command.args[0].quoted && !syntax[1].command.args[1].quoted);
+    let def = cmds.get(b"definition");
+    assert_eq!(def[0].args(&sset), vec![&b"df-it''s"[..], b"for", b"wit"]);
+    assert!(cmds.get(b"primitive").is_empty());
+}
+
+parse_test!(
+    test_command_missing_semicolon,
+    b"$( $j syntax 'wff' $)",
+    [(0, Diagnostic::CommandMissingSemicolon(Span::new(6, 18)))]
+);
+parse_test!(
+    test_command_expected_keyword,
+    b"$( $j 'wff'; ; primitive 'wi'; $)",
+    [
+        (0, Diagnostic::CommandExpectedKeyword(Span::new(6, 11))),
+        (0, Diagnostic::CommandExpectedKeyword(Span::new(13, 14))),
+    ]
+);
+parse_test!(
+    test_command_unterminated,
+    b"$( $j syntax 'wff; $)",
+    [(0, Diagnostic::CommandUnterminatedString(Span::new(13, 19)))]
+);
+parse_test!(
+    test_command_unclosed_comment,
+    b"$( $j syntax 'wff'; /* x $)",
+    [(0, Diagnostic::CommandUnclosedComment(Span::new(20, 25)))]
+);
@@ -8,9 +8,11 @@ use parser::StatementRef;
 use parser::TokenPtr;
 use scopeck::ScopeResult;
 use segment_set::SegmentSet;
+use std::cmp;
 use std::cmp::Ord;
 use std::cmp::Ordering;
 use std::cmp::PartialOrd;
+use std::cmp::Reverse;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::BinaryHeap;
 use std::collections::HashMap;
@@ -21,6 +23,7 @@ use std::hash::Hasher;
 use std::ops::Range;
 use std::u16;
 use verify::verify_one;
+use verify::verify_steps;
 use verify::ProofBuilder;
 
 /// A tree structure for storing proofs and grammar derivations.
@@ -133,6 +136,29 @@ impl ProofTreeArray {
         Ok(arr)
     }
 
+    /// Create a proof tree array for a single $p statement from a proof given
+    /// as a list of step labels in normal (uncompressed) form, checking it
+    /// with the verifier in place of the proof in the source
+    pub fn from_steps(
+        sset: &SegmentSet,
+        nset: &Nameset,
+        scopes: &ScopeResult,
+        stmt: StatementRef,
+        steps: &[TokenPtr],
+    ) -> Result<ProofTreeArray, Diagnostic> {
+        let mut arr = ProofTreeArray::default();
+        arr.qed = verify_steps(sset, nset, scopes, &mut arr, stmt, steps)?;
+        arr.indent = arr.calc_indent();
+        Ok(arr)
+    }
+
+    /// Finish an array which was built directly through `ProofBuilder`, such
+    /// as a syntax tree, by setting its QED step
+    pub fn set_qed(&mut self, qed: usize) {
+        self.qed = qed;
+        self.indent = self.calc_indent();
+    }
+
     /// Get the minimum distance from each step to the QED step
     pub fn indent(&self) -> &[u16] {
         &self.indent
@@ -269,6 +295,63 @@ impl ProofTreeArray {
         env.out
     }
 
+    /// Write the proof in compressed form, returning the label roster and the
+    /// string of step letters.
+    ///
+    /// `hyps` lists the mandatory hypotheses of the theorem being proved, in
+    /// frame order; they are numbered implicitly and do not appear in the
+    /// roster.  Roster labels are ordered by decreasing frequency of use so
+    /// that the most common steps get the shortest codes, and every step
+    /// which is used more than once (other than a bare label) is saved with a
+    /// `Z`.
+    pub fn to_compressed(&self, hyps: &[StatementAddress]) -> (Vec<StatementAddress>, String) {
+        let rpn = self.to_rpn(&self.count_parents(), false);
+
+        // count uses of each label not implied by the frame, keeping the order
+        // of first appearance for ties
+        let mut roster: Vec<(StatementAddress, usize)> = Vec::new();
+        let mut roster_index: HashMap<StatementAddress, usize> = HashMap::new();
+        for step in &rpn {
+            if let RPNStep::Normal { addr, .. } = *step {
+                if hyps.contains(&addr) {
+                    continue;
+                }
+                let ix = *roster_index.entry(addr).or_insert_with(|| {
+                    roster.push((addr, 0));
+                    roster.len() - 1
+                });
+                roster[ix].1 += 1;
+            }
+        }
+        roster.sort_by_key(|x| Reverse(x.1));
+
+        let mut numbers: HashMap<StatementAddress, usize> = HashMap::new();
+        for (ix, &addr) in hyps.iter().enumerate() {
+            numbers.insert(addr, ix);
+        }
+        for (ix, &(addr, _)) in roster.iter().enumerate() {
+            numbers.insert(addr, hyps.len() + ix);
+        }
+        let saved_base = hyps.len() + roster.len();
+
+        let mut letters = String::new();
+        for step in rpn {
+            match step {
+                RPNStep::Normal { fwdref, addr, .. } => {
+                    push_compressed_number(&mut letters, numbers[&addr]);
+                    if fwdref != 0 {
+                        letters.push('Z');
+                    }
+                }
+                RPNStep::Backref { backref, .. } => {
+                    push_compressed_number(&mut letters, saved_base + backref - 1);
+                }
+            }
+        }
+
+        (roster.into_iter().map(|(addr, _)| addr).collect(), letters)
+    }
+
     /// Produce an iterator over the steps in the proof in
     /// normal/uncompressed mode. (Because this can potentially
     /// be *very* long, we do not store the list and just stream it.)
@@ -281,6 +364,20 @@ impl ProofTreeArray {
     }
 }
 
+/// Appends the compressed-proof encoding of a zero-based step number: the last
+/// letter is a base 20 digit from `A`-`T`, and any preceding letters are
+/// bijective base 5 digits from `U`-`Y`.
+fn push_compressed_number(out: &mut String, mut num: usize) {
+    let mut digits = vec![b'A' + (num % 20) as u8];
+    num /= 20;
+    while num > 0 {
+        num -= 1;
+        digits.push(b'U' + (num % 5) as u8);
+        num /= 5;
+    }
+    out.extend(digits.iter().rev().map(|&ch| ch as char));
+}
+
 /// An iterator which loops over the steps of the proof in tree order
 /// (with repetition for duplicate subtrees).
 #[derive(Debug)]
@@ -362,7 +459,7 @@ impl ProofBuilder for ProofTreeArray {
 }
 
 /// List of possible proof output types.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ProofStyle {
     /// `/compressed` proof output (default). Label list followed by step letters.
     Compressed,
@@ -404,8 +501,13 @@ impl<'a> fmt::Display for ProofTreePrinter<'a> {
         for _ in 0..self.indent {
             indent.push(' ');
         }
-        f.write_str(&indent[(self.initial_chr + 2) as usize..])?;
-        let mut chr = self.indent - 1;
+        // pad out to the indentation column unless we are already past it
+        let mut chr = if self.initial_chr + 1 < self.indent {
+            f.write_str(&indent[(self.initial_chr + 2) as usize..])?;
+            self.indent - 1
+        } else {
+            self.initial_chr
+        };
         let parents = self.arr.count_parents();
 
         let explicit = match self.style {
@@ -435,7 +537,7 @@ impl<'a> fmt::Display for ProofTreePrinter<'a> {
             });
         }
 
-        let mut print_step = |item: RPNStep| -> fmt::Result {
+        let format_step = |item: RPNStep| -> String {
             let estr = |hyp| {
                 if explicit {
                     format!(
@@ -449,7 +551,7 @@ impl<'a> fmt::Display for ProofTreePrinter<'a> {
                     String::new()
                 }
             };
-            let fmt = match item {
+            match item {
                 RPNStep::Normal { fwdref, addr, hyp } => {
                     if fwdref == 0 {
                         format!("{}{}", estr(hyp), stmt_lookup[&addr].0)
@@ -458,28 +560,60 @@ impl<'a> fmt::Display for ProofTreePrinter<'a> {
                     }
                 }
                 RPNStep::Backref { backref, hyp } => format!("{}{}", estr(hyp), backref),
-            };
+            }
+        };
 
-            if chr + (fmt.len() as u16) < self.line_width {
-                chr += (fmt.len() as u16) + 1;
+        // A breakable word (the letter block of a compressed proof) fills the
+        // rest of the current line and continues on as many lines as needed,
+        // instead of moving to a new line as a whole.
+        let mut print_word = |mut word: &str, breakable: bool| -> fmt::Result {
+            if chr + (word.len() as u16) < self.line_width {
+                chr += (word.len() as u16) + 1;
                 f.write_char(' ')?;
-            } else {
-                chr = self.indent + (fmt.len() as u16);
-                f.write_str(&indent)?;
+                return f.write_str(word);
+            }
+            if breakable {
+                if chr + 1 < self.line_width {
+                    let (head, tail) = word.split_at((self.line_width - chr - 1) as usize);
+                    f.write_char(' ')?;
+                    f.write_str(head)?;
+                    word = tail;
+                }
+                let room = cmp::max(self.line_width.saturating_sub(self.indent), 1) as usize;
+                while word.len() > room {
+                    let (head, tail) = word.split_at(room);
+                    f.write_str(&indent)?;
+                    f.write_str(head)?;
+                    word = tail;
+                }
             }
-            f.write_str(&fmt)
+            chr = self.indent + (word.len() as u16);
+            f.write_str(&indent)?;
+            f.write_str(word)
         };
 
         match self.style {
-            ProofStyle::Compressed => unimplemented!(),
+            ProofStyle::Compressed => {
+                let hyps: Vec<StatementAddress> = match self.scope.get(self.thm_label) {
+                    Some(frame) => frame.hypotheses.iter().map(|hyp| hyp.address()).collect(),
+                    None => vec![],
+                };
+                let (roster, letters) = self.arr.to_compressed(&hyps);
+                print_word("(", false)?;
+                for addr in roster {
+                    print_word(stmt_lookup[&addr].0, false)?;
+                }
+                print_word(")", false)?;
+                print_word(&letters, true)?;
+            }
             ProofStyle::Normal | ProofStyle::Explicit => {
                 for item in self.arr.normal_iter(explicit) {
-                    print_step(item)?;
+                    print_word(&format_step(item), false)?;
                 }
             }
             ProofStyle::Packed | ProofStyle::PackedExplicit => {
                 for item in self.arr.to_rpn(&parents, explicit) {
-                    print_step(item)?;
+                    print_word(&format_step(item), false)?;
                 }
             }
         }
@@ -0,0 +1,139 @@
+use database::Database;
+use database::DbOptions;
+use diag::Diagnostic;
+use diag::DiagnosticClass;
+use export;
+use proof::ProofStyle;
+use proof::ProofTreeArray;
+use proof::ProofTreePrinter;
+use rewrite;
+use test_util::mkdb;
+
+const DEMO0: &str = "
+  $c 0 + = -> ( ) term wff |- $.
+  $v t r s P Q $.
+  tt $f term t $.
+  tr $f term r $.
+  ts $f term s $.
+  wp $f wff P $.
+  wq $f wff Q $.
+  tze $a term 0 $.
+  tpl $a term ( t + r ) $.
+  weq $a wff t = r $.
+  wim $a wff ( P -> Q ) $.
+  a1 $a |- ( t = r -> ( t = s -> r = s ) ) $.
+  a2 $a |- ( t + 0 ) = t $.
+  ${
+    min $e |- P $.
+    maj $e |- ( P -> Q ) $.
+    mp  $a |- Q $.
+  $}
+  th1 $p |- t = t $=
+    PROOF $.
+";
+
+const TH1_NORMAL: &str = "tt tze tpl tt weq tt tt weq tt a2 tt tze tpl tt weq tt tze tpl tt \
+     weq tt tt weq wim tt a2 tt tze tpl tt tt a1 mp mp";
+
+fn print_proof(db: &mut Database, label: &str, style: ProofStyle) -> String {
+    let sset = db.parse_result().clone();
+    let nset = db.name_result().clone();
+    let scope = db.scope_result().clone();
+    let sref = sset.statement(nset.lookup_label(label.as_bytes()).unwrap().address);
+    let arr = ProofTreeArray::new(&sset, &nset, &scope, sref).unwrap();
+    format!(
+        "{}",
+        ProofTreePrinter {
+            sset: &sset,
+            nset: &nset,
+            scope: &scope,
+            thm_label: sref.label(),
+            style,
+            arr: &arr,
+            initial_chr: 0,
+            indent: 4,
+            line_width: 30,
+        }
+    )
+}
+
+#[test]
+fn test_compressed_roundtrip() {
+    let mut db = mkdb(&DEMO0.replace("PROOF", TH1_NORMAL), DbOptions::default());
+    assert!(db.diag_notations(vec![DiagnosticClass::Verify]).is_empty());
+
+    let compressed = print_proof(&mut db, "th1", ProofStyle::Compressed);
+    assert!(compressed.trim_start().starts_with("( "));
+    assert!(compressed.lines().all(|line| line.len() <= 30));
+
+    let mut db2 = mkdb(&DEMO0.replace("PROOF", &compressed), DbOptions::default());
+    assert!(db2.diag_notations(vec![DiagnosticClass::Verify]).is_empty());
+    let normal = print_proof(&mut db2, "th1", ProofStyle::Normal);
+    assert_eq!(
+        normal.split_whitespace().collect::<Vec<_>>(),
+        TH1_NORMAL.split_whitespace().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_rewrite_proof() {
+    let text = DEMO0.replace("PROOF", TH1_NORMAL);
+    let mut db = mkdb(&text, DbOptions::default());
+    let arr = {
+        let sset = db.parse_result().clone();
+        let nset = db.name_result().clone();
+        let scope = db.scope_result().clone();
+        let sref = sset.statement(nset.lookup_label(b"th1").unwrap().address);
+        ProofTreeArray::new(&sset, &nset, &scope, sref).unwrap()
+    };
+    let files = db
+        .rewrite_proofs(&[("th1".to_owned(), arr)], ProofStyle::Compressed)
+        .unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].0, "test.mm");
+
+    let new_text = String::from_utf8(files[0].1.clone()).unwrap();
+    let keyword = text.find("$=").unwrap() + 2;
+    assert_eq!(new_text[..keyword], text[..keyword]);
+    assert!(new_text[keyword..].starts_with("\n    ( "));
+    assert!(new_text.ends_with(" $.\n"));
+
+    let mut db2 = mkdb(&new_text, DbOptions::default());
+    assert!(db2.diag_notations(vec![DiagnosticClass::Verify]).is_empty());
+    let normal = print_proof(&mut db2, "th1", ProofStyle::Normal);
+    assert_eq!(
+        normal.split_whitespace().collect::<Vec<_>>(),
+        TH1_NORMAL.split_whitespace().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_rewrite_errors() {
+    // the parser does not accept a $p statement with an empty math string
+    let text = DEMO0.replace("PROOF", TH1_NORMAL) + "  th0 $p $= ? $.\n";
+    let mut db = mkdb(&text, DbOptions::default());
+    let sset = db.parse_result().clone();
+    let nset = db.name_result().clone();
+    let scope = db.scope_result().clone();
+    let th1 = sset.statement(nset.lookup_label(b"th1").unwrap().address);
+    let arr = ProofTreeArray::new(&sset, &nset, &scope, th1).unwrap();
+
+    let th0 = th1
+        .segment()
+        .into_iter()
+        .find(|stmt| stmt.label() == b"th0")
+        .unwrap();
+    assert_eq!(
+        rewrite::rewrite_proofs(&sset, &nset, &scope, &[(th0, &arr)], ProofStyle::Compressed),
+        Err(Diagnostic::NotProvable)
+    );
+
+    match db.rewrite_proofs(&[("a1".to_owned(), arr.clone())], ProofStyle::Compressed) {
+        Err(export::ExportError::Verify(Diagnostic::NotProvable)) => {}
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+    match db.rewrite_proofs(&[("nosuch".to_owned(), arr)], ProofStyle::Compressed) {
+        Err(export::ExportError::UnknownLabel(label)) => assert_eq!(label, "nosuch"),
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
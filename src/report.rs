@@ -0,0 +1,176 @@
+//! Machine-readable output of notations, for tools such as continuous
+//! integration dashboards.
+//!
+//! A `Report` holds the same information as a `Notation`, but with the span
+//! resolved to line and column numbers and the source text dropped, so that it
+//! can outlive the database.  Reports can be serialized as a plain JSON array,
+//! or as a [SARIF 2.1.0](https://docs.oasis-open.org/sarif/sarif/v2.1.0/) log
+//! for upload to code review tools.  Columns count bytes from 1, and end
+//! positions are exclusive.
+
+use diag::Level;
+use diag::Notation;
+use json::Json;
+use line_cache::LineCache;
+
+/// The URI of the schema of SARIF 2.1.0 logs.
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// A notation with its location resolved.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Report {
+    /// Name of the source file.
+    pub source: String,
+    /// Line of the start of the span, counting from 1.
+    pub line: u32,
+    /// Column of the start of the span, counting from 1.
+    pub column: u32,
+    /// Line of the end of the span.
+    pub end_line: u32,
+    /// Column just after the end of the span.
+    pub end_column: u32,
+    /// Severity of the notation.
+    pub level: Level,
+    /// Name of the diagnostic, from `Notation::code`.
+    pub code: String,
+    /// The message with its arguments substituted.
+    pub message: String,
+    /// Values of the placeholders of the message.
+    pub args: Vec<(String, String)>,
+}
+
+impl Report {
+    /// Resolves the location of a notation.
+    pub fn new(lc: &mut LineCache, notation: &Notation) -> Report {
+        let source = &notation.source;
+        let start = (notation.span.start + source.span.start) as usize;
+        let end = (notation.span.end + source.span.start) as usize;
+        let (line, column) = lc.from_offset(&source.text, start);
+        let (end_line, end_column) = lc.from_offset(&source.text, end);
+        Report {
+            source: source.name.clone(),
+            line,
+            column,
+            end_line,
+            end_column,
+            level: notation.level,
+            code: notation.code.clone(),
+            message: notation.formatted_message(),
+            args: notation
+                .args
+                .iter()
+                .map(|&(id, ref value)| (id.to_owned(), value.clone()))
+                .collect(),
+        }
+    }
+
+    /// Name of the level, as used by SARIF.
+    pub fn level_name(&self) -> &'static str {
+        match self.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+        }
+    }
+
+    fn args_json(&self) -> Json {
+        Json::Object(
+            self.args
+                .iter()
+                .map(|(id, value)| (id.clone(), value.as_str().into()))
+                .collect(),
+        )
+    }
+
+    /// Serializes the report as a JSON object.
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("source", self.source.as_str().into()),
+            ("line", self.line.into()),
+            ("column", self.column.into()),
+            ("end_line", self.end_line.into()),
+            ("end_column", self.end_column.into()),
+            ("level", self.level_name().into()),
+            ("code", self.code.as_str().into()),
+            ("message", self.message.as_str().into()),
+            ("args", self.args_json()),
+        ])
+    }
+
+    fn to_sarif(&self) -> Json {
+        let region = Json::object(vec![
+            ("startLine", self.line.into()),
+            ("startColumn", self.column.into()),
+            ("endLine", self.end_line.into()),
+            ("endColumn", self.end_column.into()),
+        ]);
+        let location = Json::object(vec![(
+            "physicalLocation",
+            Json::object(vec![
+                (
+                    "artifactLocation",
+                    Json::object(vec![("uri", self.source.as_str().into())]),
+                ),
+                ("region", region),
+            ]),
+        )]);
+        Json::object(vec![
+            ("ruleId", self.code.as_str().into()),
+            ("level", self.level_name().into()),
+            (
+                "message",
+                Json::object(vec![("text", self.message.as_str().into())]),
+            ),
+            ("locations", vec![location].into()),
+            ("properties", Json::object(vec![("args", self.args_json())])),
+        ])
+    }
+}
+
+/// Serializes reports as a JSON array of objects.
+pub fn json_log(reports: &[Report]) -> Json {
+    reports
+        .iter()
+        .map(Report::to_json)
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Serializes reports as a SARIF log with a single run.
+pub fn sarif_log(reports: &[Report]) -> Json {
+    let mut rules: Vec<&str> = Vec::new();
+    for report in reports {
+        if !rules.contains(&report.code.as_str()) {
+            rules.push(&report.code);
+        }
+    }
+    let driver = Json::object(vec![
+        ("name", "smetamath".into()),
+        ("version", env!("CARGO_PKG_VERSION").into()),
+        ("informationUri", env!("CARGO_PKG_REPOSITORY").into()),
+        (
+            "rules",
+            rules
+                .into_iter()
+                .map(|rule| Json::object(vec![("id", rule.into())]))
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+    ]);
+    let run = Json::object(vec![
+        ("tool", Json::object(vec![("driver", driver)])),
+        (
+            "results",
+            reports
+                .iter()
+                .map(Report::to_sarif)
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+    ]);
+    Json::object(vec![
+        ("$schema", SARIF_SCHEMA.into()),
+        ("version", "2.1.0".into()),
+        ("runs", vec![run].into()),
+    ])
+}
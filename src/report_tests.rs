@@ -0,0 +1,57 @@
+use database::DbOptions;
+use diag::DiagnosticClass;
+use diag::Level;
+use json::Json;
+use line_cache::LineCache;
+use report;
+use report::Report;
+use test_util::mkdb;
+
+const DB: &str = "$c wff |- $.
+    $v ph $.
+    wph $f wff ph $.
+    th1 $p |- ph $= wph nothere $.";
+
+#[test]
+fn test_reports() {
+    let mut db = mkdb(DB, DbOptions::default());
+    let mut lc = LineCache::default();
+    let reports = db
+        .diag_notations(vec![DiagnosticClass::Verify])
+        .iter()
+        .map(|notation| Report::new(&mut lc, notation))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reports,
+        vec![Report {
+            source: "test.mm".to_owned(),
+            line: 4,
+            column: 5,
+            end_line: 4,
+            end_column: 35,
+            level: Level::Error,
+            code: "StepMissing".to_owned(),
+            message: "Step nothere referenced by proof does not correspond to a $p statement \
+                      (or is malformed)"
+                .to_owned(),
+            args: vec![("step".to_owned(), "nothere".to_owned())],
+        }]
+    );
+
+    let json = Json::parse(&report::json_log(&reports).to_string()).unwrap();
+    assert_eq!(json.as_array().map(|reports| reports.len()), Some(1));
+    assert_eq!(
+        json.as_array().unwrap()[0].get("line"),
+        Some(&Json::from(4u32))
+    );
+
+    let sarif = report::sarif_log(&reports);
+    assert_eq!(sarif.get("version").and_then(Json::as_str), Some("2.1.0"));
+    let run = &sarif.get("runs").and_then(Json::as_array).unwrap()[0];
+    let result = &run.get("results").and_then(Json::as_array).unwrap()[0];
+    assert_eq!(
+        result.get("ruleId").and_then(Json::as_str),
+        Some("StepMissing")
+    );
+    assert_eq!(result.get("level").and_then(Json::as_str), Some("error"));
+}
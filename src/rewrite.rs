@@ -0,0 +1,147 @@
+//! Source-preserving replacement of proofs.
+//!
+//! Only the proofs of the requested `$p` statements are touched; every other
+//! byte of the affected source files, including comments and whitespace, is
+//! kept exactly as it was read.  This allows reformatting or minimization tools
+//! to be run over a large database while producing a minimal diff.
+
+use diag::Diagnostic;
+use nameck::Nameset;
+use parser::Span;
+use parser::StatementRef;
+use parser::StatementType;
+use proof::ProofStyle;
+use proof::ProofTreeArray;
+use proof::ProofTreePrinter;
+use scopeck::ScopeResult;
+use segment_set::SegmentSet;
+use segment_set::SourceInfo;
+use std::sync::Arc;
+
+/// A replacement of the byte range `start..end` of a file by new text.
+type Edit = (usize, usize, String);
+
+/// Maximum width of the lines generated for a proof.
+const LINE_WIDTH: u16 = 79;
+
+/// Finds the `$=` keyword of a provable statement, which must be the first
+/// token after the math string that is not part of a comment.
+fn find_proof_keyword(stmt: StatementRef) -> Option<Span> {
+    let buf = &stmt.segment().buffer;
+    // with an empty math string, scan from the label past the `$p` keyword
+    let (mut pos, mut math_seen) = match stmt.math_len() {
+        0 => (stmt.span().start as usize, false),
+        len => (stmt.math_span(len - 1).end as usize, true),
+    };
+    let end = stmt.span().end as usize;
+    let mut in_comment = false;
+    loop {
+        while pos < end && buf[pos] <= b' ' {
+            pos += 1;
+        }
+        if pos == end {
+            return None;
+        }
+        let start = pos;
+        while pos < end && buf[pos] > b' ' {
+            pos += 1;
+        }
+        let token = &buf[start..pos];
+        if in_comment {
+            in_comment = token != b"$)";
+        } else if token == b"$(" {
+            in_comment = true;
+        } else if !math_seen {
+            math_seen = token == b"$p";
+        } else if token == b"$=" {
+            return Some(Span::new(start, pos));
+        } else {
+            return None;
+        }
+    }
+}
+
+/// Re-encodes the proofs of some `$p` statements and splices them into the
+/// text of their source files.
+///
+/// Each new proof is written in the given style, starting on the line after the
+/// `$=` and indented two columns past the statement label as is conventional
+/// in set.mm.  The old proof is replaced up to its last token, so the
+/// whitespace before the closing `$.` is retained.
+///
+/// Returns the name and complete new contents of each source file containing
+/// at least one of the statements, in order of first appearance in `proofs`.
+/// A statement whose proof has no `$=` keyword is reported as
+/// `Diagnostic::MissingProof`, and one which is not a `$p` statement as
+/// `Diagnostic::NotProvable`.
+pub fn rewrite_proofs(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scope: &ScopeResult,
+    proofs: &[(StatementRef, &ProofTreeArray)],
+    style: ProofStyle,
+) -> Result<Vec<(String, Vec<u8>)>, Diagnostic> {
+    let mut files: Vec<(&Arc<SourceInfo>, Vec<Edit>)> = Vec::new();
+
+    for &(stmt, arr) in proofs {
+        if stmt.statement_type() != StatementType::Provable {
+            return Err(Diagnostic::NotProvable);
+        }
+        let keyword = find_proof_keyword(stmt).ok_or(Diagnostic::MissingProof(stmt.span()))?;
+        let proof_end = if stmt.proof_len() == 0 {
+            keyword.end
+        } else {
+            stmt.proof_span(stmt.proof_len() - 1).end
+        };
+
+        let sinfo = sset.source_info(stmt.segment().id);
+        let base = sinfo.span.start as usize;
+        let label_pos = base + stmt.span().start as usize;
+        let line_start = sinfo.text[..label_pos]
+            .iter()
+            .rposition(|&ch| ch == b'\n')
+            .map_or(0, |nl| nl + 1);
+
+        // starting past the line width forces the first token onto a new line
+        let text = format!(
+            "{}",
+            ProofTreePrinter {
+                sset,
+                nset,
+                scope,
+                thm_label: stmt.label(),
+                style,
+                arr,
+                initial_chr: LINE_WIDTH,
+                indent: (label_pos - line_start) as u16 + 2,
+                line_width: LINE_WIDTH,
+            }
+        );
+
+        let edit = (base + keyword.end as usize, base + proof_end as usize, text);
+        match files
+            .iter()
+            .position(|&(fsinfo, _)| fsinfo.name == sinfo.name)
+        {
+            Some(ix) => files[ix].1.push(edit),
+            None => files.push((sinfo, vec![edit])),
+        }
+    }
+
+    Ok(files
+        .into_iter()
+        .map(|(sinfo, mut edits)| {
+            edits.sort_by_key(|edit| edit.0);
+            let mut out = Vec::with_capacity(sinfo.text.len());
+            let mut pos = 0;
+            for (start, end, text) in edits {
+                assert!(start >= pos, "overlapping proof replacements");
+                out.extend_from_slice(&sinfo.text[pos..start]);
+                out.extend_from_slice(text.as_bytes());
+                pos = end;
+            }
+            out.extend_from_slice(&sinfo.text[pos..]);
+            (sinfo.name.clone(), out)
+        })
+        .collect())
+}
@@ -29,6 +29,10 @@
 //! segment, tracking the active `$e` and `$f` statements at each point.
 
 use bit_set::Bitset;
+use cache::Decoder;
+use cache::DiskCache;
+use cache::Encoder;
+use cache::SegmentKeys;
 use diag::Diagnostic;
 use nameck::Atom;
 use nameck::NameReader;
@@ -922,6 +926,145 @@ fn scope_check_single(sset: &SegmentSet, names: &Nameset, seg: SegmentRef) -> Se
     }
 }
 
+fn encode_atom(enc: &mut Encoder, names: &Nameset, atom: Atom) {
+    enc.bytes(names.atom_name(atom));
+}
+
+fn decode_atom(dec: &mut Decoder, names: &Nameset) -> Option<Atom> {
+    names.lookup_symbol(dec.bytes()?).map(|lookup| lookup.atom)
+}
+
+fn encode_expr(enc: &mut Encoder, names: &Nameset, expr: &VerifyExpr) {
+    encode_atom(enc, names, expr.typecode);
+    enc.usize(expr.rump.start);
+    enc.usize(expr.rump.end);
+    enc.seq(&expr.tail, |enc, frag| {
+        enc.usize(frag.prefix.start);
+        enc.usize(frag.prefix.end);
+        enc.usize(frag.var);
+    });
+}
+
+fn decode_expr(dec: &mut Decoder, names: &Nameset) -> Option<VerifyExpr> {
+    Some(VerifyExpr {
+        typecode: decode_atom(dec, names)?,
+        rump: dec.usize()?..dec.usize()?,
+        tail: dec
+            .seq(|dec| {
+                Some(ExprFragment {
+                    prefix: dec.usize()?..dec.usize()?,
+                    var: dec.usize()?,
+                })
+            })?
+            .into_boxed_slice(),
+    })
+}
+
+/// Serializes the frames of a segment for the disk cache, or returns `None` if
+/// the segment has diagnostics.
+///
+/// Atoms are saved as their names, and the label atom and statement type are
+/// taken from the statement on loading.
+fn encode_scope_result(
+    res: &SegmentScopeResult,
+    keys: &SegmentKeys,
+    names: &Nameset,
+) -> Option<Encoder> {
+    if !res.diagnostics.is_empty() {
+        return None;
+    }
+    let mut enc = Encoder::default();
+    enc.seq(&res.frames_out, |enc, frame| {
+        enc.i32(frame.valid.start.index);
+        enc.i32(frame.valid.end);
+        enc.bytes(&frame.const_pool);
+        enc.seq(&frame.hypotheses, |enc, hyp| match *hyp {
+            Hyp::Essential(addr, ref expr) => {
+                enc.bool(true);
+                keys.encode_address(enc, addr);
+                encode_expr(enc, names, expr);
+            }
+            Hyp::Floating(addr, var, typecode) => {
+                enc.bool(false);
+                keys.encode_address(enc, addr);
+                enc.usize(var);
+                encode_atom(enc, names, typecode);
+            }
+        });
+        encode_expr(enc, names, &frame.target);
+        enc.bytes(&frame.stub_expr);
+        enc.seq(&frame.var_list, |enc, &atom| encode_atom(enc, names, atom));
+        enc.usize(frame.mandatory_count);
+        enc.seq(&frame.mandatory_dv, |enc, &(var1, var2)| {
+            enc.usize(var1);
+            enc.usize(var2);
+        });
+        enc.seq(&frame.optional_dv, |enc, bits| {
+            enc.seq(&bits.into_iter().collect::<Vec<_>>(), |enc, &bit| {
+                enc.usize(bit)
+            });
+        });
+    });
+    Some(enc)
+}
+
+/// Rebuilds the scope result of a segment saved by `encode_scope_result`.
+fn decode_scope_result(
+    dec: &mut Decoder,
+    sref: SegmentRef,
+    keys: &SegmentKeys,
+    names: &Nameset,
+) -> Option<SegmentScopeResult> {
+    let frames_out = dec.seq(|dec| {
+        let start = StatementAddress::new(sref.id, dec.i32()?);
+        let stmt = sref.statement(start.index);
+        Some(Frame {
+            stype: stmt.statement_type(),
+            valid: GlobalRange {
+                start,
+                end: dec.i32()?,
+            },
+            label_atom: names.lookup_label(stmt.label())?.atom,
+            const_pool: dec.bytes()?.into(),
+            hypotheses: dec
+                .seq(|dec| {
+                    if dec.bool()? {
+                        let addr = keys.decode_address(dec)?;
+                        Some(Hyp::Essential(addr, decode_expr(dec, names)?))
+                    } else {
+                        let addr = keys.decode_address(dec)?;
+                        let var = dec.usize()?;
+                        Some(Hyp::Floating(addr, var, decode_atom(dec, names)?))
+                    }
+                })?
+                .into_boxed_slice(),
+            target: decode_expr(dec, names)?,
+            stub_expr: dec.bytes()?.into(),
+            var_list: dec.seq(|dec| decode_atom(dec, names))?.into_boxed_slice(),
+            mandatory_count: dec.usize()?,
+            mandatory_dv: dec
+                .seq(|dec| Some((dec.usize()?, dec.usize()?)))?
+                .into_boxed_slice(),
+            optional_dv: dec
+                .seq(|dec| {
+                    let mut bits = Bitset::new();
+                    for bit in dec.seq(|dec| dec.usize())? {
+                        bits.set_bit(bit);
+                    }
+                    Some(bits)
+                })?
+                .into_boxed_slice(),
+        })
+    })?;
+    Some(SegmentScopeResult {
+        id: sref.id,
+        source: (*sref).clone(),
+        name_usage: NameUsage::default(),
+        diagnostics: new_map(),
+        frames_out,
+    })
+}
+
 /// Data generated by scope checking for a database.
 ///
 /// To extract frames, use a `ScopeReader`.
@@ -966,6 +1109,8 @@ pub fn scope_check(result: &mut ScopeResult, segments: &Arc<SegmentSet>, names:
     result.incremental &= segments.options.incremental;
     result.generation += 1;
     let gen = result.generation;
+    let disk =
+        DiskCache::new(&segments.options).map(|disk| (disk, Arc::new(SegmentKeys::new(segments))));
     let mut ssrq = VecDeque::new();
     // process all segments in parallel to get new scope results or identify
     // reusable ones
@@ -979,6 +1124,7 @@ pub fn scope_check(result: &mut ScopeResult, segments: &Arc<SegmentSet>, names:
             let names = names.clone();
             let id = sref.id;
             let osr = prev.get(&id).and_then(|x| x.clone());
+            let disk = disk.clone();
             ssrq.push_back(segments.exec.exec(sref.bytes(), move || {
                 let sref = segments2.segment(id);
                 if let Some(old_res) = osr {
@@ -987,10 +1133,24 @@ pub fn scope_check(result: &mut ScopeResult, segments: &Arc<SegmentSet>, names:
                         return None;
                     }
                 }
+                if let Some((ref disk, ref keys)) = disk {
+                    let loaded = disk.load_with("scope", keys.key(id), |dec| {
+                        decode_scope_result(dec, sref, keys, &names)
+                    });
+                    if let Some(res) = loaded {
+                        return Some(Arc::new(res));
+                    }
+                }
                 if segments2.options.trace_recalc {
                     println!("scopeck({:?})", parser::guess_buffer_name(&sref.buffer));
                 }
-                Some(Arc::new(scope_check_single(&segments2, &names, sref)))
+                let res = scope_check_single(&segments2, &names, sref);
+                if let Some((ref disk, ref keys)) = disk {
+                    if let Some(enc) = encode_scope_result(&res, keys, &names) {
+                        disk.store("scope", keys.key(id), enc);
+                    }
+                }
+                Some(Arc::new(res))
             }));
         }
     }
@@ -1092,6 +1252,10 @@ impl<'a> ScopeReader<'a> {
 }
 
 /// Holds a list of frames read during the lifetime of a `ScopeReader`.
+///
+/// The default usage is never valid, for results which were not computed from
+/// this scope result.
+#[derive(Default)]
 pub struct ScopeUsage {
     generation: usize,
     incremental: bool,
@@ -1116,4 +1280,13 @@ impl ScopeUsage {
                 .iter()
                 .all(|name| !res.frame_index.contains_key(name))
     }
+
+    /// Adds the frames used by another `ScopeReader` on the same result, for
+    /// work which was divided between several readers.
+    pub fn merge(&mut self, other: ScopeUsage) {
+        self.generation = self.generation.min(other.generation);
+        self.incremental &= other.incremental;
+        self.found.extend(other.found);
+        self.not_found.extend(other.not_found);
+    }
 }
@@ -26,6 +26,9 @@
 //! speeds up operation when local changes are made to large files, or when
 //! modification times cannot be used (such as some language server scenarios).
 //!
+//! If `DbOptions::cache_dir` is set, a slice which misses the second cache is
+//! then looked up by content in the persistent `cache`, before being parsed.
+//!
 //! In either case, some care is needed to retain only data which is relevant in
 //! the cache.  The caches are discarded on every read, but any data obtained
 //! from a cache miss is forwarded immediatly to the new cache; and hits in the
@@ -48,12 +51,15 @@
 //! would make changing the beginning and end at the same time faster, and is
 //! attractive future work.
 
+use cache;
+use cache::DiskCache;
 use database::DbOptions;
 use database::Executor;
 use database::Promise;
 use diag::Diagnostic;
 use filetime::FileTime;
 use parser;
+use parser::BufferRef;
 use parser::Comparer;
 use parser::Segment;
 use parser::SegmentId;
@@ -124,6 +130,28 @@ struct SliceSR(Option<LongBuf>, Vec<Arc<Segment>>, Arc<SourceInfo>);
 #[derive(Debug, Clone)]
 struct FileSR(Option<(String, FileTime)>, Vec<SliceSR>);
 
+/// Parses a slice, or loads its segments from the persistent cache.
+fn parse_or_load(options: &DbOptions, buffer: &BufferRef) -> Vec<Arc<Segment>> {
+    let disk = DiskCache::new(options).map(|disk| (disk, cache::content_hash(buffer)));
+    if let Some((ref disk, key)) = disk {
+        if let Some(segments) =
+            disk.load_with("parse", key, |dec| parser::decode_segments(buffer, dec))
+        {
+            return segments;
+        }
+    }
+    if options.trace_recalc {
+        println!("parse({:?})", parser::guess_buffer_name(buffer));
+    }
+    let segments = parser::parse_segments(buffer);
+    if let Some((ref disk, key)) = disk {
+        if let Some(enc) = parser::encode_segments(&segments) {
+            disk.store("parse", key, enc);
+        }
+    }
+    segments
+}
+
 /// SegmentSet is a container for parsed databases.
 ///
 /// If you're not writing an analysis pass you want to handle this through
@@ -153,6 +181,8 @@ pub struct SegmentSet {
     file_cache: HashMap<(String, FileTime), FileSR>,
     /// Second cache as described in the module comment.
     parse_cache: HashMap<LongBuf, Vec<Arc<Segment>>>,
+    /// Files which could not be read by the last load.
+    missing_files: Vec<String>,
 }
 
 impl SegmentSet {
@@ -166,6 +196,7 @@ impl SegmentSet {
             segments: new_map(),
             parse_cache: new_map(),
             file_cache: new_map(),
+            missing_files: Vec::new(),
         }
     }
 
@@ -212,6 +243,20 @@ impl SegmentSet {
         self.segment(addr.segment_id).statement(addr.index)
     }
 
+    /// Lists the files which were read from disk by the last `read`, including
+    /// those reached by file inclusion, with their modification times.
+    pub fn file_times(&self) -> Vec<(String, FileTime)> {
+        let mut out: Vec<(String, FileTime)> = self.file_cache.keys().cloned().collect();
+        out.sort();
+        out
+    }
+
+    /// Returns the paths of the files which could not be read when the
+    /// database was loaded, in sorted order.
+    pub fn missing_files(&self) -> &[String] {
+        &self.missing_files
+    }
+
     /// Reports any parse errors associated with loaded segments.
     pub fn parse_diagnostics(&self) -> Vec<(StatementAddress, Diagnostic)> {
         let mut out = Vec::new();
@@ -245,6 +290,8 @@ impl SegmentSet {
             /// segments which have been placed in the order so far
             segments: SegList,
             included: HashSet<String>,
+            /// files which could not be read
+            missing: Vec<String>,
             preload: HashMap<String, Vec<u8>>,
             exec: Executor,
         }
@@ -305,13 +352,11 @@ impl SegmentSet {
                         promises.push(Promise::new(sres));
                     }
                     None => {
-                        let trace = state.options.trace_recalc;
+                        let options = state.options.clone();
                         // parse it on a worker thread
                         promises.push(state.exec.exec(partbuf.len(), move || {
-                            if trace {
-                                println!("parse({:?})", parser::guess_buffer_name(&partbuf));
-                            }
-                            SliceSR(Some(cachekey), parser::parse_segments(&partbuf), srcinfo)
+                            let segments = parse_or_load(&options, &partbuf);
+                            SliceSR(Some(cachekey), segments, srcinfo)
                         }));
                     }
                 }
@@ -365,6 +410,7 @@ impl SegmentSet {
                     canonicalize_and_read(state, path.clone()).unwrap_or_else(|cerr| {
                         // read failed, insert a bogus segment so we have a
                         // place to hang the errors
+                        state.missing.push(path.clone());
                         let sinfo = SourceInfo {
                             name: path.clone(),
                             text: Arc::new(Vec::new()),
@@ -444,6 +490,7 @@ impl SegmentSet {
             new_by_time: new_map(),
             segments: Vec::new(),
             included: new_set(),
+            missing: Vec::new(),
             preload: data.into_iter().collect(),
             exec: self.exec.clone(),
         };
@@ -487,6 +534,8 @@ impl SegmentSet {
         // and later passes will be able to leverage that similarity
         self.parse_cache = state.new_by_content;
         self.file_cache = state.new_by_time;
+        self.missing_files = state.missing;
+        self.missing_files.sort();
 
         while old_r.start < old_r.end && new_r.start < new_r.end {
             self.segments
@@ -0,0 +1,40 @@
+use database::Database;
+use database::DbOptions;
+use std::env;
+use std::fs;
+
+#[test]
+fn test_file_times() {
+    let dir = env::temp_dir().join(format!("smetamath-files-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let main = dir.join("main.mm").to_str().unwrap().to_owned();
+    let inc = dir.join("inc.mm").to_str().unwrap().to_owned();
+    let missing = dir.join("missing.mm").to_str().unwrap().to_owned();
+    fs::write(
+        &main,
+        format!(
+            "$c wff $.\n$[ {} $]\n$[ other.mm $]\n$[ {} $]\n",
+            inc, missing
+        ),
+    )
+    .unwrap();
+    fs::write(&inc, "$v ph $.\n").unwrap();
+
+    // files given as text are not read from disk
+    let mut db = Database::new(DbOptions::default());
+    db.parse(
+        main.clone(),
+        vec![("other.mm".to_owned(), b"$c |- $.".to_vec())],
+    );
+    let files = db
+        .parse_result()
+        .file_times()
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect::<Vec<_>>();
+    let mut expected = vec![main, inc];
+    expected.sort();
+    assert_eq!(files, expected);
+    assert_eq!(db.parse_result().missing_files(), &[missing][..]);
+    let _ = fs::remove_dir_all(&dir);
+}
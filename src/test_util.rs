@@ -0,0 +1,19 @@
+//! Helpers shared by the unit tests.
+
+use database::Database;
+use database::DbOptions;
+
+/// Creates a database with some options, and loads it from a single file.
+pub fn mkdb(text: &str, options: DbOptions) -> Database {
+    let mut db = Database::new(options);
+    reparse(&mut db, text);
+    db
+}
+
+/// Replaces the file of a database created by `mkdb`, for incremental tests.
+pub fn reparse(db: &mut Database, text: &str) {
+    db.parse(
+        "test.mm".to_owned(),
+        vec![("test.mm".to_owned(), text.as_bytes().to_owned())],
+    );
+}
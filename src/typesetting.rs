@@ -0,0 +1,294 @@
+//! Typesetting definitions from `$t` comments.
+//!
+//! A `$t` comment holds commands which tell a website or LaTeX generator how
+//! to display math symbols, such as `htmldef "->" as " &rarr; ";`, and
+//! properties of the generated pages, such as `htmltitle "...";`.  Values may
+//! be split into several strings joined by `+`.  The parser splits the comments
+//! into commands; this pass interprets the commands of each segment, reusing
+//! the results for unchanged segments, and then combines them into a table.
+
+use diag::Diagnostic;
+use nameck::Nameset;
+use parser;
+use parser::Command;
+use parser::Segment;
+use parser::SegmentId;
+use parser::Span;
+use parser::StatementAddress;
+use parser::Token;
+use segment_set::SegmentSet;
+use std::mem;
+use std::sync::Arc;
+use util::new_map;
+use util::ptr_eq;
+use util::HashMap;
+
+/// The tables of symbol definitions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TypesettingKind {
+    /// `htmldef`, used for the GIF-based HTML pages.
+    Html,
+    /// `althtmldef`, used for the Unicode HTML pages.
+    AltHtml,
+    /// `latexdef`, used for LaTeX output.
+    Latex,
+}
+
+impl TypesettingKind {
+    fn from_keyword(keyword: &[u8]) -> Option<TypesettingKind> {
+        match keyword {
+            b"htmldef" => Some(TypesettingKind::Html),
+            b"althtmldef" => Some(TypesettingKind::AltHtml),
+            b"latexdef" => Some(TypesettingKind::Latex),
+            _ => None,
+        }
+    }
+}
+
+/// Commands which set a property of the generated pages rather than define a
+/// symbol.
+const DIRECTIVE_KEYWORDS: &[&[u8]] = &[
+    b"htmltitle",
+    b"htmlhome",
+    b"exthtmltitle",
+    b"exthtmlhome",
+    b"exthtmllabel",
+    b"htmldir",
+    b"althtmldir",
+    b"htmlbibliography",
+    b"exthtmlbibliography",
+    b"htmlvarcolor",
+    b"htmlcss",
+    b"htmlfont",
+    b"htmlexturl",
+];
+
+/// A value defined by a `$t` command.
+#[derive(Clone, Debug)]
+pub struct TypesettingDef {
+    /// Address of the `$t` comment containing the command.
+    pub address: StatementAddress,
+    /// Location of the command in the buffer of the comment's segment.
+    pub span: Span,
+    /// The value, with strings concatenated and quotes unescaped.
+    pub value: Vec<u8>,
+}
+
+/// Stored result of interpreting the `$t` commands of a segment.
+struct TypesettingSegment {
+    source: Arc<Segment>,
+    symbols: Vec<(TypesettingKind, Token, TypesettingDef)>,
+    directives: Vec<(Token, TypesettingDef)>,
+    diagnostics: Vec<(StatementAddress, Diagnostic)>,
+}
+
+/// Analysis pass result for typesetting definitions.
+#[derive(Default, Clone)]
+pub struct TypesettingResult {
+    segments: HashMap<SegmentId, Arc<TypesettingSegment>>,
+    symbols: HashMap<(TypesettingKind, Token), TypesettingDef>,
+    directives: HashMap<Token, TypesettingDef>,
+    diagnostics: Vec<(StatementAddress, Diagnostic)>,
+}
+
+impl TypesettingResult {
+    /// Returns the definition of a math symbol in one of the tables.
+    pub fn symbol(&self, kind: TypesettingKind, symbol: &[u8]) -> Option<&TypesettingDef> {
+        self.symbols.get(&(kind, symbol.into()))
+    }
+
+    /// Returns the value of a page property such as `htmltitle`.
+    pub fn directive(&self, keyword: &[u8]) -> Option<&TypesettingDef> {
+        self.directives.get(keyword)
+    }
+
+    /// Returns true if both results define the same symbols and properties
+    /// with the same values, wherever the definitions are located.
+    pub fn same_table(&self, other: &TypesettingResult) -> bool {
+        fn same<K: Eq + ::std::hash::Hash>(
+            a: &HashMap<K, TypesettingDef>,
+            b: &HashMap<K, TypesettingDef>,
+        ) -> bool {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, def)| b.get(key).is_some_and(|def2| def.value == def2.value))
+        }
+        same(&self.symbols, &other.symbols) && same(&self.directives, &other.directives)
+    }
+
+    /// Report malformed, duplicate, and unusable typesetting commands.
+    pub fn diagnostics(&self) -> Vec<(StatementAddress, Diagnostic)> {
+        let mut out = self.diagnostics.clone();
+        for tsr in self.segments.values() {
+            out.extend(tsr.diagnostics.iter().cloned());
+        }
+        out
+    }
+}
+
+/// Returns the text of a quoted argument with doubled quotes unescaped.
+fn unquote(buffer: &[u8], span: Span) -> Vec<u8> {
+    let quote = buffer[span.start as usize - 1];
+    let mut out = Vec::new();
+    let mut chars = span.as_ref(buffer).iter();
+    while let Some(&chr) = chars.next() {
+        out.push(chr);
+        if chr == quote {
+            chars.next();
+        }
+    }
+    out
+}
+
+/// Reads a value of the form `"..." + "..." + ...` from the arguments.
+fn get_value(buffer: &[u8], args: &[parser::CommandArg]) -> Option<Vec<u8>> {
+    let mut value = Vec::new();
+    for (ix, arg) in args.iter().enumerate() {
+        if ix % 2 == 0 {
+            if !arg.quoted {
+                return None;
+            }
+            value.extend(unquote(buffer, arg.span));
+        } else if arg.quoted || arg.span.as_ref(buffer) != b"+" {
+            return None;
+        }
+    }
+    if args.len() % 2 == 1 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Interprets the `$t` commands of a segment.
+fn typesetting_segment(sset: &SegmentSet, sid: SegmentId) -> TypesettingSegment {
+    let sref = sset.segment(sid);
+    let buffer = &sref.buffer;
+    let mut tsr = TypesettingSegment {
+        source: sref.segment.clone(),
+        symbols: Vec::new(),
+        directives: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+
+    for &(index, ref cmd) in &sref.typesetting {
+        let address = StatementAddress::new(sid, index);
+        let Command {
+            span,
+            keyword,
+            ref args,
+        } = *cmd;
+        let keyword_ref = keyword.as_ref(buffer);
+
+        if let Some(kind) = TypesettingKind::from_keyword(keyword_ref) {
+            let value = if args.len() >= 3
+                && args[0].quoted
+                && !args[1].quoted
+                && args[1].span.as_ref(buffer) == b"as"
+            {
+                get_value(buffer, &args[2..])
+            } else {
+                None
+            };
+            match value {
+                Some(value) => tsr.symbols.push((
+                    kind,
+                    unquote(buffer, args[0].span).into(),
+                    TypesettingDef {
+                        address,
+                        span,
+                        value,
+                    },
+                )),
+                None => tsr
+                    .diagnostics
+                    .push((address, Diagnostic::TypesettingBadCommand(span))),
+            }
+        } else if DIRECTIVE_KEYWORDS.contains(&keyword_ref) {
+            match get_value(buffer, args) {
+                Some(value) => tsr.directives.push((
+                    keyword_ref.into(),
+                    TypesettingDef {
+                        address,
+                        span,
+                        value,
+                    },
+                )),
+                None => tsr
+                    .diagnostics
+                    .push((address, Diagnostic::TypesettingBadCommand(span))),
+            }
+        } else {
+            tsr.diagnostics
+                .push((address, Diagnostic::TypesettingUnknownKeyword(keyword)));
+        }
+    }
+    tsr
+}
+
+/// Calculates or updates the typesetting table for a database.
+///
+/// Each segment's commands are interpreted in parallel, reusing the previous
+/// result if the segment is unchanged; the segments are then combined in
+/// database order, checking for duplicate definitions and definitions of
+/// undeclared symbols.
+pub fn typesetting(result: &mut TypesettingResult, segments: &Arc<SegmentSet>, nset: &Nameset) {
+    let old = mem::replace(&mut result.segments, new_map());
+    let mut tsrq = Vec::new();
+    for sref in segments.segments() {
+        let segments2 = segments.clone();
+        let id = sref.id;
+        let old_res_o = old.get(&id).cloned();
+        tsrq.push(segments.exec.exec(sref.bytes(), move || {
+            let sref = segments2.segment(id);
+            if let Some(old_res) = old_res_o {
+                if ptr_eq::<Segment>(&old_res.source, &sref) {
+                    return (id, old_res);
+                }
+            }
+            if segments2.options.trace_recalc {
+                println!("typesetting({:?})", parser::guess_buffer_name(&sref.buffer));
+            }
+            (id, Arc::new(typesetting_segment(&segments2, id)))
+        }))
+    }
+    for promise in tsrq {
+        let (id, arc) = promise.wait();
+        result.segments.insert(id, arc);
+    }
+
+    result.symbols.clear();
+    result.directives.clear();
+    result.diagnostics.clear();
+    for sref in segments.segments() {
+        let tsr = &result.segments[&sref.id];
+        for &(kind, ref symbol, ref def) in &tsr.symbols {
+            if nset.lookup_symbol(symbol).is_none() {
+                result.diagnostics.push((
+                    def.address,
+                    Diagnostic::TypesettingUndefinedSymbol(def.span),
+                ));
+            }
+            match result.symbols.get(&(kind, symbol.clone())) {
+                Some(prev) => result.diagnostics.push((
+                    def.address,
+                    Diagnostic::TypesettingDuplicate(def.span, prev.address, prev.span),
+                )),
+                None => {
+                    result.symbols.insert((kind, symbol.clone()), def.clone());
+                }
+            }
+        }
+        for (keyword, def) in &tsr.directives {
+            match result.directives.get(keyword) {
+                Some(prev) => result.diagnostics.push((
+                    def.address,
+                    Diagnostic::TypesettingDuplicate(def.span, prev.address, prev.span),
+                )),
+                None => {
+                    result.directives.insert(keyword.clone(), def.clone());
+                }
+            }
+        }
+    }
+}
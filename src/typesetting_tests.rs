@@ -0,0 +1,75 @@
+use database::DbOptions;
+use diag::Diagnostic;
+use parser::Span;
+use test_util::mkdb;
+use typesetting::TypesettingKind;
+
+#[test]
+fn test_typesetting_table() {
+    let mut db = mkdb(
+        "$c -> ( $.
+         $( $t
+           /* arrows */
+           htmldef \"->\" as \" &rarr; \";
+           althtmldef \"->\" as '<span>' + ' &#8594; ' +
+             '</span>';
+           latexdef \"(\" as \"(\";
+           htmltitle \"Don't \"\"panic\"\"\";
+         $)",
+        DbOptions::default(),
+    );
+    let ts = db.typesetting_result().clone();
+    assert!(ts.diagnostics().is_empty());
+    let html = ts.symbol(TypesettingKind::Html, b"->").unwrap();
+    assert_eq!(html.value, b" &rarr; ".to_vec());
+    assert_eq!(
+        ts.symbol(TypesettingKind::AltHtml, b"->").unwrap().value,
+        b"<span> &#8594; </span>".to_vec()
+    );
+    assert_eq!(
+        ts.symbol(TypesettingKind::Latex, b"(").unwrap().value,
+        b"(".to_vec()
+    );
+    assert!(ts.symbol(TypesettingKind::Latex, b"->").is_none());
+    assert_eq!(
+        ts.directive(b"htmltitle").unwrap().value,
+        b"Don't \"panic\"".to_vec()
+    );
+}
+
+#[test]
+fn test_typesetting_errors() {
+    let text = "$c -> $.
+                $( $t htmldef \"->\" as \"a\";
+                      htmldef \"->\" as \"b\";
+                      htmldef \"<-\" as \"c\";
+                      latexdef \"->\" \"d\";
+                      htmlfoo \"e\"; $)";
+    let mut db = mkdb(text, DbOptions::default());
+    let span = |needle: &str| {
+        let start = text.find(needle).unwrap();
+        Span::new(start, start + needle.len())
+    };
+    let first = span("htmldef \"->\" as \"a\";");
+    let mut diags = db
+        .typesetting_result()
+        .diagnostics()
+        .into_iter()
+        .map(|(_, diag)| diag)
+        .collect::<Vec<_>>();
+    diags.sort_by_key(|diag| format!("{:?}", diag));
+    let comment = {
+        let sset = db.parse_result().clone();
+        let seg = sset.segments()[0];
+        seg.into_iter().nth(1).unwrap().address()
+    };
+    assert_eq!(
+        diags,
+        vec![
+            Diagnostic::TypesettingBadCommand(span("latexdef \"->\" \"d\";")),
+            Diagnostic::TypesettingDuplicate(span("htmldef \"->\" as \"b\";"), comment, first),
+            Diagnostic::TypesettingUndefinedSymbol(span("htmldef \"<-\" as \"c\";")),
+            Diagnostic::TypesettingUnknownKeyword(span("htmlfoo")),
+        ]
+    );
+}
@@ -25,7 +25,12 @@
 //! than it is now.
 
 use bit_set::Bitset;
+use cache::Decoder;
+use cache::DiskCache;
+use cache::Encoder;
+use cache::SegmentKeys;
 use diag::Diagnostic;
+use diag::StepInfo;
 use nameck::Atom;
 use nameck::Nameset;
 use parser;
@@ -35,9 +40,12 @@ use parser::Segment;
 use parser::SegmentId;
 use parser::SegmentOrder;
 use parser::SegmentRef;
+use parser::Span;
 use parser::StatementAddress;
+use parser::StatementIndex;
 use parser::StatementRef;
 use parser::StatementType;
+use parser::Token;
 use parser::TokenPtr;
 use parser::NO_STATEMENT;
 use scopeck;
@@ -161,6 +169,12 @@ struct VerifyState<'a, P: 'a + ProofBuilder> {
     var2bit: HashMap<Atom, usize>,
     /// Disjoint variable conditions in the current extended frame
     dv_map: &'a [Bitset],
+    /// Pairs of variables which steps of the current proof require to be
+    /// disjoint, indexed by the lower variable
+    dv_used: Vec<Bitset>,
+    /// Pairs of variables which steps of the current proof require to be
+    /// disjoint, but which are not, in order of first use
+    dv_missing: Vec<(usize, usize)>,
 }
 
 type Result<T> = result::Result<T, Diagnostic>;
@@ -383,6 +397,48 @@ fn do_substitute_vars(expr: &[ExprFragment], vars: &[(Range<usize>, Bitset)]) ->
     out
 }
 
+/// Renders a math string in the stack buffer representation as text, preceded
+/// by its typecode.
+fn render_expr(nameset: &Nameset, code: Atom, expr: &[u8]) -> Token {
+    let mut out = nameset.atom_name(code).to_vec();
+    let mut token_start = true;
+    for &byte in expr {
+        if token_start {
+            out.push(b' ');
+        }
+        out.push(byte & 0x7F);
+        token_start = byte & 0x80 != 0;
+    }
+    out.into_boxed_slice()
+}
+
+/// Builds the details of a failed step, to be completed with its location by
+/// `locate_step`.
+fn step_info(expected: Token, actual: Token) -> Box<StepInfo> {
+    Box::new(StepInfo {
+        expected,
+        actual,
+        ..StepInfo::default()
+    })
+}
+
+/// Adds the location of the step being executed to a diagnostic from
+/// `execute_step`.
+fn locate_step(mut diag: Diagnostic, step: usize, span: Span, label: TokenPtr) -> Diagnostic {
+    match diag {
+        Diagnostic::ProofUnderflow(ref mut info)
+        | Diagnostic::StepEssenWrong(ref mut info)
+        | Diagnostic::StepEssenWrongType(ref mut info)
+        | Diagnostic::StepFloatWrongType(ref mut info) => {
+            info.step = step;
+            info.span = span;
+            info.label = copy_token(label);
+        }
+        _ => {}
+    }
+    diag
+}
+
 /// This is the main "VM" function, and responsible for ~30% of CPU time during
 /// a one-shot verify operation.
 fn execute_step<P: ProofBuilder>(state: &mut VerifyState<P>, index: usize) -> Result<()> {
@@ -409,7 +465,7 @@ fn execute_step<P: ProofBuilder>(state: &mut VerifyState<P>, index: usize) -> Re
         .stack
         .len()
         .checked_sub(fref.hypotheses.len())
-        .ok_or(Diagnostic::ProofUnderflow)?;
+        .ok_or_else(|| Diagnostic::ProofUnderflow(step_info(Token::default(), Token::default())))?;
 
     while state.subst_info.len() < fref.mandatory_count {
         // this is mildly unhygenic, since slots corresponding to $e hyps won't get cleared, but
@@ -432,21 +488,43 @@ fn execute_step<P: ProofBuilder>(state: &mut VerifyState<P>, index: usize) -> Re
         state.builder.push(&mut datavec, data.clone());
         match hyp {
             &Floating(_addr, var_index, typecode) => {
-                try_assert!(slot.code == typecode, Diagnostic::StepFloatWrongType);
+                if slot.code != typecode {
+                    let actual = &state.stack_buffer[slot.expr.clone()];
+                    return Err(Diagnostic::StepFloatWrongType(step_info(
+                        copy_token(state.nameset.atom_name(typecode)),
+                        render_expr(state.nameset, slot.code, actual),
+                    )));
+                }
                 state.subst_info[var_index] = (slot.expr.clone(), slot.vars.clone());
             }
             &Essential(_addr, ref expr) => {
-                try_assert!(slot.code == expr.typecode, Diagnostic::StepEssenWrongType);
-                try_assert!(
-                    do_substitute_eq(
-                        &state.stack_buffer[slot.expr.clone()],
-                        fref,
-                        &expr,
-                        &state.subst_info,
-                        &state.stack_buffer
-                    ),
-                    Diagnostic::StepEssenWrong
-                );
+                if slot.code != expr.typecode {
+                    let actual = &state.stack_buffer[slot.expr.clone()];
+                    return Err(Diagnostic::StepEssenWrongType(step_info(
+                        copy_token(state.nameset.atom_name(expr.typecode)),
+                        render_expr(state.nameset, slot.code, actual),
+                    )));
+                }
+                if !do_substitute_eq(
+                    &state.stack_buffer[slot.expr.clone()],
+                    fref,
+                    expr,
+                    &state.subst_info,
+                    &state.stack_buffer,
+                ) {
+                    // the proof is abandoned, so the expected expression can
+                    // be built on top of the stack buffer
+                    let tos = state.stack_buffer.len();
+                    do_substitute(&mut state.stack_buffer, fref, expr, &state.subst_info);
+                    return Err(Diagnostic::StepEssenWrong(step_info(
+                        render_expr(state.nameset, expr.typecode, &state.stack_buffer[tos..]),
+                        render_expr(
+                            state.nameset,
+                            slot.code,
+                            &state.stack_buffer[slot.expr.clone()],
+                        ),
+                    )));
+                }
             }
         }
     }
@@ -479,14 +557,20 @@ fn execute_step<P: ProofBuilder>(state: &mut VerifyState<P>, index: usize) -> Re
 
     // check $d constraints on the used assertion now that the dust has settled.
     // Remember that we might have variable indexes allocated during the proof
-    // that are out of range for dv_map
+    // that are out of range for dv_map.  Violations do not affect the rest of
+    // the proof, so they are collected to be reported together at the end
     for &(ix1, ix2) in &*fref.mandatory_dv {
         for var1 in &state.subst_info[ix1].1 {
             for var2 in &state.subst_info[ix2].1 {
-                try_assert!(
-                    var1 < state.dv_map.len() && state.dv_map[var1].has_bit(var2),
-                    Diagnostic::ProofDvViolation
-                );
+                let pair = (var1.min(var2), var1.max(var2));
+                if var1 < state.dv_map.len() && state.dv_map[var1].has_bit(var2) {
+                    if state.dv_used.len() <= pair.0 {
+                        state.dv_used.resize_with(pair.0 + 1, Bitset::new);
+                    }
+                    state.dv_used[pair.0].set_bit(pair.1);
+                } else if !state.dv_missing.contains(&pair) {
+                    state.dv_missing.push(pair);
+                }
             }
         }
     }
@@ -512,9 +596,60 @@ fn finalize_step<P: ProofBuilder>(state: &mut VerifyState<P>) -> Result<P::Item>
         Diagnostic::ProofWrongExprEnd
     );
 
+    try_assert!(
+        state.dv_missing.is_empty(),
+        Diagnostic::ProofDvViolation(var_pairs(state, &state.dv_missing))
+    );
+
     Ok(data.clone())
 }
 
+/// Converts pairs of variable indices in the current proof to pairs of names.
+fn var_pairs<P: ProofBuilder>(
+    state: &VerifyState<P>,
+    pairs: &[(usize, usize)],
+) -> Vec<(Token, Token)> {
+    let mut atoms = vec![Atom::default(); state.var2bit.len()];
+    for (&atom, &bit) in &state.var2bit {
+        atoms[bit] = atom;
+    }
+    let name = |bit: usize| copy_token(state.nameset.atom_name(atoms[bit]));
+    pairs
+        .iter()
+        .map(|&(var1, var2)| (name(var1), name(var2)))
+        .collect()
+}
+
+/// Returns the pairs of variables which the verified proof requires to be
+/// disjoint.
+fn used_dv<P: ProofBuilder>(state: &VerifyState<P>) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for (var1, vars) in state.dv_used.iter().enumerate() {
+        for var2 in vars {
+            pairs.push((var1, var2));
+        }
+    }
+    pairs
+}
+
+/// Returns the mandatory `$d` pairs of the current frame which the verified
+/// proof does not require.
+fn unused_dv<P: ProofBuilder>(state: &VerifyState<P>) -> Vec<(usize, usize)> {
+    // mandatory variables have the same indices in the proof as in the frame
+    state
+        .cur_frame
+        .mandatory_dv
+        .iter()
+        .map(|&(var1, var2)| (var1.min(var2), var1.max(var2)))
+        .filter(|&(var1, var2)| {
+            !state
+                .dv_used
+                .get(var1)
+                .is_some_and(|vars| vars.has_bit(var2))
+        })
+        .collect()
+}
+
 fn save_step<P: ProofBuilder>(state: &mut VerifyState<P>) {
     let &(ref data, ref top) = state
         .stack
@@ -528,17 +663,16 @@ fn save_step<P: ProofBuilder>(state: &mut VerifyState<P>) {
     ));
 }
 
-// proofs are not self-synchronizing, so it's not likely to get >1 usable error
-fn verify_proof<'a, P: ProofBuilder>(
-    state: &mut VerifyState<'a, P>,
-    stmt: StatementRef<'a>,
-) -> Result<P::Item> {
+// prepare for checking a new proof of cur_frame
+fn reset_state<P: ProofBuilder>(state: &mut VerifyState<P>) {
     // clear, but do not free memory
     state.stack.clear();
     fast_clear(&mut state.stack_buffer);
     state.prepared.clear();
     state.var2bit.clear();
     state.dv_map = &state.cur_frame.optional_dv;
+    state.dv_used.clear();
+    state.dv_missing.clear();
     // temp_buffer is cleared before use; subst_info should be overwritten
     // before use if scopeck is working correctly
 
@@ -548,6 +682,14 @@ fn verify_proof<'a, P: ProofBuilder>(
     for (index, &tokr) in state.cur_frame.var_list.iter().enumerate() {
         state.var2bit.insert(tokr, index);
     }
+}
+
+// proofs are not self-synchronizing, so it's not likely to get >1 usable error
+fn verify_proof<'a, P: ProofBuilder>(
+    state: &mut VerifyState<'a, P>,
+    stmt: StatementRef<'a>,
+) -> Result<P::Item> {
+    reset_state(state);
 
     if stmt.proof_len() > 0 && stmt.proof_slice_at(0) == b"(" {
         // this is a compressed proof
@@ -560,6 +702,7 @@ fn verify_proof<'a, P: ProofBuilder>(
         }
 
         // parse and prepare the label list before the )
+        let roster_start = state.prepared.len();
         loop {
             try_assert!(i < stmt.proof_len(), Diagnostic::ProofUnterminatedRoster);
             let chunk = stmt.proof_slice_at(i);
@@ -575,14 +718,33 @@ fn verify_proof<'a, P: ProofBuilder>(
         // after ) is a packed list of varints.  decode them and execute the
         // corresponding steps.  the varint decoder is surprisingly CPU-heavy,
         // presumably due to branch overhead
+        let roster_end = i - 1;
         let mut k = 0usize;
         let mut can_save = false;
+        let mut step = 0;
+        // offset in the buffer of the first letter of the current step, which
+        // may be in an earlier chunk if the step is split by white space
+        let mut step_start = 0;
         while i < stmt.proof_len() {
             let chunk = stmt.proof_slice_at(i);
-            for &ch in chunk {
+            let chunk_start = stmt.proof_span(i).start as usize;
+            for (pos, &ch) in chunk.iter().enumerate() {
+                if k == 0 {
+                    step_start = chunk_start + pos;
+                }
                 if ch >= b'A' && ch <= b'T' {
                     k = k * 20 + (ch - b'A') as usize;
-                    execute_step(state, k).unwrap();
+                    step += 1;
+                    execute_step(state, k).map_err(|diag| {
+                        // only steps from the roster can fail
+                        let label = k
+                            .checked_sub(roster_start)
+                            .map(|ix| ix as i32 + 1)
+                            .filter(|&ix| ix < roster_end)
+                            .map_or(&b""[..], |ix| stmt.proof_slice_at(ix));
+                        let span = Span::new(step_start, chunk_start + pos + 1);
+                        locate_step(diag, step, span, label)
+                    })?;
                     k = 0;
                     can_save = true;
                 } else if ch >= b'U' && ch <= b'Y' {
@@ -606,20 +768,29 @@ fn verify_proof<'a, P: ProofBuilder>(
 
         try_assert!(k == 0, Diagnostic::ProofMalformedVarint);
     } else {
-        let mut count = 0;
-        // NORMAL mode proofs are just a list of steps, with no saving provision
-        for i in 0..stmt.proof_len() {
-            let chunk = stmt.proof_slice_at(i);
-            try_assert!(chunk != b"?", Diagnostic::ProofIncomplete);
-            prepare_step(state, chunk)?;
-            execute_step(state, count)?;
-            count += 1;
-        }
+        verify_normal_steps(
+            state,
+            (0..stmt.proof_len()).map(|i| (stmt.proof_slice_at(i), stmt.proof_span(i))),
+        )?;
     }
 
     finalize_step(state)
 }
 
+// NORMAL mode proofs are just a list of steps, with no saving provision.  Each
+// step comes with its span, for diagnostics
+fn verify_normal_steps<'b, P: ProofBuilder, I: Iterator<Item = (TokenPtr<'b>, Span)>>(
+    state: &mut VerifyState<P>,
+    steps: I,
+) -> Result<()> {
+    for (count, (chunk, span)) in steps.enumerate() {
+        try_assert!(chunk != b"?", Diagnostic::ProofIncomplete);
+        prepare_step(state, chunk)?;
+        execute_step(state, count).map_err(|diag| locate_step(diag, count + 1, span, chunk))?;
+    }
+    Ok(())
+}
+
 /// Stored result of running the verifier on a segment.
 struct VerifySegment {
     source: Arc<Segment>,
@@ -646,13 +817,110 @@ impl VerifyResult {
     }
 }
 
-/// Driver which verifies each statement in a segment.
-fn verify_segment(
+/// Serializes the diagnostics of a segment for the disk cache, or returns
+/// `None` if there are any besides unused `$d` warnings.
+fn encode_verify_segment(vsr: &VerifySegment) -> Option<Encoder> {
+    let mut diags = Vec::new();
+    for (addr, diag) in &vsr.diagnostics {
+        match *diag {
+            Diagnostic::ProofUnusedDv(ref pairs) => diags.push((addr.index, pairs)),
+            _ => return None,
+        }
+    }
+    diags.sort_by_key(|&(index, _)| index);
+    let mut enc = Encoder::default();
+    enc.seq(&diags, |enc, &(index, pairs)| {
+        enc.i32(index);
+        enc.seq(pairs, |enc, (var1, var2)| {
+            enc.bytes(var1);
+            enc.bytes(var2);
+        });
+    });
+    Some(enc)
+}
+
+/// Rebuilds the result for a segment saved by `encode_verify_segment`.
+fn decode_verify_segment(dec: &mut Decoder, sref: SegmentRef) -> Option<VerifySegment> {
+    let mut diagnostics = new_map();
+    for (index, pairs) in dec.seq(|dec| {
+        let index = dec.i32()?;
+        Some((index, dec.seq(|dec| Some((dec.token()?, dec.token()?)))?))
+    })? {
+        diagnostics.insert(
+            StatementAddress::new(sref.id, index),
+            Diagnostic::ProofUnusedDv(pairs),
+        );
+    }
+    Some(VerifySegment {
+        source: (*sref).clone(),
+        scope_usage: ScopeUsage::default(),
+        diagnostics,
+    })
+}
+
+/// Verifies the proof of a `$p` statement against the current frame,
+/// returning its error, or a warning about unneeded `$d` pairs.
+fn check_proof<'a>(state: &mut VerifyState<'a, ()>, stmt: StatementRef<'a>) -> Option<Diagnostic> {
+    match verify_proof(state, stmt) {
+        Err(diag) => Some(diag),
+        Ok(()) => {
+            let unused = unused_dv(state);
+            if unused.is_empty() {
+                None
+            } else {
+                Some(Diagnostic::ProofUnusedDv(var_pairs(state, &unused)))
+            }
+        }
+    }
+}
+
+/// Amount of proof text, in bytes, to verify in each job.  The `$p` statements
+/// of a larger segment are divided between several jobs, so that a large file
+/// can use several threads even when it is not split into segments.
+const CHUNK_BYTES: usize = 1 << 16;
+
+/// Estimates the work of verifying a `$p` statement from the length of its
+/// proof.
+fn proof_bytes(stmt: StatementRef) -> usize {
+    match stmt.proof_len() {
+        0 => 0,
+        len => (stmt.proof_span(len - 1).end - stmt.proof_span(0).start) as usize,
+    }
+}
+
+/// Divides the `$p` statements of a segment into chunks of about `CHUNK_BYTES`
+/// of proof text, returning each chunk with its estimated size.  There is
+/// always at least one chunk, so that the usage of a segment without proofs is
+/// still recorded.
+fn chunk_segment(sref: SegmentRef) -> Vec<(Vec<StatementIndex>, usize)> {
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    let mut bytes = 0;
+    for stmt in sref {
+        if stmt.statement_type() != StatementType::Provable {
+            continue;
+        }
+        chunk.push(stmt.index());
+        bytes += proof_bytes(stmt);
+        if bytes >= CHUNK_BYTES {
+            chunks.push((mem::take(&mut chunk), bytes));
+            bytes = 0;
+        }
+    }
+    if !chunk.is_empty() || chunks.is_empty() {
+        chunks.push((chunk, bytes));
+    }
+    chunks
+}
+
+/// Driver which verifies a chunk of `$p` statements in a segment.
+fn verify_chunk(
     sset: &SegmentSet,
     nset: &Nameset,
     scopes: &ScopeResult,
     sid: SegmentId,
-) -> VerifySegment {
+    chunk: &[StatementIndex],
+) -> (HashMap<StatementAddress, Diagnostic>, ScopeUsage) {
     let mut diagnostics = new_map();
     let dummy_frame = Frame::default();
     let sref = sset.segment(sid);
@@ -670,29 +938,29 @@ fn verify_segment(
         subst_info: Vec::new(),
         var2bit: new_map(),
         dv_map: &dummy_frame.optional_dv,
+        dv_used: Vec::new(),
+        dv_missing: Vec::new(),
     };
     // use the _same_ VerifyState so that memory can be reused
-    for stmt in sref {
-        // only intend to check $p statements
-        if stmt.statement_type() == StatementType::Provable {
-            // no valid frame -> no use checking
-            // may wish to record a secondary error?
-            if let Some(frame) = state.scoper.get(stmt.label()) {
-                state.cur_frame = frame;
-                if let Err(diag) = verify_proof(&mut state, stmt) {
-                    diagnostics.insert(stmt.address(), diag);
-                }
+    for &index in chunk {
+        let stmt = sref.statement(index);
+        // no valid frame -> no use checking
+        // may wish to record a secondary error?
+        if let Some(frame) = state.scoper.get(stmt.label()) {
+            state.cur_frame = frame;
+            if let Some(diag) = check_proof(&mut state, stmt) {
+                diagnostics.insert(stmt.address(), diag);
             }
         }
     }
-    VerifySegment {
-        source: (*sref).clone(),
-        diagnostics: diagnostics,
-        scope_usage: state.scoper.into_usage(),
-    }
+    (diagnostics, state.scoper.into_usage())
 }
 
 /// Calculates or updates the verification result for a database.
+///
+/// Each segment is first checked for a reusable result in memory or on disk;
+/// the segments which need verifying are then divided into chunks, which are
+/// queued separately and merged once all of them are done.
 pub fn verify(
     result: &mut VerifyResult,
     segments: &Arc<SegmentSet>,
@@ -700,6 +968,8 @@ pub fn verify(
     scope: &Arc<ScopeResult>,
 ) {
     let old = mem::replace(&mut result.segments, new_map());
+    let disk =
+        DiskCache::new(&segments.options).map(|disk| (disk, Arc::new(SegmentKeys::new(segments))));
     let mut ssrq = Vec::new();
     for sref in segments.segments() {
         let segments2 = segments.clone();
@@ -707,46 +977,95 @@ pub fn verify(
         let scope = scope.clone();
         let id = sref.id;
         let old_res_o = old.get(&id).cloned();
+        let disk = disk.clone();
         ssrq.push(segments.exec.exec(sref.bytes(), move || {
             let sref = segments2.segment(id);
             if let Some(old_res) = old_res_o {
                 if old_res.scope_usage.valid(&nset, &scope)
                     && ptr_eq::<Segment>(&old_res.source, &sref)
                 {
-                    return (id, old_res.clone());
+                    return (id, Some(old_res.clone()));
                 }
             }
-            if segments2.options.trace_recalc {
-                println!("verify({:?})", parser::guess_buffer_name(&sref.buffer));
+            if let Some((ref disk, ref keys)) = disk {
+                let loaded = disk.load_with("verify", keys.key(id), |dec| {
+                    decode_verify_segment(dec, sref)
+                });
+                if let Some(vsr) = loaded {
+                    return (id, Some(Arc::new(vsr)));
+                }
             }
-            (id, Arc::new(verify_segment(&segments2, &nset, &scope, id)))
+            (id, None)
         }))
     }
 
+    // jobs cannot wait for other jobs, so the chunks are queued from here
     result.segments.clear();
+    let mut chunkq = Vec::new();
     for promise in ssrq {
         let (id, arc) = promise.wait();
-        result.segments.insert(id, arc);
+        if let Some(arc) = arc {
+            result.segments.insert(id, arc);
+            continue;
+        }
+        let sref = segments.segment(id);
+        if segments.options.trace_recalc {
+            println!("verify({:?})", parser::guess_buffer_name(&sref.buffer));
+        }
+        let mut promises = Vec::new();
+        for (chunk, bytes) in chunk_segment(sref) {
+            let segments2 = segments.clone();
+            let nset = nset.clone();
+            let scope = scope.clone();
+            promises.push(segments.exec.exec(bytes, move || {
+                verify_chunk(&segments2, &nset, &scope, id, &chunk)
+            }));
+        }
+        chunkq.push((id, promises));
+    }
+
+    for (id, promises) in chunkq {
+        let mut diagnostics = new_map();
+        let mut scope_usage: Option<ScopeUsage> = None;
+        for promise in promises {
+            let (diags, usage) = promise.wait();
+            diagnostics.extend(diags);
+            match scope_usage {
+                Some(ref mut scope_usage) => scope_usage.merge(usage),
+                None => scope_usage = Some(usage),
+            }
+        }
+        let vsr = VerifySegment {
+            source: (*segments.segment(id)).clone(),
+            diagnostics,
+            scope_usage: scope_usage.expect("every segment has a chunk"),
+        };
+        if let Some((ref disk, ref keys)) = disk {
+            if let Some(enc) = encode_verify_segment(&vsr) {
+                disk.store("verify", keys.key(id), enc);
+            }
+        }
+        result.segments.insert(id, Arc::new(vsr));
     }
 }
 
-/// Parse a single $p statement, returning the result of the given
-/// proof builder, or an error if the proof is faulty
-pub fn verify_one<P: ProofBuilder>(
-    sset: &SegmentSet,
-    nset: &Nameset,
-    scopes: &ScopeResult,
-    builder: &mut P,
-    stmt: StatementRef,
-) -> result::Result<P::Item, Diagnostic> {
-    let dummy_frame = Frame::default();
+/// Builds verifier working memory for checking a single `$p` statement
+/// outside of a segment pass.
+fn one_shot_state<'a, P: ProofBuilder>(
+    sset: &'a SegmentSet,
+    nset: &'a Nameset,
+    scopes: &'a ScopeResult,
+    builder: &'a mut P,
+    stmt: StatementRef<'a>,
+    dummy_frame: &'a Frame,
+) -> VerifyState<'a, P> {
     let mut state = VerifyState {
         this_seg: stmt.segment(),
         scoper: ScopeReader::new(scopes),
         nameset: nset,
         builder: builder,
         order: &sset.order,
-        cur_frame: &dummy_frame,
+        cur_frame: dummy_frame,
         stack: Vec::new(),
         stack_buffer: Vec::new(),
         prepared: Vec::new(),
@@ -754,10 +1073,78 @@ pub fn verify_one<P: ProofBuilder>(
         subst_info: Vec::new(),
         var2bit: new_map(),
         dv_map: &dummy_frame.optional_dv,
+        dv_used: Vec::new(),
+        dv_missing: Vec::new(),
     };
 
     assert!(stmt.statement_type() == StatementType::Provable);
-    let frame = state.scoper.get(stmt.label()).unwrap();
-    state.cur_frame = frame;
+    state.cur_frame = state.scoper.get(stmt.label()).unwrap();
+    state
+}
+
+/// Parse a single $p statement, returning the result of the given
+/// proof builder, or an error if the proof is faulty
+pub fn verify_one<P: ProofBuilder>(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scopes: &ScopeResult,
+    builder: &mut P,
+    stmt: StatementRef,
+) -> result::Result<P::Item, Diagnostic> {
+    let dummy_frame = Frame::default();
+    let mut state = one_shot_state(sset, nset, scopes, builder, stmt, &dummy_frame);
     verify_proof(&mut state, stmt)
 }
+
+/// Verify a single $p statement, returning the diagnostic which the verify
+/// pass would report for it, if any; statements without a valid frame are
+/// skipped, as by the pass
+pub fn verify_statement(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scopes: &ScopeResult,
+    stmt: StatementRef,
+) -> Option<Diagnostic> {
+    scopes.get(stmt.label())?;
+    let dummy_frame = Frame::default();
+    let builder = &mut ();
+    let mut state = one_shot_state(sset, nset, scopes, builder, stmt, &dummy_frame);
+    check_proof(&mut state, stmt)
+}
+
+/// Check a proof for a single $p statement which is given as a list of step
+/// labels in normal (uncompressed) form, rather than taken from the source,
+/// returning the result of the given proof builder, or an error if the proof
+/// is faulty
+pub fn verify_steps<P: ProofBuilder>(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scopes: &ScopeResult,
+    builder: &mut P,
+    stmt: StatementRef,
+    steps: &[TokenPtr],
+) -> result::Result<P::Item, Diagnostic> {
+    let dummy_frame = Frame::default();
+    let mut state = one_shot_state(sset, nset, scopes, builder, stmt, &dummy_frame);
+    reset_state(&mut state);
+    verify_normal_steps(&mut state, steps.iter().map(|&step| (step, Span::null())))?;
+    finalize_step(&mut state)
+}
+
+/// Returns the pairs of variables which the proof of a `$p` statement requires
+/// to be disjoint, or an error if the proof is faulty.
+///
+/// This is the smallest set of `$d` constraints under which the proof is
+/// valid; each pair is given once.
+pub fn required_dv(
+    sset: &SegmentSet,
+    nset: &Nameset,
+    scopes: &ScopeResult,
+    stmt: StatementRef,
+) -> result::Result<Vec<(Token, Token)>, Diagnostic> {
+    let dummy_frame = Frame::default();
+    let builder = &mut ();
+    let mut state = one_shot_state(sset, nset, scopes, builder, stmt, &dummy_frame);
+    verify_proof(&mut state, stmt)?;
+    Ok(var_pairs(&state, &used_dv(&state)))
+}
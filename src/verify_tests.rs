@@ -0,0 +1,207 @@
+use database::DbOptions;
+use diag::Diagnostic;
+use diag::DiagnosticClass;
+use diag::Notation;
+use parser::Token;
+use test_util::mkdb;
+use verify;
+
+const DB: &str = "$c wff setvar |- ( -> ) A. $.
+    $v ph ps x $.
+    wph $f wff ph $.
+    wps $f wff ps $.
+    vx $f setvar x $.
+    wi $a wff ( ph -> ps ) $.
+    wal $a wff A. x ph $.
+    ${
+      $d x ph $.
+      ax-17 $a |- ( ph -> A. x ph ) $.
+    $}
+    ${
+      $d x ph ps $.
+      th1 $p |- ( ( ph -> ps ) -> A. x ( ph -> ps ) ) $= wph wps wi vx ax-17 $.
+    $}
+    th2 $p |- ( ph -> A. x ph ) $= ( ax-17 ) ABC $.";
+
+fn pairs(names: &[(&str, &str)]) -> Vec<(Token, Token)> {
+    names
+        .iter()
+        .map(|&(var1, var2)| (var1.as_bytes().into(), var2.as_bytes().into()))
+        .collect()
+}
+
+#[test]
+fn test_dv_analysis() {
+    let mut db = mkdb(DB, DbOptions::default());
+    let mut diags = db.verify_result().diagnostics();
+    let sset = db.parse_result().clone();
+    diags.sort_by_key(|&(addr, _)| addr.index);
+    let diags = diags
+        .into_iter()
+        .map(|(addr, diag)| {
+            (
+                String::from_utf8_lossy(sset.statement(addr).label()).into_owned(),
+                diag,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // th1 does not need ph and ps to be disjoint; th2 needs ph and x to be
+    assert_eq!(
+        diags,
+        vec![
+            (
+                "th1".to_owned(),
+                Diagnostic::ProofUnusedDv(pairs(&[("ph", "ps")]))
+            ),
+            (
+                "th2".to_owned(),
+                Diagnostic::ProofDvViolation(pairs(&[("ph", "x")]))
+            ),
+        ]
+    );
+
+    let nset = db.name_result().clone();
+    let scopes = db.scope_result().clone();
+    let th1 = db.statement("th1").unwrap();
+    assert_eq!(
+        verify::required_dv(&sset, &nset, &scopes, th1),
+        Ok(pairs(&[("ph", "x"), ("ps", "x")]))
+    );
+}
+
+const MP_DB: &str = "$c wff |- ( -> ) $.
+    $v ph ps $.
+    wph $f wff ph $.
+    wps $f wff ps $.
+    wi $a wff ( ph -> ps ) $.
+    ${
+      min $e |- ph $.
+      maj $e |- ( ph -> ps ) $.
+      ax-mp $a |- ps $.
+    $}
+    ${
+      h1 $e |- ph $.
+      th1 $p |- ps $= wph wps h1 h1 ax-mp $.
+      th2 $p |- ps $= ( ax-mp ) ABCCD $.
+    $}";
+
+#[test]
+fn test_step_info() {
+    let mut db = mkdb(MP_DB, DbOptions::default());
+    let sset = db.parse_result().clone();
+    let mut diags = db.verify_result().diagnostics();
+    diags.sort_by_key(|&(addr, _)| addr.index);
+    let steps = diags
+        .into_iter()
+        .map(|(addr, diag)| match diag {
+            Diagnostic::StepEssenWrong(info) => {
+                let text = &MP_DB[info.span.start as usize..info.span.end as usize];
+                assert_eq!(&*info.label, b"ax-mp");
+                assert_eq!(&*info.expected, b"|- ( ph -> ps )");
+                assert_eq!(&*info.actual, b"|- ph");
+                let label = String::from_utf8_lossy(sset.statement(addr).label()).into_owned();
+                (label, info.step, text.to_owned())
+            }
+            _ => panic!("unexpected diagnostic {:?}", diag),
+        })
+        .collect::<Vec<_>>();
+
+    // the minor premise is accepted, so the major premise is the failing step
+    assert_eq!(
+        steps,
+        vec![
+            ("th1".to_owned(), 5, "ax-mp".to_owned()),
+            ("th2".to_owned(), 5, "D".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn test_split_step() {
+    // with the three hypotheses first, ax-mp is the 21st label and is written
+    // with two letters split across lines; it underflows the stack
+    let text = MP_DB.to_owned()
+        + "
+    ${
+      h3 $e |- ph $.
+      th3 $p |- ps $=
+        ( wi wi wi wi wi wi wi wi wi wi wi wi wi wi wi wi wi ax-mp ) U
+        A $.
+    $}";
+    let mut db = mkdb(&text, DbOptions::default());
+    let notations = db.verify_statements(&["th3"]);
+    assert_eq!(notations.len(), 1);
+    let notation = &notations[0];
+    assert_eq!(notation.code, "ProofUnderflow");
+    assert_eq!(
+        &text[notation.span.start as usize..notation.span.end as usize],
+        "U\n        A"
+    );
+    assert_eq!(
+        notation.args,
+        vec![("step", "1".to_owned()), ("label", "ax-mp".to_owned())]
+    );
+}
+
+#[test]
+fn test_verify_statements() {
+    let mut db = mkdb(MP_DB, DbOptions::default());
+    let summary = |notations: Vec<Notation>| {
+        notations
+            .into_iter()
+            .map(|notation| {
+                let text = &MP_DB[notation.span.start as usize..notation.span.end as usize];
+                (
+                    notation.code.clone(),
+                    text.to_owned(),
+                    notation.formatted_message(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // labels which are not $p statements are skipped
+    let only = summary(db.verify_statements(&["th2", "wi", "nosuch"]));
+    assert_eq!(only.len(), 1);
+    assert_eq!(only[0].0, "StepEssenWrong");
+    assert_eq!(only[0].1, "D");
+
+    let mut full = summary(db.diag_notations(vec![DiagnosticClass::Verify]));
+    full.retain(|entry| entry.1 == "D");
+    assert_eq!(only, full);
+}
+
+#[test]
+fn test_verify_chunks() {
+    // enough proofs that the single segment is verified in several jobs
+    let mut text = MP_DB.trim_end_matches("$}").to_owned();
+    for i in 0..20000 {
+        let first = if i % 5000 == 4999 { "wps" } else { "wph" };
+        text.push_str(&format!(
+            "ch{} $p wff ( ph -> ps ) $= {} wps wi $.\n",
+            i, first
+        ));
+    }
+    text.push_str("$}");
+    let mut db = mkdb(
+        &text,
+        DbOptions {
+            jobs: 4,
+            incremental: true,
+            ..DbOptions::default()
+        },
+    );
+    let sset = db.parse_result().clone();
+    let mut labels = db
+        .verify_result()
+        .diagnostics()
+        .into_iter()
+        .map(|(addr, _)| String::from_utf8_lossy(sset.statement(addr).label()).into_owned())
+        .collect::<Vec<_>>();
+    labels.sort();
+    assert_eq!(
+        labels,
+        ["ch14999", "ch19999", "ch4999", "ch9999", "th1", "th2"]
+    );
+}
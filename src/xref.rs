@@ -0,0 +1,220 @@
+//! Cross-references from labels to the theorems which use them.
+//!
+//! A `$p` statement uses every label cited by its proof: all tokens of a
+//! normal proof, and the roster between the parentheses of a compressed proof.
+//! The mandatory hypotheses of the statement, which a compressed proof refers
+//! to implicitly, are not counted in either format.  Labels are recorded as
+//! text, so the index of a segment is reused while the segment and the frames
+//! of its theorems are unchanged.
+
+use nameck::Nameset;
+use parser;
+use parser::copy_token;
+use parser::Segment;
+use parser::SegmentId;
+use parser::Span;
+use parser::StatementAddress;
+use parser::StatementIndex;
+use parser::StatementRef;
+use parser::StatementType;
+use parser::Token;
+use parser::TokenIndex;
+use scopeck::ScopeReader;
+use scopeck::ScopeResult;
+use scopeck::ScopeUsage;
+use segment_set::SegmentSet;
+use std::mem;
+use std::sync::Arc;
+use util::new_map;
+use util::ptr_eq;
+use util::HashMap;
+
+/// Stored result of indexing the proofs of a segment.
+struct XrefSegment {
+    source: Arc<Segment>,
+    scope_usage: ScopeUsage,
+    /// For each label, the theorems of the segment using it, in order.
+    users: HashMap<Token, Vec<StatementIndex>>,
+}
+
+/// Analysis pass result for the cross-reference index.
+#[derive(Default, Clone)]
+pub struct XrefResult {
+    /// Segment IDs in database order.
+    order: Vec<SegmentId>,
+    segments: HashMap<SegmentId, Arc<XrefSegment>>,
+}
+
+impl XrefResult {
+    /// Returns the `$p` statements whose proofs cite a label, in database
+    /// order.
+    pub fn usages(&self, label: &[u8]) -> Vec<StatementAddress> {
+        let mut out = Vec::new();
+        for &id in &self.order {
+            if let Some(users) = self.segments[&id].users.get(label) {
+                out.extend(users.iter().map(|&index| StatementAddress::new(id, index)));
+            }
+        }
+        out
+    }
+}
+
+/// Returns the labels cited by a proof, without repetitions, each with the
+/// index of its first occurrence in the proof.
+///
+/// This is the text of the proof, so unlike a compressed proof, a normal proof
+/// also cites the mandatory hypotheses of the statement.
+pub fn cited_steps<'a>(stmt: StatementRef<'a>) -> Vec<(TokenIndex, &'a [u8])> {
+    let len = stmt.proof_len();
+    let mut steps: Vec<(TokenIndex, &'a [u8])> = Vec::new();
+    let (start, end) = if len > 0 && stmt.proof_slice_at(0) == b"(" {
+        let close = (1..len)
+            .find(|&ix| stmt.proof_slice_at(ix) == b")")
+            .unwrap_or(len);
+        (1, close)
+    } else {
+        (0, len)
+    };
+    for ix in start..end {
+        let label = stmt.proof_slice_at(ix);
+        if label != b"?" && !steps.iter().any(|step| step.1 == label) {
+            steps.push((ix, label));
+        }
+    }
+    steps
+}
+
+/// Returns the labels cited by a proof, without repetitions, each with the
+/// span of the first step which applies it.
+///
+/// `hyps` is the number of mandatory hypotheses of the statement, which
+/// compressed proofs number before the roster.  The steps of a compressed proof
+/// are decoded, so each span covers the letters of a step rather than the
+/// roster entry; hypotheses and references to saved steps are skipped.
+pub fn cited_spans<'a>(stmt: StatementRef<'a>, hyps: usize) -> Vec<(Span, &'a [u8])> {
+    let len = stmt.proof_len();
+    if len == 0 || stmt.proof_slice_at(0) != b"(" {
+        return cited_steps(stmt)
+            .into_iter()
+            .map(|(ix, label)| (stmt.proof_span(ix), label))
+            .collect();
+    }
+    let close = (1..len)
+        .find(|&ix| stmt.proof_slice_at(ix) == b")")
+        .unwrap_or(len);
+    let roster = (1..close)
+        .map(|ix| stmt.proof_slice_at(ix))
+        .collect::<Vec<_>>();
+    let mut steps: Vec<(Span, &'a [u8])> = Vec::new();
+    let mut k = 0;
+    // offset in the buffer of the first letter of the current step, which may
+    // be in an earlier chunk
+    let mut step_start = 0;
+    for ix in close + 1..len {
+        let chunk = stmt.proof_slice_at(ix);
+        let chunk_start = stmt.proof_span(ix).start as usize;
+        for (pos, &ch) in chunk.iter().enumerate() {
+            if k == 0 {
+                step_start = chunk_start + pos;
+            }
+            if (b'A'..=b'T').contains(&ch) {
+                k = k * 20 + (ch - b'A') as usize;
+                let label = k.checked_sub(hyps).and_then(|ix| roster.get(ix));
+                if let Some(&label) = label {
+                    if label != b"?" && !steps.iter().any(|step| step.1 == label) {
+                        steps.push((Span::new(step_start, chunk_start + pos + 1), label));
+                    }
+                }
+                k = 0;
+            } else if (b'U'..=b'Y').contains(&ch) {
+                k = k.saturating_mul(5).saturating_add(1 + (ch - b'U') as usize);
+            }
+        }
+    }
+    steps
+}
+
+/// Returns the labels cited by a proof, without repetitions.
+pub fn cited_labels<'a>(stmt: StatementRef<'a>) -> Vec<&'a [u8]> {
+    cited_steps(stmt)
+        .into_iter()
+        .map(|(_, label)| label)
+        .collect()
+}
+
+/// Indexes the proofs of a segment.
+fn xref_segment(sset: &SegmentSet, scope: &ScopeResult, sid: SegmentId) -> XrefSegment {
+    let sref = sset.segment(sid);
+    let mut scoper = ScopeReader::new(scope);
+    let mut users: HashMap<Token, Vec<StatementIndex>> = new_map();
+    for stmt in sref {
+        if stmt.statement_type() != StatementType::Provable {
+            continue;
+        }
+        let hyps = scoper
+            .get(stmt.label())
+            .map(|frame| {
+                frame
+                    .hypotheses
+                    .iter()
+                    .map(|hyp| sset.statement(hyp.address()).label())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        for label in cited_labels(stmt) {
+            if hyps.contains(&label) {
+                continue;
+            }
+            users
+                .entry(copy_token(label))
+                .or_default()
+                .push(stmt.address().index);
+        }
+    }
+    XrefSegment {
+        source: sref.segment.clone(),
+        scope_usage: scoper.into_usage(),
+        users,
+    }
+}
+
+/// Calculates or updates the cross-reference index for a database.
+///
+/// Each segment is indexed in parallel, reusing the previous index of the
+/// segment if it and the frames it used are unchanged.
+pub fn cross_reference(
+    result: &mut XrefResult,
+    segments: &Arc<SegmentSet>,
+    nset: &Arc<Nameset>,
+    scope: &Arc<ScopeResult>,
+) {
+    let old = mem::replace(&mut result.segments, new_map());
+    let mut xrefq = Vec::new();
+    result.order.clear();
+    for sref in segments.segments() {
+        let segments2 = segments.clone();
+        let nset = nset.clone();
+        let scope = scope.clone();
+        let id = sref.id;
+        let old_res_o = old.get(&id).cloned();
+        result.order.push(id);
+        xrefq.push(segments.exec.exec(sref.bytes(), move || {
+            let sref = segments2.segment(id);
+            if let Some(old_res) = old_res_o {
+                if old_res.scope_usage.valid(&nset, &scope)
+                    && ptr_eq::<Segment>(&old_res.source, &sref)
+                {
+                    return (id, old_res);
+                }
+            }
+            if segments2.options.trace_recalc {
+                println!("xref({:?})", parser::guess_buffer_name(&sref.buffer));
+            }
+            (id, Arc::new(xref_segment(&segments2, &scope, id)))
+        }))
+    }
+    for promise in xrefq {
+        let (id, arc) = promise.wait();
+        result.segments.insert(id, arc);
+    }
+}
@@ -0,0 +1,76 @@
+use database::Database;
+use database::DbOptions;
+use test_util::mkdb;
+use test_util::reparse;
+
+const DB: &str = "$c wff |- ( -> ) $.
+    $v ph ps $.
+    wph $f wff ph $.
+    wps $f wff ps $.
+    wi $a wff ( ph -> ps ) $.
+    ${
+      mp.1 $e |- ph $.
+      mp.2 $e |- ( ph -> ps ) $.
+      ax-mp $a |- ps $.
+    $}
+    ${
+      mp2.1 $e |- ph $.
+      mp2.2 $e |- ( ph -> ps ) $.
+      mp2 $p |- ps $= ( ax-mp ) ABCDE $.
+    $}
+    ${
+      mp3.1 $e |- ph $.
+      mp3.2 $e |- ( ph -> ps ) $.
+      mp3 $p |- ps $= wph wps mp3.1 mp3.2 ax-mp $.
+    $}
+    ${
+      mp4.1 $e |- ph $.
+      mp4.2 $e |- ( ph -> ps ) $.
+      mp4 $p |- ps $= wph wps mp4.1 mp4.2 mp2 $.
+    $}";
+
+fn labels(db: &mut Database, label: &str) -> Vec<String> {
+    db.usages(label)
+        .into_iter()
+        .map(|stmt| String::from_utf8_lossy(stmt.label()).into_owned())
+        .collect()
+}
+
+#[test]
+fn test_usages() {
+    let mut db = mkdb(DB, DbOptions::default());
+    assert_eq!(labels(&mut db, "ax-mp"), vec!["mp2", "mp3"]);
+    assert_eq!(labels(&mut db, "mp2"), vec!["mp4"]);
+    assert!(labels(&mut db, "wph").is_empty());
+    assert!(labels(&mut db, "mp3.1").is_empty());
+    assert!(labels(&mut db, "wi").is_empty());
+    assert!(labels(&mut db, "nonexistent").is_empty());
+}
+
+#[test]
+fn test_usages_incremental() {
+    let mut db = mkdb(
+        DB,
+        DbOptions {
+            incremental: true,
+            ..DbOptions::default()
+        },
+    );
+    assert_eq!(labels(&mut db, "mp2"), vec!["mp4"]);
+    reparse(&mut db, &DB.replace("mp4.2 mp2 $.", "mp4.2 ax-mp $."));
+    assert!(labels(&mut db, "mp2").is_empty());
+    assert_eq!(labels(&mut db, "ax-mp"), vec!["mp2", "mp3", "mp4"]);
+}
+
+#[test]
+fn test_usages_both_formats() {
+    let index = |text: &str| {
+        let mut db = mkdb(text, DbOptions::default());
+        ["ax-mp", "mp2", "mp2.1", "mp2.2", "wph", "wps"]
+            .iter()
+            .map(|label| labels(&mut db, label))
+            .collect::<Vec<_>>()
+    };
+    let normal = DB.replace("( ax-mp ) ABCDE", "wph wps mp2.1 mp2.2 ax-mp");
+    assert_eq!(index(DB), index(&normal));
+}